@@ -0,0 +1,70 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use multiqr::specter::SpecterQR;
+use multiqr::{Decode, Encode};
+use regex::Regex;
+
+/// Frames of a Specter animation of `parts` frames
+fn frames(parts: usize) -> Vec<String> {
+    let payload = "cHNidP8BAH0CAAAAA".repeat(parts * 100 / 17 + 1);
+    let payload = &payload[..parts * 100];
+    let mut qr = SpecterQR::new();
+    qr.data.max_len = Some(100);
+    let mut qr = qr.load_string(payload).unwrap();
+    (0..parts).map(|_| qr.next().unwrap()).collect()
+}
+
+/// Scan every frame `repeat` times, as a camera at 30 FPS does on a looping animation
+fn scan(frames: &[String], repeat: usize) -> bool {
+    let mut decoder = SpecterQR::new();
+    for _ in 0..repeat {
+        for frame in frames {
+            decoder.receive(frame).unwrap();
+        }
+    }
+    decoder.is_complete()
+}
+
+/// Reassembly as done before the chunk store kept its state: on each frame
+/// the header regex is compiled, the missing chunks counted and the payload
+/// rebuilt
+fn scan_baseline(frames: &[String], repeat: usize) -> bool {
+    let mut chunks: Vec<Option<Vec<u8>>> = Vec::new();
+    let mut payload = None;
+    for _ in 0..repeat {
+        for frame in frames {
+            let header = Regex::new(SpecterQR::pattern()).unwrap();
+            let data = header.replace_all(frame, "").to_string();
+            let (position, _) = frame[1..].split_once(' ').unwrap();
+            let (index, total) = position.split_once("of").unwrap();
+            let index: usize = index.parse().unwrap();
+            if chunks.is_empty() {
+                chunks = vec![None; total.parse().unwrap()];
+            }
+            chunks[index - 1] = Some(data.into_bytes());
+            if chunks.iter().all(|chunk| chunk.is_some()) {
+                let data: Vec<u8> = chunks.iter().flatten().flatten().copied().collect();
+                payload = Some(data);
+            }
+        }
+    }
+    payload.is_some()
+}
+
+fn reassembly(c: &mut Criterion) {
+    let mut group = c.benchmark_group("specter_reassembly");
+    for parts in [10, 100, 300] {
+        let frames = frames(parts);
+        let id = BenchmarkId::new("chunk_store", parts);
+        group.bench_with_input(id, &frames, |b, frames| {
+            b.iter(|| scan(black_box(frames), 3))
+        });
+        let id = BenchmarkId::new("baseline", parts);
+        group.bench_with_input(id, &frames, |b, frames| {
+            b.iter(|| scan_baseline(black_box(frames), 3))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, reassembly);
+criterion_main!(benches);
@@ -0,0 +1,18 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use multiqr::registry::RawQR;
+use multiqr::Decode;
+
+// one frame per line, decoding must return an error instead of panicking
+fuzz_target!(|data: &[u8]| {
+    if let Ok(data) = std::str::from_utf8(data) {
+        let mut decoder = RawQR::default();
+        for frame in data.lines() {
+            let _ = decoder.receive(frame);
+        }
+        if decoder.is_complete() {
+            let _ = decoder.result();
+        }
+    }
+});
@@ -0,0 +1,17 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use multiqr::registry::{QRDecoder, Registry};
+
+// one frame per line, the first one selects the format
+fuzz_target!(|data: &[u8]| {
+    if let Ok(data) = std::str::from_utf8(data) {
+        let mut decoder = QRDecoder::new(Registry::new());
+        for frame in data.lines() {
+            let _ = decoder.receive(frame);
+        }
+        if decoder.is_complete() {
+            let _ = decoder.result();
+        }
+    }
+});
@@ -0,0 +1,92 @@
+//! Electrum Base43, used for transaction QRCodes (alphanumeric mode friendly)
+
+use crate::Error;
+
+pub const ALPHABET: &[u8; 43] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ$*+-./:";
+
+/// Encode bytes in Base43
+pub fn encode(data: &[u8]) -> String {
+    let zeros = data.iter().take_while(|b| **b == 0).count();
+    // little endian digits in base 43
+    let mut digits: Vec<u8> = Vec::with_capacity(data.len() * 3 / 2);
+    for byte in &data[zeros..] {
+        let mut carry = *byte as u32;
+        for digit in digits.iter_mut() {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 43) as u8;
+            carry /= 43;
+        }
+        while carry > 0 {
+            digits.push((carry % 43) as u8);
+            carry /= 43;
+        }
+    }
+    let mut out = String::with_capacity(zeros + digits.len());
+    out.extend(std::iter::repeat(ALPHABET[0] as char).take(zeros));
+    out.extend(digits.iter().rev().map(|d| ALPHABET[*d as usize] as char));
+    out
+}
+
+/// Decode a Base43 string
+pub fn decode(data: &str) -> Result<Vec<u8>, Error> {
+    let data = data.trim();
+    let zeros = data.bytes().take_while(|b| *b == ALPHABET[0]).count();
+    // little endian bytes
+    let mut bytes: Vec<u8> = Vec::with_capacity(data.len());
+    for c in data.bytes().skip(zeros) {
+        let mut carry = ALPHABET
+            .iter()
+            .position(|a| *a == c)
+            .ok_or_else(|| Error::ParsingError("Invalid Base43 character!".to_string()))?
+            as u32;
+        for byte in bytes.iter_mut() {
+            carry += (*byte as u32) * 43;
+            *byte = (carry & 0xff) as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push((carry & 0xff) as u8);
+            carry >>= 8;
+        }
+    }
+    let mut out = vec![0u8; zeros];
+    out.extend(bytes.iter().rev());
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // reference values from Electrum's base_encode(v, base=43)
+    #[test]
+    fn electrum_vectors() {
+        for (data, expected) in [
+            ("01", "1"),
+            ("2a", ":"),
+            ("2b", "10"),
+            ("ffff", "ZJ3"),
+            ("00000100", "005/"),
+            ("68656c6c6f20776f726c64", "-V6IR149FGZJH+5K"),
+        ] {
+            let data = crate::hex::decode(data).unwrap();
+            assert_eq!(encode(&data), expected);
+            assert_eq!(decode(expected).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        assert_eq!(encode(&[]), "");
+        assert!(decode("").unwrap().is_empty());
+        let data: Vec<u8> = (0..=255).rev().collect();
+        assert_eq!(decode(&encode(&data)).unwrap(), data);
+        assert_eq!(decode(" 005/\n").unwrap(), vec![0, 0, 1, 0]);
+    }
+
+    #[test]
+    fn invalid_character() {
+        assert!(decode("ZJ3a").is_err());
+        assert!(decode("Z J3").is_err());
+    }
+}
@@ -0,0 +1,784 @@
+//! Blockchain Commons registry types (BCR-2020-006/007/010) carried by UR
+
+use crate::cbor::{self, Value};
+use crate::{descriptor, hex, Error};
+use bip39::{Language, Mnemonic};
+use bitcoin::bip32::{ChainCode, ChildNumber, ExtendedPubKey as XPub, Fingerprint};
+use bitcoin::secp256k1;
+use bitcoin::{Network, PrivateKey};
+use miniscript::descriptor::{
+    DescriptorPublicKey, DescriptorXKey, ShInner, SinglePubKey, Wildcard, WshInner,
+};
+use miniscript::{Descriptor, Terminal};
+use std::str::FromStr;
+
+pub const CRYPTO_BIP39: u64 = 301;
+pub const CRYPTO_HDKEY: u64 = 303;
+pub const CRYPTO_KEYPATH: u64 = 304;
+pub const CRYPTO_COIN_INFO: u64 = 305;
+pub const CRYPTO_ECKEY: u64 = 306;
+pub const CRYPTO_OUTPUT: u64 = 308;
+pub const CRYPTO_ACCOUNT: u64 = 311;
+
+const SH: u64 = 400;
+const WSH: u64 = 401;
+const PK: u64 = 402;
+const PKH: u64 = 403;
+const WPKH: u64 = 404;
+const MULTI: u64 = 406;
+const SORTED_MULTI: u64 = 407;
+const TAPROOT: u64 = 409;
+const COSIGNER: u64 = 410;
+
+fn unsupported() -> Error {
+    Error::EncodingError("Descriptor cannot be expressed as crypto-output!".to_string())
+}
+
+fn fingerprint_to_u64(fingerprint: &Fingerprint) -> u64 {
+    fingerprint[..]
+        .iter()
+        .fold(0, |acc, byte| (acc << 8) | *byte as u64)
+}
+
+fn components(path: &[ChildNumber], wildcard: Wildcard) -> Value {
+    let mut out = Vec::new();
+    for child in path {
+        match child {
+            ChildNumber::Normal { index } => {
+                out.push(Value::Unsigned(*index as u64));
+                out.push(Value::Bool(false));
+            }
+            ChildNumber::Hardened { index } => {
+                out.push(Value::Unsigned(*index as u64));
+                out.push(Value::Bool(true));
+            }
+        }
+    }
+    match wildcard {
+        Wildcard::None => {}
+        Wildcard::Unhardened => {
+            out.push(Value::Array(vec![]));
+            out.push(Value::Bool(false));
+        }
+        Wildcard::Hardened => {
+            out.push(Value::Array(vec![]));
+            out.push(Value::Bool(true));
+        }
+    }
+    Value::Array(out)
+}
+
+fn keypath(path: &[ChildNumber], wildcard: Wildcard, fingerprint: Option<Fingerprint>) -> Value {
+    let mut map = vec![(1, components(path, wildcard))];
+    if let Some(fingerprint) = fingerprint {
+        map.push((2, Value::Unsigned(fingerprint_to_u64(&fingerprint))));
+        map.push((3, Value::Unsigned(path.len() as u64)));
+    }
+    Value::tag(CRYPTO_KEYPATH, Value::map(map))
+}
+
+/// Serialize an xpub as crypto-hdkey
+pub fn xpub_to_hdkey(
+    xpub: &XPub,
+    origin: Option<&(Fingerprint, bitcoin::bip32::DerivationPath)>,
+    children: Option<(&[ChildNumber], Wildcard)>,
+) -> Value {
+    let mut map = vec![
+        (3, Value::Bytes(xpub.public_key.serialize().to_vec())),
+        (4, Value::Bytes(xpub.chain_code[..].to_vec())),
+    ];
+    if xpub.network != Network::Bitcoin {
+        map.push((
+            5,
+            Value::tag(CRYPTO_COIN_INFO, Value::map(vec![(2, Value::Unsigned(1))])),
+        ));
+    }
+    match origin {
+        Some((fingerprint, path)) => map.push((
+            6,
+            keypath(path.as_ref(), Wildcard::None, Some(*fingerprint)),
+        )),
+        // no source fingerprint, the keypath only keeps depth and child number
+        None if xpub.depth > 0 => map.push((
+            6,
+            Value::tag(
+                CRYPTO_KEYPATH,
+                Value::map(vec![
+                    (1, components(&[xpub.child_number], Wildcard::None)),
+                    (3, Value::Unsigned(xpub.depth as u64)),
+                ]),
+            ),
+        )),
+        None => {}
+    }
+    if let Some((path, wildcard)) = children {
+        if !path.is_empty() || wildcard != Wildcard::None {
+            map.push((7, keypath(path, wildcard, None)));
+        }
+    }
+    map.push((
+        8,
+        Value::Unsigned(fingerprint_to_u64(&xpub.parent_fingerprint)),
+    ));
+    Value::tag(CRYPTO_HDKEY, Value::map(map))
+}
+
+/// Serialize an xpub as a top-level crypto-hdkey
+pub fn xpub_to_hdkey_bytes(xpub: &XPub) -> Vec<u8> {
+    match xpub_to_hdkey(xpub, None, None) {
+        Value::Tag(_, inner) => cbor::encode(&inner),
+        value => cbor::encode(&value),
+    }
+}
+
+/// Serialize a single xpub key expression as a top-level crypto-hdkey
+pub fn key_to_hdkey_bytes(keys: &[DescriptorPublicKey]) -> Result<Vec<u8>, Error> {
+    match keys {
+        [DescriptorPublicKey::XPub(DescriptorXKey {
+            origin,
+            xkey,
+            derivation_path,
+            wildcard,
+        })] => match xpub_to_hdkey(
+            xkey,
+            origin.as_ref(),
+            Some((derivation_path.as_ref(), *wildcard)),
+        ) {
+            Value::Tag(_, inner) => Ok(cbor::encode(&inner)),
+            value => Ok(cbor::encode(&value)),
+        },
+        _ => Err(Error::EncodingError(
+            "crypto-hdkey holds a single xpub key!".to_string(),
+        )),
+    }
+}
+
+fn key(key: &DescriptorPublicKey) -> Result<Value, Error> {
+    match key {
+        DescriptorPublicKey::XPub(DescriptorXKey {
+            origin,
+            xkey,
+            derivation_path,
+            wildcard,
+        }) => Ok(xpub_to_hdkey(
+            xkey,
+            origin.as_ref(),
+            Some((derivation_path.as_ref(), *wildcard)),
+        )),
+        DescriptorPublicKey::Single(single) => {
+            let data = match &single.key {
+                SinglePubKey::FullKey(key) => key.to_bytes(),
+                SinglePubKey::XOnly(key) => key.serialize().to_vec(),
+            };
+            Ok(Value::tag(
+                CRYPTO_ECKEY,
+                Value::map(vec![(3, Value::Bytes(data))]),
+            ))
+        }
+        DescriptorPublicKey::MultiXPub(_) => Err(unsupported()),
+    }
+}
+
+fn multi(tag: u64, threshold: usize, keys: &[DescriptorPublicKey]) -> Result<Value, Error> {
+    let keys = keys.iter().map(key).collect::<Result<Vec<_>, _>>()?;
+    Ok(Value::tag(
+        tag,
+        Value::map(vec![
+            (1, Value::Unsigned(threshold as u64)),
+            (2, Value::Array(keys)),
+        ]),
+    ))
+}
+
+fn wsh_expression(inner: &WshInner<DescriptorPublicKey>) -> Result<Value, Error> {
+    match inner {
+        WshInner::SortedMulti(smv) => multi(SORTED_MULTI, smv.k, &smv.pks),
+        WshInner::Ms(ms) => match &ms.node {
+            Terminal::Multi(k, pks) => multi(MULTI, *k, pks),
+            _ => Err(unsupported()),
+        },
+    }
+}
+
+fn script_expression(descriptor: &Descriptor<DescriptorPublicKey>) -> Result<Value, Error> {
+    match descriptor {
+        Descriptor::Pkh(pkh) => Ok(Value::tag(PKH, key(pkh.as_inner())?)),
+        Descriptor::Wpkh(wpkh) => Ok(Value::tag(WPKH, key(wpkh.as_inner())?)),
+        Descriptor::Wsh(wsh) => Ok(Value::tag(WSH, wsh_expression(wsh.as_inner())?)),
+        Descriptor::Sh(sh) => {
+            let inner = match sh.as_inner() {
+                ShInner::Wsh(wsh) => Value::tag(WSH, wsh_expression(wsh.as_inner())?),
+                ShInner::Wpkh(wpkh) => Value::tag(WPKH, key(wpkh.as_inner())?),
+                ShInner::SortedMulti(smv) => multi(SORTED_MULTI, smv.k, &smv.pks)?,
+                ShInner::Ms(ms) => match &ms.node {
+                    Terminal::Multi(k, pks) => multi(MULTI, *k, pks)?,
+                    _ => return Err(unsupported()),
+                },
+            };
+            Ok(Value::tag(SH, inner))
+        }
+        Descriptor::Tr(tr) if tr.taptree().is_none() => {
+            Ok(Value::tag(TAPROOT, key(tr.internal_key())?))
+        }
+        _ => Err(unsupported()),
+    }
+}
+
+/// Wrap a payload in a CBOR byte string, as UR `bytes` expects
+pub fn bytes(data: &[u8]) -> Vec<u8> {
+    cbor::encode(&Value::Bytes(data.to_vec()))
+}
+
+/// Unwrap the CBOR byte string of a UR `bytes` message, non CBOR messages are
+/// returned as is
+pub fn unwrap_bytes(data: &[u8]) -> Vec<u8> {
+    match cbor::decode(data) {
+        Ok(Value::Bytes(bytes)) => bytes,
+        _ => data.to_vec(),
+    }
+}
+
+/// Serialize a single path descriptor as crypto-output, a top-level UR is not tagged
+pub fn descriptor_to_output(descriptor: &Descriptor<DescriptorPublicKey>) -> Result<Vec<u8>, Error> {
+    let expression = script_expression(descriptor)?;
+    Ok(cbor::encode(&expression))
+}
+
+/// Remove the registry tag of an embedded item, a top-level UR is not tagged
+fn top_level(value: &Value, tag: u64) -> &Value {
+    match value {
+        Value::Tag(t, inner) if *t == tag => inner,
+        value => value,
+    }
+}
+
+fn path_from_components(value: &Value) -> Result<(Vec<ChildNumber>, String), Error> {
+    let items = value.get(1).unwrap_or(&Value::Null);
+    let items = match items {
+        Value::Null => &[][..],
+        items => items.as_array()?,
+    };
+    if items.len() % 2 != 0 {
+        return Err(Error::DecodingError(
+            "Invalid crypto-keypath components!".to_string(),
+        ));
+    }
+    let mut path = Vec::new();
+    let mut suffix = String::new();
+    for step in items.chunks(2) {
+        let hardened = step[1].as_bool()?;
+        match &step[0] {
+            Value::Unsigned(index) => {
+                let index = u32::try_from(*index).map_err(|_| {
+                    Error::DecodingError("Invalid crypto-keypath index!".to_string())
+                })?;
+                let child = if hardened {
+                    ChildNumber::from_hardened_idx(index)
+                } else {
+                    ChildNumber::from_normal_idx(index)
+                }
+                .map_err(|_| Error::DecodingError("Invalid crypto-keypath index!".to_string()))?;
+                suffix += &format!("/{}", child);
+                path.push(child);
+            }
+            Value::Array(wildcard) if wildcard.is_empty() => {
+                suffix += if hardened { "/*'" } else { "/*" };
+            }
+            _ => {
+                return Err(Error::DecodingError(
+                    "Unsupported crypto-keypath component!".to_string(),
+                ))
+            }
+        }
+    }
+    Ok((path, suffix))
+}
+
+fn fingerprint(value: &Value) -> Result<Fingerprint, Error> {
+    let value = u32::try_from(value.as_u64()?)
+        .map_err(|_| Error::DecodingError("Invalid fingerprint!".to_string()))?;
+    Ok(Fingerprint::from(value.to_be_bytes()))
+}
+
+/// Parse a crypto-hdkey into an xpub and its origin/children descriptor suffixes,
+/// `network` is used when the key has no coin-info
+pub fn hdkey_to_xpub(value: &Value, network: Network) -> Result<(XPub, String, String), Error> {
+    let map = value.untag(CRYPTO_HDKEY)?;
+    if map.get(2).map(|v| v.as_bool()).transpose()? == Some(true) {
+        return Err(Error::DecodingError(
+            "Private crypto-hdkey not expected here!".to_string(),
+        ));
+    }
+    let key_data = map
+        .get(3)
+        .ok_or_else(|| Error::DecodingError("crypto-hdkey without key-data!".to_string()))?
+        .as_bytes()?;
+    let public_key = secp256k1::PublicKey::from_slice(key_data)
+        .map_err(|_| Error::DecodingError("Invalid crypto-hdkey key-data!".to_string()))?;
+    let chain_code: [u8; 32] = map
+        .get(4)
+        .ok_or_else(|| Error::DecodingError("crypto-hdkey without chain-code!".to_string()))?
+        .as_bytes()?
+        .try_into()
+        .map_err(|_| Error::DecodingError("Invalid crypto-hdkey chain-code!".to_string()))?;
+    let network = match map.get(5) {
+        Some(info) => match info.untag(CRYPTO_COIN_INFO)?.get(2) {
+            None | Some(Value::Unsigned(0)) => Network::Bitcoin,
+            Some(Value::Unsigned(1)) => Network::Testnet,
+            _ => {
+                return Err(Error::DecodingError(
+                    "Unsupported crypto-coininfo network!".to_string(),
+                ))
+            }
+        },
+        None => network,
+    };
+
+    let mut origin = String::new();
+    let mut depth = 0;
+    let mut child_number = ChildNumber::from(0);
+    if let Some(keypath) = map.get(6) {
+        let keypath = keypath.untag(CRYPTO_KEYPATH)?;
+        let (path, suffix) = path_from_components(keypath)?;
+        depth = match keypath.get(3) {
+            Some(depth) => depth.as_u64()?,
+            None => path.len() as u64,
+        };
+        if let Some(last) = path.last() {
+            child_number = *last;
+        }
+        if let Some(source) = keypath.get(2) {
+            origin = format!("[{}{}]", fingerprint(source)?, suffix);
+        }
+    }
+    let children = match map.get(7) {
+        Some(keypath) => path_from_components(keypath.untag(CRYPTO_KEYPATH)?)?.1,
+        None => String::new(),
+    };
+    let parent_fingerprint = match map.get(8) {
+        Some(value) => fingerprint(value)?,
+        None => Fingerprint::default(),
+    };
+
+    let xpub = XPub {
+        network,
+        depth: u8::try_from(depth)
+            .map_err(|_| Error::DecodingError("Invalid crypto-hdkey depth!".to_string()))?,
+        parent_fingerprint,
+        child_number,
+        public_key,
+        chain_code: ChainCode::from(chain_code),
+    };
+    Ok((xpub, origin, children))
+}
+
+fn key_to_string(value: &Value, network: Network) -> Result<String, Error> {
+    match value {
+        Value::Tag(CRYPTO_HDKEY, _) => {
+            let (xpub, origin, children) = hdkey_to_xpub(value, network)?;
+            Ok(format!("{}{}{}", origin, xpub, children))
+        }
+        Value::Tag(CRYPTO_ECKEY, map) => {
+            let data = map
+                .get(3)
+                .ok_or_else(|| Error::DecodingError("crypto-eckey without data!".to_string()))?
+                .as_bytes()?;
+            Ok(hex::encode(data))
+        }
+        _ => Err(Error::DecodingError(
+            "Unsupported key in crypto-output!".to_string(),
+        )),
+    }
+}
+
+fn expression_to_string(value: &Value, network: Network) -> Result<String, Error> {
+    let (tag, inner) = match value {
+        Value::Tag(tag, inner) => (*tag, inner.as_ref()),
+        _ => {
+            return Err(Error::DecodingError(
+                "Expected a script expression!".to_string(),
+            ))
+        }
+    };
+    match tag {
+        SH => Ok(format!("sh({})", expression_to_string(inner, network)?)),
+        WSH => Ok(format!("wsh({})", expression_to_string(inner, network)?)),
+        PK => Ok(format!("pk({})", key_to_string(inner, network)?)),
+        PKH => Ok(format!("pkh({})", key_to_string(inner, network)?)),
+        WPKH => Ok(format!("wpkh({})", key_to_string(inner, network)?)),
+        TAPROOT => Ok(format!("tr({})", key_to_string(inner, network)?)),
+        MULTI | SORTED_MULTI => {
+            let threshold = inner
+                .get(1)
+                .ok_or_else(|| Error::DecodingError("multi without threshold!".to_string()))?
+                .as_u64()?;
+            let keys = inner
+                .get(2)
+                .ok_or_else(|| Error::DecodingError("multi without keys!".to_string()))?
+                .as_array()?
+                .iter()
+                .map(|key| key_to_string(key, network))
+                .collect::<Result<Vec<_>, _>>()?;
+            let name = if tag == MULTI { "multi" } else { "sortedmulti" };
+            Ok(format!("{}({},{})", name, threshold, keys.join(",")))
+        }
+        _ => Err(Error::DecodingError(
+            "Unsupported crypto-output script expression!".to_string(),
+        )),
+    }
+}
+
+/// Parse a crypto-output into a descriptor, `network` is used for the keys
+/// without coin-info
+pub fn output_to_descriptor(
+    data: &[u8],
+    network: Network,
+) -> Result<Descriptor<DescriptorPublicKey>, Error> {
+    let value = cbor::decode(data)?;
+    let expression = top_level(&value, CRYPTO_OUTPUT);
+    descriptor::parse(&expression_to_string(expression, network)?)
+}
+
+/// Parse a top-level crypto-hdkey into a key expression
+pub fn hdkey_to_key(data: &[u8], network: Network) -> Result<DescriptorPublicKey, Error> {
+    let value = cbor::decode(data)?;
+    let value = Value::tag(CRYPTO_HDKEY, top_level(&value, CRYPTO_HDKEY).clone());
+    parse_key(&key_to_string(&value, network)?)
+}
+
+fn parse_key(key: &str) -> Result<DescriptorPublicKey, Error> {
+    DescriptorPublicKey::from_str(key)
+        .map_err(|e| Error::descriptor_with("invalid key expression", e))
+}
+
+/// The key of a single key script expression (or of a cosigner)
+fn expression_key(value: &Value) -> Option<&Value> {
+    match value {
+        Value::Tag(SH | WSH, inner) => expression_key(inner),
+        Value::Tag(PK | PKH | WPKH | TAPROOT | COSIGNER, inner) => Some(inner.as_ref()),
+        _ => None,
+    }
+}
+
+/// Parse a crypto-account into the keys of its outputs, outputs that are not
+/// single key script expressions are skipped
+pub fn account_to_keys(data: &[u8], network: Network) -> Result<Vec<DescriptorPublicKey>, Error> {
+    let value = cbor::decode(data)?;
+    let value = top_level(&value, CRYPTO_ACCOUNT);
+    let outputs = value
+        .get(2)
+        .ok_or_else(|| Error::DecodingError("crypto-account without outputs!".to_string()))?
+        .as_array()?;
+    let mut keys = Vec::new();
+    for output in outputs {
+        let output = top_level(output, CRYPTO_OUTPUT);
+        if let Some(key) = expression_key(output) {
+            let key = parse_key(&key_to_string(key, network)?)?;
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+    }
+    Ok(keys)
+}
+
+/// Serialize a mnemonic as crypto-bip39
+pub fn mnemonic_to_bip39(mnemonic: &Mnemonic) -> Vec<u8> {
+    let words = mnemonic
+        .word_iter()
+        .map(|word| Value::Text(word.to_string()))
+        .collect();
+    cbor::encode(&Value::map(vec![
+        (1, Value::Array(words)),
+        (2, Value::Text("en".to_string())),
+    ]))
+}
+
+/// Parse a crypto-bip39 into a mnemonic
+pub fn bip39_to_mnemonic(data: &[u8]) -> Result<Mnemonic, Error> {
+    let value = cbor::decode(data)?;
+    let value = top_level(&value, CRYPTO_BIP39);
+    if let Some(lang) = value.get(2) {
+        if *lang != Value::Text("en".to_string()) {
+            return Err(Error::DecodingError(
+                "Only english crypto-bip39 is supported!".to_string(),
+            ));
+        }
+    }
+    let words = value
+        .get(1)
+        .ok_or_else(|| Error::DecodingError("crypto-bip39 without words!".to_string()))?
+        .as_array()?
+        .iter()
+        .map(|word| match word {
+            Value::Text(word) => Ok(word.as_str()),
+            _ => Err(Error::DecodingError("Invalid crypto-bip39 word!".to_string())),
+        })
+        .collect::<Result<Vec<&str>, Error>>()?;
+    Mnemonic::parse_in(Language::English, words.join(" "))
+        .map_err(|e| Error::mnemonic_with("invalid crypto-bip39 words", e))
+}
+
+/// Serialize a private key as crypto-eckey
+pub fn private_key_to_eckey(key: &PrivateKey) -> Vec<u8> {
+    cbor::encode(&Value::map(vec![
+        (2, Value::Bool(true)),
+        (3, Value::Bytes(key.inner.secret_bytes().to_vec())),
+    ]))
+}
+
+/// Parse a private crypto-eckey, the key is returned as compressed
+pub fn eckey_to_private_key(data: &[u8], network: Network) -> Result<PrivateKey, Error> {
+    let value = cbor::decode(data)?;
+    let value = top_level(&value, CRYPTO_ECKEY);
+    if value.get(2).map(|v| v.as_bool()).transpose()? != Some(true) {
+        return Err(Error::DecodingError(
+            "crypto-eckey is not a private key!".to_string(),
+        ));
+    }
+    let data = value
+        .get(3)
+        .ok_or_else(|| Error::DecodingError("crypto-eckey without data!".to_string()))?
+        .as_bytes()?;
+    PrivateKey::from_slice(data, network)
+        .map_err(|_| Error::DecodingError("Invalid crypto-eckey private key!".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const XPUB: &str = "xpub6ASuArnXKPbfEwhqN6e3mwBcDTgzisQN1wXN9BJcM47sSikHjJf3UFHKkNAWbWMiGj7Wf5uMash7SyYq527Hqck2AxYysAA7xmALppuCkwQ";
+    const MASTER: &str = "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8";
+
+    fn check_output(descriptor: &str, expected: &str) {
+        let descriptor = descriptor::parse(descriptor).unwrap();
+        let data = descriptor_to_output(&descriptor).unwrap();
+        assert_eq!(hex::encode(&data), expected);
+        assert_eq!(
+            output_to_descriptor(&data, Network::Bitcoin).unwrap(),
+            descriptor
+        );
+    }
+
+    // BCR-2020-010 examples
+    #[test]
+    fn crypto_output_vectors() {
+        check_output(
+            "pkh(02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5)",
+            "d90193d90132a103582102c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5",
+        );
+        check_output(
+            "sh(wpkh(03fff97bd5755eeea420453a14355235d382f6472f8568a18b2f057a1460297556))",
+            "d90190d90194d90132a103582103fff97bd5755eeea420453a14355235d382f6472f8568a18b2f057a1460297556",
+        );
+        check_output(
+            "sh(multi(1,022f01e5e15cca351daff3843fb70f3c2f0a1bdd05e5af888a67784ef3e10a2a01,03acd484e2f0c7f65309ad178a9f559abde09796974c57e714c35f110dfc27ccbe))",
+            "d90190d90196a201010282d90132a1035821022f01e5e15cca351daff3843fb70f3c2f0a1bdd05e5af888a67784ef3e10a2a01d90132a103582103acd484e2f0c7f65309ad178a9f559abde09796974c57e714c35f110dfc27ccbe",
+        );
+    }
+
+    #[test]
+    fn tagged_top_level_output() {
+        let data = hex::decode(
+            "d90134d90193d90132a103582102c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5",
+        )
+        .unwrap();
+        let descriptor = output_to_descriptor(&data, Network::Bitcoin).unwrap();
+        assert_eq!(
+            descriptor.to_string().split('#').next().unwrap(),
+            "pkh(02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5)"
+        );
+    }
+
+    #[test]
+    fn hdkey_output_roundtrip() {
+        for descriptor in [
+            format!("wpkh([d34db33f/0'/1]{}/0/*)", XPUB),
+            format!("sh(wpkh([d34db33f/0'/1]{}/1/*))", XPUB),
+            format!(
+                "wsh(sortedmulti(1,[d34db33f/0'/1]{}/0/*,03acd484e2f0c7f65309ad178a9f559abde09796974c57e714c35f110dfc27ccbe))",
+                XPUB
+            ),
+        ] {
+            let descriptor = descriptor::parse(&descriptor).unwrap();
+            let data = descriptor_to_output(&descriptor).unwrap();
+            assert_eq!(output_to_descriptor(&data, Network::Bitcoin).unwrap(), descriptor);
+        }
+    }
+
+    #[test]
+    fn unsupported_output() {
+        let multipath = descriptor::parse(&format!("wpkh({}/<0;1>/*)", XPUB)).unwrap();
+        assert!(descriptor_to_output(&multipath).is_err());
+        assert!(output_to_descriptor(&[0xd9, 0x01, 0x93], Network::Bitcoin).is_err());
+        assert!(output_to_descriptor(&hex::decode("d9019a00").unwrap(), Network::Bitcoin).is_err());
+    }
+
+    // BCR-2020-006 example
+    #[test]
+    fn bip39_vector() {
+        let words = "shield group erode awake lock sausage cash glare wave crew flame glove";
+        let expected = "a2018c66736869656c646567726f75706565726f6465656177616b65646c6f636b6773617573616765646361736865676c6172656477617665646372657765666c616d6565676c6f76650262656e";
+        let mnemonic = Mnemonic::parse_in(Language::English, words).unwrap();
+        assert_eq!(hex::encode(&mnemonic_to_bip39(&mnemonic)), expected);
+        assert_eq!(
+            bip39_to_mnemonic(&hex::decode(expected).unwrap()).unwrap(),
+            mnemonic
+        );
+        // tagged, without language
+        let mut tagged = hex::decode("d9012da1018c").unwrap();
+        tagged.extend_from_slice(&hex::decode(&expected[6..expected.len() - 8]).unwrap());
+        assert_eq!(bip39_to_mnemonic(&tagged).unwrap(), mnemonic);
+        // other language
+        let french = expected.replace("0262656e", "02626672");
+        assert!(bip39_to_mnemonic(&hex::decode(&french).unwrap()).is_err());
+    }
+
+    // BCR-2020-008 example, the network comes from the caller
+    #[test]
+    fn eckey_vector() {
+        let expected =
+            "a202f50358208c05c4b4f3e88840a4f4b5f155cfd69473ea169f3d0431b7a6787a23777f08aa";
+        let data = hex::decode(expected).unwrap();
+        let key = eckey_to_private_key(&data, Network::Testnet).unwrap();
+        assert_eq!(key.network, Network::Testnet);
+        assert!(key.compressed);
+        assert_eq!(
+            key.to_wif(),
+            "cSGtQcdhujWGpeqgrRoTGmMhETV1tWYwqKbvyGyhHsA1MFVux9A5"
+        );
+        assert_eq!(hex::encode(&private_key_to_eckey(&key)), expected);
+        let key = eckey_to_private_key(&data, Network::Bitcoin).unwrap();
+        assert_eq!(key.network, Network::Bitcoin);
+        // public key
+        let public = hex::decode(expected.replace("02f5", "02f4")).unwrap();
+        assert!(eckey_to_private_key(&public, Network::Bitcoin).is_err());
+    }
+
+    #[test]
+    fn hdkey_network() {
+        let xpub = XPub::from_str(MASTER).unwrap();
+        let tpub = XPub {
+            network: Network::Testnet,
+            ..xpub
+        };
+        // a mainnet key has no coin-info, the caller network is used
+        let data = xpub_to_hdkey_bytes(&xpub);
+        assert_eq!(
+            hdkey_to_key(&data, Network::Bitcoin).unwrap().to_string(),
+            MASTER
+        );
+        assert_eq!(
+            hdkey_to_key(&data, Network::Testnet).unwrap().to_string(),
+            tpub.to_string()
+        );
+        // coin-info wins over the caller network
+        let data = xpub_to_hdkey_bytes(&tpub);
+        assert_eq!(
+            hdkey_to_key(&data, Network::Bitcoin).unwrap().to_string(),
+            tpub.to_string()
+        );
+        let tpub = XPub {
+            network: Network::Testnet,
+            ..XPub::from_str(XPUB).unwrap()
+        };
+        let descriptor = descriptor::parse(&format!("wpkh([d34db33f/0'/1]{}/0/*)", tpub)).unwrap();
+        let data = descriptor_to_output(&descriptor).unwrap();
+        assert_eq!(
+            output_to_descriptor(&data, Network::Bitcoin).unwrap(),
+            descriptor
+        );
+        // unknown network
+        let mut data = xpub_to_hdkey_bytes(&tpub);
+        // coin-info {2: 1}
+        let info = data
+            .windows(6)
+            .position(|w| w == [0xd9, 0x01, 0x31, 0xa1, 0x02, 0x01])
+            .unwrap();
+        data[info + 5] = 0x02;
+        assert!(hdkey_to_key(&data, Network::Bitcoin).is_err());
+    }
+
+    #[test]
+    fn hdkey_depth() {
+        // a standalone xpub keeps its depth and child number, without source
+        let data = xpub_to_hdkey_bytes(&XPub::from_str(XPUB).unwrap());
+        let keypath = cbor::decode(&data)
+            .unwrap()
+            .get(6)
+            .unwrap()
+            .untag(CRYPTO_KEYPATH)
+            .unwrap()
+            .clone();
+        assert_eq!(
+            keypath.get(1),
+            Some(&components(&[ChildNumber::from(1)], Wildcard::None))
+        );
+        assert_eq!(keypath.get(2), None);
+        assert_eq!(keypath.get(3), Some(&Value::Unsigned(2)));
+        assert_eq!(
+            hdkey_to_key(&data, Network::Bitcoin).unwrap().to_string(),
+            XPUB
+        );
+        // a master key has no keypath
+        let data = xpub_to_hdkey_bytes(&XPub::from_str(MASTER).unwrap());
+        assert_eq!(cbor::decode(&data).unwrap().get(6), None);
+
+        let descriptor = descriptor::parse(&format!("wpkh({}/0/*)", XPUB)).unwrap();
+        let data = descriptor_to_output(&descriptor).unwrap();
+        assert_eq!(
+            output_to_descriptor(&data, Network::Bitcoin).unwrap(),
+            descriptor
+        );
+    }
+
+    #[test]
+    fn hdkey_key_expression() {
+        let key = DescriptorPublicKey::from_str(&format!("[3442193e/0'/1]{}/0/*", XPUB)).unwrap();
+        let data = key_to_hdkey_bytes(&[key.clone()]).unwrap();
+        assert_eq!(hdkey_to_key(&data, Network::Bitcoin).unwrap(), key);
+        assert!(key_to_hdkey_bytes(&[key.clone(), key]).is_err());
+        let single = DescriptorPublicKey::from_str(
+            "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5",
+        )
+        .unwrap();
+        assert!(key_to_hdkey_bytes(&[single]).is_err());
+    }
+
+    #[test]
+    fn account_keys() {
+        let account = format!("[3442193e/0'/1]{}", XPUB);
+        let master = format!("[3442193e]{}", MASTER);
+        let outputs = [
+            format!("wpkh({})", account),
+            format!("sh(wpkh({}))", account),
+            format!("pkh({})", master),
+            // not a single key output
+            format!("wsh(sortedmulti(1,{},{}))", account, master),
+        ]
+        .iter()
+        .map(|output| {
+            let descriptor = descriptor::parse(output).unwrap();
+            Value::tag(CRYPTO_OUTPUT, script_expression(&descriptor).unwrap())
+        })
+        .collect();
+        let data = cbor::encode(&Value::map(vec![
+            (1, Value::Unsigned(0x3442193e)),
+            (2, Value::Array(outputs)),
+        ]));
+        let keys = account_to_keys(&data, Network::Bitcoin).unwrap();
+        assert_eq!(
+            keys,
+            vec![
+                DescriptorPublicKey::from_str(&account).unwrap(),
+                DescriptorPublicKey::from_str(&master).unwrap(),
+            ]
+        );
+        // tagged top level
+        let tagged = cbor::encode(&Value::tag(CRYPTO_ACCOUNT, cbor::decode(&data).unwrap()));
+        assert_eq!(account_to_keys(&tagged, Network::Bitcoin).unwrap(), keys);
+        let empty = cbor::encode(&Value::map(vec![(1, Value::Unsigned(0x3442193e))]));
+        assert!(account_to_keys(&empty, Network::Bitcoin).is_err());
+    }
+}
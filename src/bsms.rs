@@ -0,0 +1,354 @@
+//! BIP129 (BSMS) descriptor records
+
+use crate::{descriptor, hex, Error};
+use aes::Aes256;
+use bitcoin::hashes::{sha256, sha512, Hash, HashEngine, Hmac, HmacEngine};
+use bitcoin::{Address, Network};
+use ctr::cipher::{KeyIvInit, StreamCipher};
+use miniscript::descriptor::checksum::desc_checksum;
+use miniscript::{Descriptor, DescriptorPublicKey};
+use std::fmt;
+use std::str::FromStr;
+
+type Aes256Ctr = ctr::Ctr128BE<Aes256>;
+
+pub const HEADER: &str = "BSMS 1.0";
+pub const NO_PATH_RESTRICTIONS: &str = "No path restrictions";
+
+/// Token of the unencrypted (standard) mode
+pub const NO_ENCRYPTION: &str = "00";
+
+/// A BSMS descriptor record, as sent by the coordinator to the signers
+#[derive(Debug, Clone, PartialEq)]
+pub struct DescriptorRecord {
+    pub descriptor: Descriptor<DescriptorPublicKey>,
+    pub first_address: Address,
+}
+
+/// Expand the `/**` of a descriptor template against the path restrictions
+fn expand_template(template: &str, restrictions: &str) -> Result<String, Error> {
+    if restrictions == NO_PATH_RESTRICTIONS || !template.contains("/**") {
+        return Ok(template.to_string());
+    }
+    let steps = restrictions
+        .split(',')
+        .map(|path| {
+            path.trim()
+                .strip_prefix('/')
+                .and_then(|path| path.strip_suffix("/*"))
+                .filter(|step| step.parse::<u32>().is_ok())
+                .ok_or_else(|| {
+                    Error::ParsingError(format!("Unsupported BSMS path restriction: {}", path))
+                })
+        })
+        .collect::<Result<Vec<&str>, Error>>()?;
+    let expanded = match steps.as_slice() {
+        [step] => format!("/{}/*", step),
+        steps => format!("/<{}>/*", steps.join(";")),
+    };
+    Ok(template.replace("/**", &expanded))
+}
+
+impl DescriptorRecord {
+    /// Parse a descriptor record and verify its first address
+    pub fn parse(data: &str) -> Result<DescriptorRecord, Error> {
+        let lines: Vec<&str> = data
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .collect();
+        let (template, restrictions, address) = match lines.as_slice() {
+            [header, template, restrictions, address] if *header == HEADER => {
+                (*template, *restrictions, *address)
+            }
+            _ => {
+                return Err(Error::ParsingError(
+                    "Not a BSMS 1.0 descriptor record!".to_string(),
+                ))
+            }
+        };
+
+        let template = match template.split_once('#') {
+            Some((template, checksum)) => {
+                let expected = desc_checksum(template)
+                    .map_err(|e| Error::descriptor_with("invalid BSMS template", e))?;
+                if expected != checksum {
+                    return Err(Error::ChecksumMismatch {
+                        expected,
+                        found: checksum.to_string(),
+                    });
+                }
+                template
+            }
+            None => template,
+        };
+        let descriptor = descriptor::parse(&expand_template(template, restrictions)?)?;
+
+        let address = Address::from_str(address)
+            .map_err(|_| Error::ParsingError(format!("Invalid BSMS address: {}", address)))?;
+        let network = [
+            Network::Bitcoin,
+            Network::Testnet,
+            Network::Signet,
+            Network::Regtest,
+        ]
+        .into_iter()
+        .find(|network| address.is_valid_for_network(*network))
+        .ok_or_else(|| Error::ParsingError("Unknown BSMS address network!".to_string()))?;
+        let first_address = address.assume_checked();
+
+        let record = DescriptorRecord::from_descriptor(&descriptor, network)?;
+        if record.first_address.script_pubkey() != first_address.script_pubkey() {
+            return Err(Error::ParsingError(
+                "BSMS first address does not match the descriptor!".to_string(),
+            ));
+        }
+        Ok(record)
+    }
+
+    /// Build the record of a descriptor, computing its first receive address
+    pub fn from_descriptor(
+        descriptor: &Descriptor<DescriptorPublicKey>,
+        network: Network,
+    ) -> Result<DescriptorRecord, Error> {
+        let receive = descriptor::receive_descriptor(descriptor)?;
+        let first_address = receive
+            .at_derivation_index(0)
+            .map_err(|e| Error::descriptor_with("cannot derive", e))?
+            .address(network)
+            .map_err(|e| Error::descriptor_with("cannot derive address", e))?;
+        Ok(DescriptorRecord {
+            descriptor: descriptor.clone(),
+            first_address,
+        })
+    }
+
+    /// Descriptor template, multipath steps replaced by `/**`
+    pub fn template(&self) -> String {
+        let descriptor = descriptor::to_string(&self.descriptor);
+        let descriptor = descriptor.split('#').next().unwrap_or_default();
+        descriptor.replace("/<0;1>/*", "/**")
+    }
+
+    /// Path restrictions matching the template
+    pub fn path_restrictions(&self) -> &'static str {
+        if self.template().contains("/**") {
+            "/0/*,/1/*"
+        } else {
+            NO_PATH_RESTRICTIONS
+        }
+    }
+}
+
+impl FromStr for DescriptorRecord {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DescriptorRecord::parse(s)
+    }
+}
+
+impl fmt::Display for DescriptorRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        writeln!(f, "{}", self.template())?;
+        writeln!(f, "{}", self.path_restrictions())?;
+        write!(f, "{}", self.first_address)
+    }
+}
+
+fn hmac_sha512(key: &[u8], data: &[u8]) -> [u8; 64] {
+    let mut engine = HmacEngine::<sha512::Hash>::new(key);
+    engine.input(data);
+    Hmac::<sha512::Hash>::from_engine(engine).to_byte_array()
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut engine = HmacEngine::<sha256::Hash>::new(key);
+    engine.input(data);
+    Hmac::<sha256::Hash>::from_engine(engine).to_byte_array()
+}
+
+/// ENCRYPTION_KEY = PBKDF2_SHA512("No SPOF", TOKEN, 2048 iterations, 32 bytes)
+fn encryption_key(token: &[u8]) -> [u8; 32] {
+    let mut salt = token.to_vec();
+    salt.extend_from_slice(&1u32.to_be_bytes());
+    let mut u = hmac_sha512(b"No SPOF", &salt);
+    let mut out = u;
+    for _ in 1..2048 {
+        u = hmac_sha512(b"No SPOF", &u);
+        out.iter_mut().zip(u.iter()).for_each(|(o, u)| *o ^= u);
+    }
+    let mut key = [0u8; 32];
+    key.copy_from_slice(&out[..32]);
+    key
+}
+
+fn parse_token(token: &str) -> Result<Vec<u8>, Error> {
+    let bytes = hex::decode(token)?;
+    match bytes.len() {
+        8 | 16 => Ok(bytes),
+        _ => Err(Error::ParsingError(
+            "BSMS token must be 64 or 128 bits!".to_string(),
+        )),
+    }
+}
+
+/// Encrypt a BSMS record with a token, return hex(MAC || CIPHERTEXT)
+pub fn encrypt(record: &str, token: &str) -> Result<String, Error> {
+    if token == NO_ENCRYPTION {
+        return Ok(record.to_string());
+    }
+    let token = parse_token(token)?;
+    let key = encryption_key(&token);
+    let hmac_key = sha256::Hash::hash(&key).to_byte_array();
+    let mac = hmac_sha256(&hmac_key, &[token.as_slice(), record.as_bytes()].concat());
+
+    let mut data = record.as_bytes().to_vec();
+    let mut cipher = Aes256Ctr::new(&key.into(), (&mac[..16]).into());
+    cipher.apply_keystream(&mut data);
+
+    Ok(hex::encode(&[mac.as_slice(), data.as_slice()].concat()))
+}
+
+/// Decrypt a hex(MAC || CIPHERTEXT) BSMS record with a token, the MAC is verified
+pub fn decrypt(data: &str, token: &str) -> Result<String, Error> {
+    if token == NO_ENCRYPTION {
+        return Ok(data.to_string());
+    }
+    let token = parse_token(token)?;
+    let bytes = hex::decode(data)?;
+    if bytes.len() < 32 {
+        return Err(Error::DecodingError(
+            "Encrypted BSMS record too short!".to_string(),
+        ));
+    }
+    let (mac, ciphertext) = bytes.split_at(32);
+    let key = encryption_key(&token);
+
+    let mut plaintext = ciphertext.to_vec();
+    let mut cipher = Aes256Ctr::new(&key.into(), (&mac[..16]).into());
+    cipher.apply_keystream(&mut plaintext);
+
+    let hmac_key = sha256::Hash::hash(&key).to_byte_array();
+    let expected = hmac_sha256(&hmac_key, &[token.as_slice(), plaintext.as_slice()].concat());
+    if expected != mac {
+        return Err(Error::DecodingError(
+            "BSMS record MAC mismatch, wrong token?".to_string(),
+        ));
+    }
+    String::from_utf8(plaintext)
+        .map_err(|_| Error::DecodingError("FromUtf8Error".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MASTER: &str = "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8";
+    const CHILD: &str = "xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw";
+    const ADDRESS: &str = "bc1qjyvnx75km9k296t3kgr8965cr4lpmdnaaznukn0qcttzgx509kfsgf2asr";
+
+    fn template() -> String {
+        format!(
+            "wsh(sortedmulti(2,[0f056943/48'/0'/0'/2']{}/**,[5c1bd648/48'/0'/0'/2']{}/**))",
+            MASTER, CHILD
+        )
+    }
+
+    fn record(checksum: &str) -> String {
+        format!(
+            "BSMS 1.0\n{}#{}\n/0/*,/1/*\n{}",
+            template(),
+            checksum,
+            ADDRESS
+        )
+    }
+
+    #[test]
+    fn parse_record() {
+        let record = DescriptorRecord::parse(&record("8u7ztlnt")).unwrap();
+        let expected = template().replace("/**", "/<0;1>/*");
+        assert_eq!(record.descriptor, descriptor::parse(&expected).unwrap());
+        assert_eq!(record.first_address.to_string(), ADDRESS);
+        assert_eq!(record.template(), template());
+        assert_eq!(record.path_restrictions(), "/0/*,/1/*");
+        assert_eq!(
+            DescriptorRecord::parse(&record.to_string()).unwrap(),
+            record
+        );
+
+        // the checksum is optional
+        let without = format!("BSMS 1.0\n{}\n/0/*,/1/*\n{}", template(), ADDRESS);
+        assert_eq!(DescriptorRecord::parse(&without).unwrap(), record);
+    }
+
+    #[test]
+    fn template_checksum_mismatch() {
+        match DescriptorRecord::parse(&record("8u7ztlnq")) {
+            Err(Error::ChecksumMismatch { expected, found }) => {
+                assert_eq!(expected, "8u7ztlnt");
+                assert_eq!(found, "8u7ztlnq");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn first_address_mismatch() {
+        let record =
+            record("8u7ztlnt").replace(ADDRESS, "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq");
+        assert!(DescriptorRecord::parse(&record).is_err());
+        assert!(DescriptorRecord::parse("BSMS 1.0\nwpkh()").is_err());
+    }
+
+    // reference values computed with Python hashlib and pyca/cryptography
+    #[test]
+    fn encryption_key_vectors() {
+        assert_eq!(
+            hex::encode(&encryption_key(&hex::decode("a54044308ceac9b7").unwrap())),
+            "7673ffd9efd70336a5442eda0b31457f7b6cdf7b42fe17f274434df55efa9839"
+        );
+        assert_eq!(
+            hex::encode(&encryption_key(
+                &hex::decode("00112233445566778899aabbccddeeff").unwrap()
+            )),
+            "0ddad86d5e541947dbe4c3ca38a8a7c0023abbd36ce846e877caf066045c97df"
+        );
+    }
+
+    #[test]
+    fn encrypt_vectors() {
+        for (token, expected) in [
+            (
+                "a54044308ceac9b7",
+                "4f72b70b01f49b4853b48c7b5c2067a73d5be61b5549df2d3230038465cb558ffb841a053e09cdf26c",
+            ),
+            (
+                "00112233445566778899aabbccddeeff",
+                "744ccadadfe75a7096b8a10756ff79ac11224ae8aad20cbdbedc23267658ed0f3949c63280610049dd",
+            ),
+        ] {
+            assert_eq!(encrypt("BSMS 1.0\n", token).unwrap(), expected);
+            assert_eq!(decrypt(expected, token).unwrap(), "BSMS 1.0\n");
+        }
+    }
+
+    #[test]
+    fn encrypted_record_roundtrip() {
+        let token = "a54044308ceac9b7";
+        let encrypted = encrypt(&record("8u7ztlnt"), token).unwrap();
+        let decrypted = decrypt(&encrypted, token).unwrap();
+        assert_eq!(decrypted, record("8u7ztlnt"));
+        assert!(DescriptorRecord::parse(&decrypted).is_ok());
+
+        assert!(decrypt(&encrypted, "a54044308ceac9b8").is_err());
+        let mut tampered = hex::decode(&encrypted).unwrap();
+        tampered[40] ^= 1;
+        assert!(decrypt(&hex::encode(&tampered), token).is_err());
+        assert!(decrypt("00", token).is_err());
+        assert!(encrypt("BSMS 1.0\n", "a540").is_err());
+        assert_eq!(encrypt("BSMS 1.0\n", NO_ENCRYPTION).unwrap(), "BSMS 1.0\n");
+        assert_eq!(decrypt("BSMS 1.0\n", NO_ENCRYPTION).unwrap(), "BSMS 1.0\n");
+    }
+}
@@ -0,0 +1,386 @@
+//! Builder for QREncoder, all the settings are validated when building
+
+use crate::device::DeviceProfile;
+use crate::psbt::{self, PsbtEncoding, PsbtVersion};
+use crate::{DataType, Encode, Encoding, Error, OutputType, QREncoder};
+use bip39::Mnemonic;
+use bitcoin::bip32::{ExtendedPrivKey as XPriv, ExtendedPubKey as XPub};
+use bitcoin::psbt::PartiallySignedTransaction as Psbt;
+use bitcoin::{PrivateKey, Transaction};
+use liana::descriptors::LianaDescriptor;
+use miniscript::{Descriptor, DescriptorPublicKey};
+
+/// Builder for QREncoder, see `QREncoder::builder()`
+#[derive(Debug, Clone, Default)]
+pub struct EncoderBuilder {
+    encoding: Option<Encoding>,
+    max_len: Option<usize>,
+    profile: Option<DeviceProfile>,
+    payload: Option<DataType>,
+    psbt_encoding: Option<PsbtEncoding>,
+    psbt_version: Option<PsbtVersion>,
+}
+
+impl QREncoder {
+    pub fn builder() -> EncoderBuilder {
+        EncoderBuilder::default()
+    }
+}
+
+impl EncoderBuilder {
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = Some(encoding);
+        self
+    }
+
+    /// Max frame length, the payload is sent as a single QRCode if not set
+    pub fn max_frame_len(mut self, max_len: usize) -> Self {
+        self.max_len = Some(max_len);
+        self
+    }
+
+    /// Take the encoding and max frame length from a device profile, explicit
+    /// `encoding()` and `max_frame_len()` take precedence
+    pub fn profile(mut self, profile: &DeviceProfile) -> Self {
+        self.profile = Some(*profile);
+        self
+    }
+
+    pub fn payload(mut self, data: DataType) -> Self {
+        self.payload = Some(data);
+        self
+    }
+
+    pub fn payload_psbt(self, psbt: &Psbt) -> Self {
+        self.payload(DataType::Psbt(Some(psbt.clone())))
+    }
+
+    /// Text or binary representation of a PSBT payload, base64 if not set. UR
+    /// carries the PSBT itself and Specter frames are text
+    pub fn psbt_encoding(mut self, encoding: PsbtEncoding) -> Self {
+        self.psbt_encoding = Some(encoding);
+        self
+    }
+
+    /// PSBT version the receiver expects, the PSBT payload is converted
+    pub fn psbt_version(mut self, version: PsbtVersion) -> Self {
+        self.psbt_version = Some(version);
+        self
+    }
+
+    pub fn payload_descriptor(self, descriptor: &Descriptor<DescriptorPublicKey>) -> Self {
+        self.payload(DataType::Descriptor(Some(descriptor.clone())))
+    }
+
+    pub fn payload_liana_descriptor(self, descriptor: &LianaDescriptor) -> Self {
+        self.payload(DataType::LianaDescriptor(Some(descriptor.clone())))
+    }
+
+    pub fn payload_xpub(self, xpub: &XPub) -> Self {
+        self.payload(DataType::Xpub(Some(*xpub)))
+    }
+
+    pub fn payload_xpriv(self, xpriv: &XPriv) -> Self {
+        self.payload(DataType::Xpriv(Some(*xpriv)))
+    }
+
+    pub fn payload_mnemonic(self, mnemonic: &Mnemonic) -> Self {
+        self.payload(DataType::Mnemonic(Some(mnemonic.clone())))
+    }
+
+    pub fn payload_private_key(self, key: &PrivateKey) -> Self {
+        self.payload(DataType::PrivateKey(Some(*key)))
+    }
+
+    pub fn payload_transaction(self, tx: &Transaction) -> Self {
+        self.payload(DataType::Transaction(Some(tx.clone())))
+    }
+
+    /// Untyped string payload
+    pub fn payload_string(self, data: &str) -> Self {
+        self.payload(DataType::NoType(Some(data.to_string())))
+    }
+
+    /// Validate the settings and return an encoder ready to serve frames
+    pub fn build(self) -> Result<Box<QREncoder>, Error> {
+        let data = self
+            .payload
+            .ok_or_else(|| Error::EncodingError("No payload to encode!".to_string()))?;
+        let data = match (data, self.psbt_version) {
+            (DataType::Psbt(Some(psbt)) | DataType::PsbtV2(Some(psbt)), Some(version)) => {
+                DataType::from_psbt(psbt, version)
+            }
+            (data @ (DataType::Psbt(_) | DataType::PsbtV2(_)), _) => data,
+            (data, None) if self.psbt_encoding.is_none() => data,
+            _ => {
+                return Err(Error::EncodingError(
+                    "PSBT encoding or version set for a payload that is not a PSBT!".to_string(),
+                ))
+            }
+        };
+
+        let encoding = match (self.encoding, &self.profile) {
+            (Some(encoding), _) => encoding,
+            (None, Some(profile)) => {
+                profile
+                    .format(&data)
+                    .ok_or_else(|| {
+                        Error::EncodingError(format!(
+                            "{:?} cannot receive this data!",
+                            profile.device
+                        ))
+                    })?
+                    .0
+            }
+            (None, None) => {
+                return Err(Error::EncodingError("No encoding selected!".to_string()))
+            }
+        };
+        // frames and single QRCodes must fit the QRCode version of the device
+        let max_len = match &self.profile {
+            Some(profile) => {
+                if encoding == Encoding::Raw {
+                    profile.check_single(&self.raw_payload(&data)?)?;
+                }
+                profile.frame_len(encoding, self.max_len)?
+            }
+            None => self.max_len,
+        };
+        // report unsupported data/encoding before encoding anything
+        EncoderBuilder::check(&data, encoding, max_len)?;
+
+        match (&data, self.psbt_encoding) {
+            (DataType::Psbt(Some(psbt)) | DataType::PsbtV2(Some(psbt)), Some(psbt_encoding)) => {
+                let mut out = QREncoder::new();
+                out.set_output_type(data.clone(), encoding, max_len)?;
+                out.load_psbt(psbt, psbt_encoding, version_of(&data))?;
+                Ok(Box::new(out))
+            }
+            _ => QREncoder::from_data(&data, encoding, max_len),
+        }
+    }
+
+    /// Payload of the single QRCode of the raw encoding
+    fn raw_payload(&self, data: &DataType) -> Result<Vec<u8>, Error> {
+        match (data, self.psbt_encoding) {
+            (DataType::Psbt(Some(psbt)) | DataType::PsbtV2(Some(psbt)), Some(encoding)) => {
+                psbt::encode_version(psbt, encoding, version_of(data))
+            }
+            _ => Ok(data.to_text()?.into_bytes()),
+        }
+    }
+
+    /// Validate an encoding and max frame length for `data`, the checks shared
+    /// by `build()` and `QREncoder::set_output_type()`
+    pub(crate) fn check(
+        data: &DataType,
+        encoding: Encoding,
+        max_len: Option<usize>,
+    ) -> Result<OutputType, Error> {
+        let output_type = QREncoder::output_type(data, encoding)?;
+        if max_len == Some(0) {
+            return Err(Error::EncodingError(
+                "Max frame length cannot be 0!".to_string(),
+            ));
+        }
+        if let (Encoding::Ur, DataType::Descriptor(Some(descriptor))) = (encoding, data) {
+            if descriptor.is_multipath() {
+                return Err(Error::EncodingError(
+                    "crypto-output cannot hold a multipath descriptor!".to_string(),
+                ));
+            }
+        }
+        Ok(output_type)
+    }
+}
+
+fn version_of(data: &DataType) -> PsbtVersion {
+    match data {
+        DataType::PsbtV2(_) => PsbtVersion::V2,
+        _ => PsbtVersion::V0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptor;
+    use crate::device::Device;
+    use bitcoin::absolute::LockTime;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{OutPoint, ScriptBuf, TxIn, TxOut, Txid};
+    use std::str::FromStr;
+
+    const XPUB: &str = "xpub6ASuArnXKPbfEwhqN6e3mwBcDTgzisQN1wXN9BJcM47sSikHjJf3UFHKkNAWbWMiGj7Wf5uMash7SyYq527Hqck2AxYysAA7xmALppuCkwQ";
+
+    fn xpub() -> XPub {
+        XPub::from_str(XPUB).unwrap()
+    }
+
+    fn psbt() -> Psbt {
+        let tx = Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::from_byte_array([1; 32]), 0),
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: 1_000,
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+        Psbt::from_unsigned_tx(tx).unwrap()
+    }
+
+    #[test]
+    fn build_raw() {
+        let mut encoder = QREncoder::builder()
+            .encoding(Encoding::Raw)
+            .payload_xpub(&xpub())
+            .build()
+            .unwrap();
+        assert_eq!(*encoder.output(), OutputType::Xpub);
+        assert_eq!(encoder.next().unwrap(), XPUB);
+    }
+
+    #[test]
+    fn invalid_settings() {
+        assert!(QREncoder::builder()
+            .encoding(Encoding::Raw)
+            .build()
+            .is_err());
+        assert!(QREncoder::builder().payload_xpub(&xpub()).build().is_err());
+        assert!(QREncoder::builder()
+            .encoding(Encoding::Ur)
+            .payload(DataType::Xpriv(None))
+            .build()
+            .is_err());
+        assert!(QREncoder::builder()
+            .encoding(Encoding::Specter)
+            .max_frame_len(0)
+            .payload_xpub(&xpub())
+            .build()
+            .is_err());
+        let multipath = descriptor::parse(&format!("wpkh({}/<0;1>/*)", XPUB)).unwrap();
+        assert!(QREncoder::builder()
+            .encoding(Encoding::Ur)
+            .payload_descriptor(&multipath)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn build_with_profile() {
+        let jade = DeviceProfile::get(Device::Jade);
+        let encoder = QREncoder::builder()
+            .profile(&jade)
+            .payload_xpub(&xpub())
+            .build()
+            .unwrap();
+        assert_eq!(*encoder.output(), OutputType::UrXpub);
+        // explicit settings take precedence
+        let encoder = QREncoder::builder()
+            .profile(&jade)
+            .encoding(Encoding::Raw)
+            .payload_xpub(&xpub())
+            .build()
+            .unwrap();
+        assert_eq!(*encoder.output(), OutputType::Xpub);
+        // larger than the QRCode version the device scans
+        assert!(QREncoder::builder()
+            .profile(&jade)
+            .max_frame_len(1000)
+            .payload_xpub(&xpub())
+            .build()
+            .is_err());
+        let mk4 = DeviceProfile::get(Device::ColdcardMk4);
+        assert!(QREncoder::builder()
+            .profile(&mk4)
+            .payload_xpub(&xpub())
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn load_string_uses_builder() {
+        let mut encoder = QREncoder::new();
+        // no output type selected
+        assert!(encoder.load_string(XPUB).is_err());
+        assert!(encoder
+            .set_output_type(DataType::Xpriv(None), Encoding::Ur, None)
+            .is_err());
+        assert!(encoder
+            .set_output_type(DataType::Xpub(None), Encoding::Raw, Some(0))
+            .is_err());
+        encoder
+            .set_output_type(DataType::Xpub(None), Encoding::Raw, None)
+            .unwrap();
+        let mut loaded = encoder.load_string(XPUB).unwrap();
+        assert_eq!(*loaded.output(), OutputType::Xpub);
+        assert_eq!(loaded.next().unwrap(), XPUB);
+        assert!(encoder.load_string("not an xpub").is_err());
+    }
+
+    #[test]
+    fn build_psbt() {
+        let psbt = psbt();
+        // the payload of from_psbt_with()
+        for encoding in [
+            PsbtEncoding::Base64,
+            PsbtEncoding::Hex,
+            PsbtEncoding::Binary,
+        ] {
+            let mut built = QREncoder::builder()
+                .encoding(Encoding::Raw)
+                .payload_psbt(&psbt)
+                .psbt_encoding(encoding)
+                .psbt_version(PsbtVersion::V2)
+                .build()
+                .unwrap();
+            let mut expected = QREncoder::from_psbt_with(&psbt, encoding, PsbtVersion::V2).unwrap();
+            assert_eq!(*built.output(), OutputType::Psbt);
+            assert_eq!(built.next(), expected.next());
+            assert_eq!(built.next_bytes(), expected.next_bytes());
+        }
+
+        let mut encoder = QREncoder::builder()
+            .encoding(Encoding::Specter)
+            .max_frame_len(50)
+            .payload_psbt(&psbt)
+            .psbt_encoding(PsbtEncoding::Hex)
+            .build()
+            .unwrap();
+        assert_eq!(*encoder.output(), OutputType::SpecterPsbt);
+        let frame = encoder.next().unwrap();
+        assert!(frame.split_once(' ').unwrap().1.starts_with("70736274ff"));
+
+        // a version alone converts UR payloads
+        let encoder = QREncoder::builder()
+            .encoding(Encoding::Ur)
+            .payload_psbt(&psbt)
+            .psbt_version(PsbtVersion::V2)
+            .build()
+            .unwrap();
+        assert_eq!(*encoder.output(), OutputType::UrPsbt);
+
+        // Specter frames are text, UR carries the PSBT itself
+        for (encoding, psbt_encoding) in [
+            (Encoding::Specter, PsbtEncoding::Binary),
+            (Encoding::Ur, PsbtEncoding::Hex),
+        ] {
+            assert!(QREncoder::builder()
+                .encoding(encoding)
+                .payload_psbt(&psbt)
+                .psbt_encoding(psbt_encoding)
+                .build()
+                .is_err());
+        }
+        assert!(QREncoder::builder()
+            .encoding(Encoding::Raw)
+            .payload_xpub(&xpub())
+            .psbt_encoding(PsbtEncoding::Hex)
+            .build()
+            .is_err());
+    }
+}
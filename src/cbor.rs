@@ -0,0 +1,351 @@
+use crate::Error;
+
+/// A minimal CBOR data model, covering what UR registry types need
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Unsigned(u64),
+    Negative(u64),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+    Tag(u64, Box<Value>),
+    Bool(bool),
+    Null,
+}
+
+impl Value {
+    /// Return the value of an unsigned integer key in a map
+    pub fn get(&self, key: u64) -> Option<&Value> {
+        match self {
+            Value::Map(entries) => entries
+                .iter()
+                .find(|(k, _)| *k == Value::Unsigned(key))
+                .map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Unwrap a tagged value if the tag match
+    pub fn untag(&self, tag: u64) -> Result<&Value, Error> {
+        match self {
+            Value::Tag(t, value) if *t == tag => Ok(value),
+            _ => Err(Error::cbor(&format!("Expected CBOR tag {}!", tag))),
+        }
+    }
+
+    pub fn as_u64(&self) -> Result<u64, Error> {
+        match self {
+            Value::Unsigned(value) => Ok(*value),
+            _ => Err(Error::cbor("Expected CBOR unsigned!")),
+        }
+    }
+
+    pub fn as_bytes(&self) -> Result<&[u8], Error> {
+        match self {
+            Value::Bytes(value) => Ok(value),
+            _ => Err(Error::cbor("Expected CBOR bytes!")),
+        }
+    }
+
+    pub fn as_array(&self) -> Result<&[Value], Error> {
+        match self {
+            Value::Array(value) => Ok(value),
+            _ => Err(Error::cbor("Expected CBOR array!")),
+        }
+    }
+
+    pub fn as_bool(&self) -> Result<bool, Error> {
+        match self {
+            Value::Bool(value) => Ok(*value),
+            _ => Err(Error::cbor("Expected CBOR bool!")),
+        }
+    }
+
+    /// Build a map from unsigned integer keys
+    pub fn map(entries: Vec<(u64, Value)>) -> Value {
+        Value::Map(
+            entries
+                .into_iter()
+                .map(|(k, v)| (Value::Unsigned(k), v))
+                .collect(),
+        )
+    }
+
+    pub fn tag(tag: u64, value: Value) -> Value {
+        Value::Tag(tag, Box::new(value))
+    }
+}
+
+fn write_header(out: &mut Vec<u8>, major: u8, value: u64) {
+    let major = major << 5;
+    if value < 24 {
+        out.push(major | value as u8);
+    } else if value <= u8::MAX as u64 {
+        out.push(major | 24);
+        out.push(value as u8);
+    } else if value <= u16::MAX as u64 {
+        out.push(major | 25);
+        out.extend_from_slice(&(value as u16).to_be_bytes());
+    } else if value <= u32::MAX as u64 {
+        out.push(major | 26);
+        out.extend_from_slice(&(value as u32).to_be_bytes());
+    } else {
+        out.push(major | 27);
+        out.extend_from_slice(&value.to_be_bytes());
+    }
+}
+
+fn write_value(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Unsigned(v) => write_header(out, 0, *v),
+        Value::Negative(v) => write_header(out, 1, *v),
+        Value::Bytes(v) => {
+            write_header(out, 2, v.len() as u64);
+            out.extend_from_slice(v);
+        }
+        Value::Text(v) => {
+            write_header(out, 3, v.len() as u64);
+            out.extend_from_slice(v.as_bytes());
+        }
+        Value::Array(v) => {
+            write_header(out, 4, v.len() as u64);
+            for item in v {
+                write_value(out, item);
+            }
+        }
+        Value::Map(v) => {
+            write_header(out, 5, v.len() as u64);
+            for (key, item) in v {
+                write_value(out, key);
+                write_value(out, item);
+            }
+        }
+        Value::Tag(tag, v) => {
+            write_header(out, 6, *tag);
+            write_value(out, v);
+        }
+        Value::Bool(false) => out.push(0xf4),
+        Value::Bool(true) => out.push(0xf5),
+        Value::Null => out.push(0xf6),
+    }
+}
+
+/// Serialize a CBOR value
+pub fn encode(value: &Value) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_value(&mut out, value);
+    out
+}
+
+/// Max nesting of arrays, maps and tags, deeper input is rejected instead of
+/// overflowing the stack
+const MAX_DEPTH: usize = 64;
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| Error::cbor("Unexpected end of CBOR data!"))?;
+        let out = &self.data[self.position..end];
+        self.position = end;
+        Ok(out)
+    }
+
+    fn header(&mut self) -> Result<(u8, u8, u64), Error> {
+        let first = self.take(1)?[0];
+        let major = first >> 5;
+        let info = first & 0x1f;
+        let value = match info {
+            0..=23 => info as u64,
+            24 => self.take(1)?[0] as u64,
+            25 => u16::from_be_bytes(self.take(2)?.try_into().unwrap()) as u64,
+            26 => u32::from_be_bytes(self.take(4)?.try_into().unwrap()) as u64,
+            27 => u64::from_be_bytes(self.take(8)?.try_into().unwrap()),
+            _ => return Err(Error::cbor("Unsupported CBOR length encoding!")),
+        };
+        Ok((major, info, value))
+    }
+
+    fn len(&self, value: u64) -> Result<usize, Error> {
+        // every item takes at least one byte, this bound avoid huge allocations
+        if value > (self.data.len() - self.position) as u64 {
+            Err(Error::cbor("CBOR length out of bounds!"))
+        } else {
+            Ok(value as usize)
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, Error> {
+        if self.depth >= MAX_DEPTH {
+            return Err(Error::cbor("CBOR nesting too deep!"));
+        }
+        self.depth += 1;
+        let value = self.item();
+        self.depth -= 1;
+        value
+    }
+
+    fn item(&mut self) -> Result<Value, Error> {
+        let (major, info, value) = self.header()?;
+        match major {
+            0 => Ok(Value::Unsigned(value)),
+            1 => Ok(Value::Negative(value)),
+            2 => {
+                let len = self.len(value)?;
+                Ok(Value::Bytes(self.take(len)?.to_vec()))
+            }
+            3 => {
+                let len = self.len(value)?;
+                let text = std::str::from_utf8(self.take(len)?)
+                    .map_err(|e| Error::cbor_with("Invalid CBOR text!", e))?;
+                Ok(Value::Text(text.to_string()))
+            }
+            4 => {
+                let len = self.len(value)?;
+                let mut items = Vec::with_capacity(len);
+                for _ in 0..len {
+                    items.push(self.value()?);
+                }
+                Ok(Value::Array(items))
+            }
+            5 => {
+                let len = self.len(value)?;
+                let mut entries = Vec::with_capacity(len);
+                for _ in 0..len {
+                    let key = self.value()?;
+                    let item = self.value()?;
+                    entries.push((key, item));
+                }
+                Ok(Value::Map(entries))
+            }
+            6 => Ok(Value::Tag(value, Box::new(self.value()?))),
+            _ => match info {
+                20 => Ok(Value::Bool(false)),
+                21 => Ok(Value::Bool(true)),
+                22 => Ok(Value::Null),
+                _ => Err(Error::cbor("Unsupported CBOR simple value!")),
+            },
+        }
+    }
+}
+
+/// Deserialize a CBOR value, the whole input must be consumed
+pub fn decode(data: &[u8]) -> Result<Value, Error> {
+    let mut reader = Reader {
+        data,
+        position: 0,
+        depth: 0,
+    };
+    let value = reader.value()?;
+    if reader.position != data.len() {
+        return Err(Error::cbor("Trailing bytes after CBOR value!"));
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex;
+
+    fn check(value: Value, expected: &str) {
+        let data = encode(&value);
+        assert_eq!(hex::encode(&data), expected);
+        assert_eq!(decode(&data).unwrap(), value);
+    }
+
+    #[test]
+    fn rfc8949_vectors() {
+        check(Value::Unsigned(0), "00");
+        check(Value::Unsigned(23), "17");
+        check(Value::Unsigned(24), "1818");
+        check(Value::Unsigned(100), "1864");
+        check(Value::Unsigned(1000), "1903e8");
+        check(Value::Unsigned(1000000), "1a000f4240");
+        check(Value::Unsigned(1000000000000), "1b000000e8d4a51000");
+        check(Value::Negative(0), "20");
+        check(Value::Negative(99), "3863");
+        check(Value::Bytes(vec![]), "40");
+        check(Value::Bytes(vec![1, 2, 3, 4]), "4401020304");
+        check(Value::Text(String::new()), "60");
+        check(Value::Text("IETF".to_string()), "6449455446");
+        check(Value::Array(vec![]), "80");
+        check(
+            Value::Array(vec![
+                Value::Unsigned(1),
+                Value::Array(vec![Value::Unsigned(2), Value::Unsigned(3)]),
+                Value::Array(vec![Value::Unsigned(4), Value::Unsigned(5)]),
+            ]),
+            "8301820203820405",
+        );
+        check(
+            Value::map(vec![(1, Value::Unsigned(2)), (3, Value::Unsigned(4))]),
+            "a201020304",
+        );
+        check(Value::tag(1, Value::Unsigned(1363896240)), "c11a514b67b0");
+        check(Value::Bool(false), "f4");
+        check(Value::Bool(true), "f5");
+        check(Value::Null, "f6");
+    }
+
+    #[test]
+    fn accessors() {
+        let value = Value::tag(
+            303,
+            Value::map(vec![
+                (2, Value::Bool(true)),
+                (3, Value::Bytes(vec![0xab])),
+                (4, Value::Array(vec![Value::Null])),
+            ]),
+        );
+        assert!(value.untag(304).is_err());
+        let map = value.untag(303).unwrap();
+        assert!(map.get(2).unwrap().as_bool().unwrap());
+        assert_eq!(map.get(3).unwrap().as_bytes().unwrap(), &[0xab]);
+        assert_eq!(map.get(4).unwrap().as_array().unwrap(), &[Value::Null]);
+        assert!(map.get(5).is_none());
+        assert!(map.get(2).unwrap().as_u64().is_err());
+    }
+
+    #[test]
+    fn malformed() {
+        // truncated
+        assert!(decode(&[0x19, 0x03]).is_err());
+        assert!(decode(&[0x44, 0x01]).is_err());
+        // trailing bytes
+        assert!(decode(&[0x01, 0x02]).is_err());
+        // length larger than the input
+        assert!(decode(&[0x9b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]).is_err());
+        // indefinite length
+        assert!(decode(&[0x9f, 0xff]).is_err());
+        // invalid utf8
+        assert!(decode(&[0x61, 0xff]).is_err());
+        // nesting deeper than MAX_DEPTH
+        let mut data = vec![0x81; MAX_DEPTH];
+        data.push(0x00);
+        assert!(matches!(decode(&data), Err(Error::CborError { .. })));
+        assert!(decode(&data[1..]).is_ok());
+    }
+
+    #[test]
+    fn error_source() {
+        use std::error::Error as _;
+        let err = decode(&[0x62, 0xff, 0xfe]).unwrap_err();
+        assert!(matches!(err, Error::CborError { .. }));
+        assert!(err
+            .source()
+            .unwrap()
+            .downcast_ref::<std::str::Utf8Error>()
+            .is_some());
+        assert!(decode(&[0x62, 0x61]).unwrap_err().source().is_none());
+    }
+}
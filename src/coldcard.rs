@@ -0,0 +1,377 @@
+//! Coldcard multisig wallet configuration file
+
+use crate::{descriptor, Error};
+use bitcoin::base58;
+use bitcoin::bip32::{DerivationPath, ExtendedPubKey as XPub, Fingerprint};
+use miniscript::descriptor::{DescriptorPublicKey, ShInner, SortedMultiVec, WshInner};
+use miniscript::{Descriptor, ScriptContext};
+use std::fmt;
+use std::str::FromStr;
+
+/// Coldcard wallet name max length
+pub const MAX_NAME_LEN: usize = 20;
+
+/// Address format of a Coldcard multisig wallet
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    P2sh,
+    P2shP2wsh,
+    P2wsh,
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Format::P2sh => write!(f, "P2SH"),
+            Format::P2shP2wsh => write!(f, "P2SH-P2WSH"),
+            Format::P2wsh => write!(f, "P2WSH"),
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_uppercase().as_str() {
+            "P2SH" => Ok(Format::P2sh),
+            "P2SH-P2WSH" | "P2WSH-P2SH" => Ok(Format::P2shP2wsh),
+            "P2WSH" => Ok(Format::P2wsh),
+            _ => Err(Error::ParsingError(format!("Unknown Coldcard format: {}", s))),
+        }
+    }
+}
+
+/// A cosigner of a Coldcard multisig wallet
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cosigner {
+    pub fingerprint: Fingerprint,
+    pub derivation: DerivationPath,
+    pub xpub: XPub,
+}
+
+/// A Coldcard multisig wallet configuration
+#[derive(Debug, Clone, PartialEq)]
+pub struct MultisigConfig {
+    pub name: String,
+    pub threshold: usize,
+    pub format: Format,
+    pub cosigners: Vec<Cosigner>,
+}
+
+/// Parse an xpub, SLIP132 (Ypub/Zpub/Upub/Vpub...) versions are converted
+pub fn parse_xpub(data: &str) -> Result<XPub, Error> {
+    let mut bytes = base58::decode_check(data.trim())
+        .map_err(|_| Error::ParsingError(format!("Invalid xpub: {}", data)))?;
+    if bytes.len() != 78 {
+        return Err(Error::ParsingError(format!("Invalid xpub: {}", data)));
+    }
+    let version: [u8; 4] = match bytes[..4] {
+        // xpub ypub zpub Ypub Zpub
+        [0x04, 0x88, 0xb2, 0x1e]
+        | [0x04, 0x9d, 0x7c, 0xb2]
+        | [0x04, 0xb2, 0x47, 0x46]
+        | [0x02, 0x95, 0xb4, 0x3f]
+        | [0x02, 0xaa, 0x7e, 0xd3] => [0x04, 0x88, 0xb2, 0x1e],
+        // tpub upub vpub Upub Vpub
+        [0x04, 0x35, 0x87, 0xcf]
+        | [0x04, 0x4a, 0x52, 0x62]
+        | [0x04, 0x5f, 0x1c, 0xf6]
+        | [0x02, 0x42, 0x89, 0xef]
+        | [0x02, 0x57, 0x54, 0x83] => [0x04, 0x35, 0x87, 0xcf],
+        _ => return Err(Error::ParsingError(format!("Unknown xpub version: {}", data))),
+    };
+    bytes[..4].copy_from_slice(&version);
+    XPub::decode(&bytes).map_err(|_| Error::ParsingError(format!("Invalid xpub: {}", data)))
+}
+
+fn parse_policy(data: &str) -> Result<(usize, usize), Error> {
+    let error = || Error::ParsingError(format!("Invalid Coldcard policy: {}", data));
+    let data = data.to_lowercase();
+    let (m, n) = data
+        .split_once("of")
+        .or_else(|| data.split_once('/'))
+        .ok_or_else(error)?;
+    let m = m.trim().parse::<usize>().map_err(|_| error())?;
+    let n = n.trim().parse::<usize>().map_err(|_| error())?;
+    if m == 0 || m > n {
+        return Err(error());
+    }
+    Ok((m, n))
+}
+
+impl MultisigConfig {
+    /// Parse a Coldcard multisig configuration file
+    pub fn parse(data: &str) -> Result<MultisigConfig, Error> {
+        let mut name = None;
+        let mut policy = None;
+        let mut format = Format::P2sh;
+        let mut derivation: Option<DerivationPath> = None;
+        let mut cosigners = Vec::new();
+
+        for line in data.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line.split_once(':').ok_or_else(|| {
+                Error::ParsingError(format!("Invalid Coldcard config line: {}", line))
+            })?;
+            let value = value.trim();
+            match key.trim().to_lowercase().as_str() {
+                "name" => name = Some(value.to_string()),
+                "policy" => policy = Some(parse_policy(value)?),
+                "format" => format = Format::from_str(value)?,
+                "derivation" => {
+                    derivation = Some(DerivationPath::from_str(value).map_err(|_| {
+                        Error::ParsingError(format!("Invalid derivation path: {}", value))
+                    })?)
+                }
+                xfp if xfp.len() == 8 => {
+                    let fingerprint = Fingerprint::from_str(xfp).map_err(|_| {
+                        Error::ParsingError(format!("Invalid fingerprint: {}", xfp))
+                    })?;
+                    let derivation = derivation.clone().ok_or_else(|| {
+                        Error::ParsingError(format!("Missing derivation for {}", xfp))
+                    })?;
+                    cosigners.push(Cosigner {
+                        fingerprint,
+                        derivation,
+                        xpub: parse_xpub(value)?,
+                    });
+                }
+                _ => {
+                    return Err(Error::ParsingError(format!(
+                        "Unknown Coldcard config line: {}",
+                        line
+                    )))
+                }
+            }
+        }
+
+        let name = name
+            .ok_or_else(|| Error::ParsingError("Missing Coldcard wallet name!".to_string()))?;
+        let (threshold, total) = policy
+            .ok_or_else(|| Error::ParsingError("Missing Coldcard policy!".to_string()))?;
+        if cosigners.len() != total {
+            return Err(Error::ParsingError(format!(
+                "Policy expects {} keys, {} found!",
+                total,
+                cosigners.len()
+            )));
+        }
+        Ok(MultisigConfig {
+            name,
+            threshold,
+            format,
+            cosigners,
+        })
+    }
+
+    /// Return the `<0;1>` multipath sortedmulti descriptor of the wallet
+    pub fn to_descriptor(&self) -> Result<Descriptor<DescriptorPublicKey>, Error> {
+        let keys: Vec<String> = self
+            .cosigners
+            .iter()
+            .map(|c| {
+                let path = c.derivation.to_string();
+                let path = path.trim_start_matches('m');
+                format!("[{}{}]{}/<0;1>/*", c.fingerprint, path, c.xpub)
+            })
+            .collect();
+        let multi = format!("sortedmulti({},{})", self.threshold, keys.join(","));
+        let descriptor = match self.format {
+            Format::P2sh => format!("sh({})", multi),
+            Format::P2shP2wsh => format!("sh(wsh({}))", multi),
+            Format::P2wsh => format!("wsh({})", multi),
+        };
+        descriptor::parse(&descriptor)
+    }
+
+    /// Build the configuration of a sortedmulti descriptor, keys must have an origin
+    pub fn from_descriptor(
+        descriptor: &Descriptor<DescriptorPublicKey>,
+        name: &str,
+    ) -> Result<MultisigConfig, Error> {
+        if name.is_empty() || name.len() > MAX_NAME_LEN {
+            return Err(Error::EncodingError(format!(
+                "Coldcard wallet name must be 1 to {} characters!",
+                MAX_NAME_LEN
+            )));
+        }
+        let (format, threshold, keys) = match descriptor {
+            Descriptor::Wsh(wsh) => match wsh.as_inner() {
+                WshInner::SortedMulti(smv) => (Format::P2wsh, smv.k, cosigners(smv)?),
+                _ => return Err(not_sortedmulti()),
+            },
+            Descriptor::Sh(sh) => match sh.as_inner() {
+                ShInner::Wsh(wsh) => match wsh.as_inner() {
+                    WshInner::SortedMulti(smv) => (Format::P2shP2wsh, smv.k, cosigners(smv)?),
+                    _ => return Err(not_sortedmulti()),
+                },
+                ShInner::SortedMulti(smv) => (Format::P2sh, smv.k, cosigners(smv)?),
+                _ => return Err(not_sortedmulti()),
+            },
+            _ => return Err(not_sortedmulti()),
+        };
+        Ok(MultisigConfig {
+            name: name.to_string(),
+            threshold,
+            format,
+            cosigners: keys,
+        })
+    }
+}
+
+fn not_sortedmulti() -> Error {
+    Error::EncodingError("Coldcard only register sortedmulti descriptors!".to_string())
+}
+
+fn cosigners<Ctx: ScriptContext>(
+    smv: &SortedMultiVec<DescriptorPublicKey, Ctx>,
+) -> Result<Vec<Cosigner>, Error> {
+    smv.pks
+        .iter()
+        .map(|key| {
+            let (origin, xpub) = match key {
+                DescriptorPublicKey::XPub(key) => (&key.origin, key.xkey),
+                DescriptorPublicKey::MultiXPub(key) => (&key.origin, key.xkey),
+                DescriptorPublicKey::Single(_) => {
+                    return Err(Error::EncodingError(
+                        "Coldcard multisig keys must be xpubs!".to_string(),
+                    ))
+                }
+            };
+            let (fingerprint, derivation) = origin.clone().ok_or_else(|| {
+                Error::EncodingError("Coldcard multisig keys must have an origin!".to_string())
+            })?;
+            Ok(Cosigner {
+                fingerprint,
+                derivation,
+                xpub,
+            })
+        })
+        .collect()
+}
+
+impl FromStr for MultisigConfig {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        MultisigConfig::parse(s)
+    }
+}
+
+impl fmt::Display for MultisigConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# Coldcard Multisig setup file")?;
+        writeln!(f, "#")?;
+        writeln!(f, "Name: {}", self.name)?;
+        writeln!(f, "Policy: {} of {}", self.threshold, self.cosigners.len())?;
+        writeln!(f, "Format: {}", self.format)?;
+        let mut derivation = None;
+        for cosigner in &self.cosigners {
+            if derivation != Some(&cosigner.derivation) {
+                writeln!(f)?;
+                writeln!(f, "Derivation: {}", cosigner.derivation)?;
+                derivation = Some(&cosigner.derivation);
+            }
+            writeln!(
+                f,
+                "{}: {}",
+                cosigner.fingerprint.to_string().to_uppercase(),
+                cosigner.xpub
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MASTER: &str = "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8";
+    const CHILD: &str = "xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw";
+    const XPUB: &str = "xpub6ASuArnXKPbfEwhqN6e3mwBcDTgzisQN1wXN9BJcM47sSikHjJf3UFHKkNAWbWMiGj7Wf5uMash7SyYq527Hqck2AxYysAA7xmALppuCkwQ";
+    // XPUB with the SLIP132 Zpub version
+    const ZPUB: &str = "Zpub711WuRroBiEzN7FSyUgH2BiRHC29pU4xASDUdEMvUqi3B6wf12NSoVTXbV3A9ktTKUR72d6bPQniLiPioe6GaaDtk74ESPG6PwZWBshqeZy";
+
+    fn config() -> String {
+        format!(
+            "# Coldcard Multisig setup file\n\
+             #\n\
+             Name: Family vault\n\
+             Policy: 2 of 3\n\
+             Format: P2WSH\n\
+             \n\
+             Derivation: m/48'/0'/0'/2'\n\
+             0F056943: {}\n\
+             5C1BD648: {}\n\
+             Derivation: m/48'/0'/1'/2'\n\
+             BEF5A2F9: {}\n",
+            MASTER, CHILD, ZPUB
+        )
+    }
+
+    #[test]
+    fn slip132() {
+        assert_eq!(parse_xpub(ZPUB).unwrap(), XPub::from_str(XPUB).unwrap());
+        assert_eq!(parse_xpub(XPUB).unwrap(), XPub::from_str(XPUB).unwrap());
+        assert!(parse_xpub("xpub").is_err());
+    }
+
+    #[test]
+    fn parse_config() {
+        let config = MultisigConfig::parse(&config()).unwrap();
+        assert_eq!(config.name, "Family vault");
+        assert_eq!(config.threshold, 2);
+        assert_eq!(config.format, Format::P2wsh);
+        assert_eq!(config.cosigners.len(), 3);
+        assert_eq!(config.cosigners[2].xpub, XPub::from_str(XPUB).unwrap());
+        assert_eq!(
+            config.cosigners[2].derivation,
+            DerivationPath::from_str("m/48'/0'/1'/2'").unwrap()
+        );
+
+        let expected = descriptor::parse(&format!(
+            "wsh(sortedmulti(2,[0f056943/48'/0'/0'/2']{}/<0;1>/*,[5c1bd648/48'/0'/0'/2']{}/<0;1>/*,[bef5a2f9/48'/0'/1'/2']{}/<0;1>/*))",
+            MASTER, CHILD, XPUB
+        ))
+        .unwrap();
+        assert_eq!(config.to_descriptor().unwrap(), expected);
+    }
+
+    #[test]
+    fn config_roundtrip() {
+        let config = MultisigConfig::parse(&config()).unwrap();
+        assert_eq!(MultisigConfig::parse(&config.to_string()).unwrap(), config);
+        let descriptor = config.to_descriptor().unwrap();
+        assert_eq!(
+            MultisigConfig::from_descriptor(&descriptor, "Family vault").unwrap(),
+            config
+        );
+    }
+
+    #[test]
+    fn invalid_config() {
+        let config = config();
+        for (from, to) in [
+            ("2 of 3", "4 of 3"),
+            ("2 of 3", "2 of 4"),
+            ("Name: Family vault\n", ""),
+            ("Derivation: m/48'/0'/0'/2'\n", ""),
+            ("P2WSH", "P2TR"),
+        ] {
+            assert!(MultisigConfig::parse(&config.replace(from, to)).is_err());
+        }
+        let descriptor = MultisigConfig::parse(&config)
+            .unwrap()
+            .to_descriptor()
+            .unwrap();
+        assert!(MultisigConfig::from_descriptor(&descriptor, "").is_err());
+        assert!(MultisigConfig::from_descriptor(&descriptor, &"x".repeat(21)).is_err());
+        let wpkh = descriptor::parse(&format!("wpkh([5c1bd648/0']{}/0/*)", XPUB)).unwrap();
+        assert!(MultisigConfig::from_descriptor(&wpkh, "Single").is_err());
+    }
+}
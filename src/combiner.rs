@@ -0,0 +1,282 @@
+//! Combine the PSBTs scanned back from several cosigners
+
+use crate::{DataType, Error};
+use bitcoin::blockdata::opcodes::all::{OP_CHECKMULTISIG, OP_PUSHNUM_1, OP_PUSHNUM_16};
+use bitcoin::blockdata::script::Instruction;
+use bitcoin::psbt::{Input, PartiallySignedTransaction as Psbt};
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::{Script, Transaction};
+use miniscript::psbt::PsbtExt;
+
+/// Signing status of a PSBT input
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputStatus {
+    pub index: usize,
+    /// Signatures collected so far
+    pub signatures: usize,
+    /// Signatures required, None if the script is not a plain multisig/singlesig
+    pub required: Option<usize>,
+    /// The input can be finalized with the collected signatures
+    pub complete: bool,
+}
+
+/// Threshold of a `OP_k <keys> OP_n OP_CHECKMULTISIG` script
+fn multisig_threshold(script: &Script) -> Option<usize> {
+    let instructions: Vec<Instruction> = script.instructions().collect::<Result<_, _>>().ok()?;
+    match (instructions.first(), instructions.last()) {
+        (Some(Instruction::Op(k)), Some(Instruction::Op(last))) if *last == OP_CHECKMULTISIG => {
+            let k = k.to_u8();
+            if (OP_PUSHNUM_1.to_u8()..=OP_PUSHNUM_16.to_u8()).contains(&k) {
+                Some((k - OP_PUSHNUM_1.to_u8() + 1) as usize)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+fn required_signatures(input: &Input) -> Option<usize> {
+    match (&input.witness_script, &input.redeem_script) {
+        (Some(script), _) => multisig_threshold(script),
+        (None, Some(script)) if !script.is_v0_p2wpkh() => multisig_threshold(script),
+        // p2wpkh, p2pkh or taproot key path
+        (None, _) if input.tap_scripts.is_empty() => Some(1),
+        _ => None,
+    }
+}
+
+fn signatures(input: &Input) -> usize {
+    input.partial_sigs.len() + input.tap_script_sigs.len() + input.tap_key_sig.iter().count()
+}
+
+/// A session merging the PSBTs signed by each cosigner
+#[derive(Debug, Clone, Default)]
+pub struct PsbtCombiner {
+    psbt: Option<Psbt>,
+    received: usize,
+}
+
+impl PsbtCombiner {
+    pub fn new() -> PsbtCombiner {
+        PsbtCombiner::default()
+    }
+
+    /// Add a decoded PSBT (v0 or v2)
+    pub fn add(&mut self, data: &DataType) -> Result<(), Error> {
+        match data {
+            DataType::Psbt(Some(psbt)) | DataType::PsbtV2(Some(psbt)) => {
+                self.add_psbt(psbt.clone())
+            }
+            _ => Err(Error::ParsingError("Data is not a PSBT!".to_string())),
+        }
+    }
+
+    /// Add a PSBT, it must spend the same unsigned transaction as the previous ones
+    pub fn add_psbt(&mut self, psbt: Psbt) -> Result<(), Error> {
+        match self.psbt.as_mut() {
+            None => self.psbt = Some(psbt),
+            Some(combined) => {
+                if combined.unsigned_tx.txid() != psbt.unsigned_tx.txid() {
+                    return Err(Error::ParsingError(
+                        "PSBT does not share the same unsigned transaction!".to_string(),
+                    ));
+                }
+                combined
+                    .combine(psbt)
+                    .map_err(|e| Error::psbt_with("cannot combine", e))?;
+            }
+        }
+        self.received += 1;
+        Ok(())
+    }
+
+    /// Number of PSBTs combined
+    pub fn received(&self) -> usize {
+        self.received
+    }
+
+    /// The combined PSBT
+    pub fn psbt(&self) -> Option<&Psbt> {
+        self.psbt.as_ref()
+    }
+
+    /// Signing status of every input
+    pub fn status(&self) -> Vec<InputStatus> {
+        let psbt = match &self.psbt {
+            Some(psbt) => psbt,
+            None => return vec![],
+        };
+        let secp = Secp256k1::verification_only();
+        // inputs are finalized on a single copy, each one independently
+        let mut finalized = psbt.clone();
+        psbt.inputs
+            .iter()
+            .enumerate()
+            .map(|(index, input)| {
+                let complete = input.final_script_witness.is_some()
+                    || input.final_script_sig.is_some()
+                    || finalized.finalize_inp_mut(&secp, index).is_ok();
+                InputStatus {
+                    index,
+                    signatures: signatures(input),
+                    required: required_signatures(input),
+                    complete,
+                }
+            })
+            .collect()
+    }
+
+    /// Inputs still short of signatures
+    pub fn pending(&self) -> Vec<InputStatus> {
+        self.status()
+            .into_iter()
+            .filter(|status| !status.complete)
+            .collect()
+    }
+
+    /// Finalize every input of the combined PSBT
+    pub fn finalize(&self) -> Result<Psbt, Error> {
+        let mut psbt = self
+            .psbt
+            .clone()
+            .ok_or_else(|| Error::ParsingError("No PSBT received!".to_string()))?;
+        let secp = Secp256k1::verification_only();
+        psbt.finalize_mut(&secp).map_err(|errors| {
+            let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            Error::ParsingError(format!("Cannot finalize PSBT: {}", errors.join(", ")))
+        })?;
+        Ok(psbt)
+    }
+
+    /// Finalize the combined PSBT and extract the signed transaction
+    pub fn extract(&self) -> Result<Transaction, Error> {
+        let psbt = self.finalize()?;
+        let secp = Secp256k1::verification_only();
+        psbt.extract(&secp)
+            .map_err(|e| Error::ParsingError(format!("Cannot extract transaction: {}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::absolute::LockTime;
+    use bitcoin::blockdata::opcodes::all::OP_PUSHNUM_2;
+    use bitcoin::blockdata::script::Builder;
+    use bitcoin::hashes::Hash;
+    use bitcoin::secp256k1::{Message, SecretKey};
+    use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+    use bitcoin::{OutPoint, PublicKey, ScriptBuf, TxIn, TxOut, Txid};
+
+    const AMOUNT: u64 = 10_000;
+
+    /// A PSBT spending a 2-of-2 P2WSH output, with the signatures of the given keys
+    fn cosigner_psbt(signers: &[usize]) -> Psbt {
+        let secp = Secp256k1::new();
+        let keys: Vec<SecretKey> = (1..=2)
+            .map(|i| SecretKey::from_slice(&[i; 32]).unwrap())
+            .collect();
+        let pks: Vec<PublicKey> = keys
+            .iter()
+            .map(|key| PublicKey::new(key.public_key(&secp)))
+            .collect();
+        let witness_script = Builder::new()
+            .push_opcode(OP_PUSHNUM_2)
+            .push_key(&pks[0])
+            .push_key(&pks[1])
+            .push_opcode(OP_PUSHNUM_2)
+            .push_opcode(OP_CHECKMULTISIG)
+            .into_script();
+        let tx = Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::from_byte_array([1; 32]), 0),
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: AMOUNT - 1_000,
+                script_pubkey: ScriptBuf::new_op_return(&[]),
+            }],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo = Some(TxOut {
+            value: AMOUNT,
+            script_pubkey: witness_script.to_v0_p2wsh(),
+        });
+        psbt.inputs[0].witness_script = Some(witness_script.clone());
+        let sighash = SighashCache::new(&psbt.unsigned_tx)
+            .segwit_signature_hash(0, &witness_script, AMOUNT, EcdsaSighashType::All)
+            .unwrap();
+        let message = Message::from(sighash);
+        for signer in signers {
+            let signature = secp.sign_ecdsa(&message, &keys[*signer]);
+            psbt.inputs[0].partial_sigs.insert(
+                pks[*signer],
+                bitcoin::ecdsa::Signature::sighash_all(signature),
+            );
+        }
+        psbt
+    }
+
+    #[test]
+    fn multisig_threshold_script() {
+        let psbt = cosigner_psbt(&[]);
+        let script = psbt.inputs[0].witness_script.as_ref().unwrap();
+        assert_eq!(multisig_threshold(script), Some(2));
+        assert_eq!(multisig_threshold(&ScriptBuf::new_op_return(&[])), None);
+        assert_eq!(required_signatures(&psbt.inputs[0]), Some(2));
+        assert_eq!(required_signatures(&Input::default()), Some(1));
+    }
+
+    #[test]
+    fn combine_cosigners() {
+        let mut combiner = PsbtCombiner::new();
+        assert!(combiner.status().is_empty());
+        assert!(combiner.finalize().is_err());
+
+        combiner
+            .add(&DataType::Psbt(Some(cosigner_psbt(&[0]))))
+            .unwrap();
+        let status = combiner.pending();
+        assert_eq!(
+            status,
+            vec![InputStatus {
+                index: 0,
+                signatures: 1,
+                required: Some(2),
+                complete: false,
+            }]
+        );
+        assert!(combiner.extract().is_err());
+
+        combiner
+            .add(&DataType::PsbtV2(Some(cosigner_psbt(&[1]))))
+            .unwrap();
+        assert_eq!(combiner.received(), 2);
+        assert!(combiner.pending().is_empty());
+        assert_eq!(combiner.status()[0].signatures, 2);
+        // status does not finalize the combined PSBT
+        assert!(combiner.psbt().unwrap().inputs[0]
+            .final_script_witness
+            .is_none());
+
+        let tx = combiner.extract().unwrap();
+        // empty item, 2 signatures, witness script
+        assert_eq!(tx.input[0].witness.len(), 4);
+    }
+
+    #[test]
+    fn reject_other_transaction() {
+        let mut combiner = PsbtCombiner::new();
+        combiner.add_psbt(cosigner_psbt(&[0])).unwrap();
+        let mut other = cosigner_psbt(&[1]);
+        other.unsigned_tx.lock_time = LockTime::from_consensus(1);
+        assert!(combiner.add_psbt(other).is_err());
+        assert!(combiner
+            .add(&DataType::NoType(Some("psbt".to_string())))
+            .is_err());
+        assert_eq!(combiner.received(), 1);
+    }
+}
@@ -0,0 +1,240 @@
+use crate::Error;
+use miniscript::descriptor::checksum::desc_checksum;
+use miniscript::{Descriptor, DescriptorPublicKey};
+use regex::{Captures, Regex};
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+/// Parse a public descriptor, the checksum is verified if present
+pub fn parse(data: &str) -> Result<Descriptor<DescriptorPublicKey>, Error> {
+    let data = data.trim();
+    let (body, checksum) = match data.split_once('#') {
+        Some((body, checksum)) => (body, Some(checksum)),
+        None => (data, None),
+    };
+    if let Some(checksum) = checksum {
+        let expected =
+            desc_checksum(body).map_err(|e| Error::descriptor_with("invalid checksum", e))?;
+        if expected != checksum {
+            return Err(Error::ParsingError(format!(
+                "Descriptor checksum mismatch: expected {}, found {}",
+                expected, checksum
+            )));
+        }
+    }
+    Descriptor::<DescriptorPublicKey>::from_str(body)
+        .map_err(|e| Error::descriptor_with("cannot parse", e))
+}
+
+/// Return the descriptor string, checksum appended
+pub fn to_string(descriptor: &Descriptor<DescriptorPublicKey>) -> String {
+    // Display already append the checksum
+    descriptor.to_string()
+}
+
+/// Append the checksum to a descriptor string if missing
+pub fn with_checksum(data: &str) -> Result<String, Error> {
+    Ok(to_string(&parse(data)?))
+}
+
+/// Parse either a single descriptor (possibly BIP389 multipath) or a
+/// receive/change pair of descriptors, one per line
+pub fn parse_descriptors(data: &str) -> Result<Descriptor<DescriptorPublicKey>, Error> {
+    let lines: Vec<&str> = data
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .collect();
+    match lines.as_slice() {
+        [descriptor] => parse(descriptor),
+        [receive, change] => from_single_paths(&parse(receive)?, &parse(change)?),
+        _ => Err(Error::ParsingError(
+            "Expected one multipath descriptor or a receive/change pair!".to_string(),
+        )),
+    }
+}
+
+/// Split a `<0;1>` multipath descriptor into its (receive, change) descriptors
+pub fn single_paths(
+    descriptor: &Descriptor<DescriptorPublicKey>,
+) -> Result<(Descriptor<DescriptorPublicKey>, Descriptor<DescriptorPublicKey>), Error> {
+    if !descriptor.is_multipath() {
+        return Err(Error::ParsingError(
+            "Descriptor is not multipath!".to_string(),
+        ));
+    }
+    let mut descriptors = descriptor
+        .clone()
+        .into_single_descriptors()
+        .map_err(|e| Error::descriptor_with("invalid multipath", e))?;
+    if descriptors.len() != 2 {
+        return Err(Error::ParsingError(
+            "Multipath descriptor must have exactly 2 paths (receive & change)!".to_string(),
+        ));
+    }
+    let change = descriptors.pop().unwrap();
+    let receive = descriptors.pop().unwrap();
+    Ok((receive, change))
+}
+
+/// Return the receive descriptor, a single path descriptor is returned as is
+pub fn receive_descriptor(
+    descriptor: &Descriptor<DescriptorPublicKey>,
+) -> Result<Descriptor<DescriptorPublicKey>, Error> {
+    if descriptor.is_multipath() {
+        Ok(single_paths(descriptor)?.0)
+    } else {
+        Ok(descriptor.clone())
+    }
+}
+
+/// Return the change descriptor of a multipath descriptor
+pub fn change_descriptor(
+    descriptor: &Descriptor<DescriptorPublicKey>,
+) -> Result<Descriptor<DescriptorPublicKey>, Error> {
+    Ok(single_paths(descriptor)?.1)
+}
+
+fn without_checksum(descriptor: &Descriptor<DescriptorPublicKey>) -> String {
+    let descriptor = descriptor.to_string();
+    match descriptor.split_once('#') {
+        Some((body, _)) => body.to_string(),
+        None => descriptor,
+    }
+}
+
+/// Merge a receive and a change descriptor into a `<a;b>` multipath descriptor,
+/// both descriptors must only differ by the step preceding the wildcard
+pub fn from_single_paths(
+    receive: &Descriptor<DescriptorPublicKey>,
+    change: &Descriptor<DescriptorPublicKey>,
+) -> Result<Descriptor<DescriptorPublicKey>, Error> {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| Regex::new(r"/(\d+)/\*").unwrap());
+    let receive = without_checksum(receive);
+    let change = without_checksum(change);
+
+    if re.replace_all(&receive, "/*") != re.replace_all(&change, "/*") {
+        return Err(Error::ParsingError(
+            "Receive and change descriptors does not match!".to_string(),
+        ));
+    }
+    let change_steps: Vec<String> = re
+        .captures_iter(&change)
+        .map(|caps| caps[1].to_string())
+        .collect();
+    if change_steps.is_empty() {
+        return Err(Error::ParsingError(
+            "Descriptors do not contain ranged keys!".to_string(),
+        ));
+    }
+
+    let mut index = 0;
+    let mut same_path = false;
+    let merged = re.replace_all(&receive, |caps: &Captures| {
+        let change_step = &change_steps[index];
+        index += 1;
+        same_path |= &caps[1] == change_step;
+        format!("/<{};{}>/*", &caps[1], change_step)
+    });
+    if same_path {
+        return Err(Error::ParsingError(
+            "Receive and change descriptors must use distinct paths!".to_string(),
+        ));
+    }
+    parse(&merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DataType;
+
+    const XPUB: &str = "xpub6ASuArnXKPbfEwhqN6e3mwBcDTgzisQN1wXN9BJcM47sSikHjJf3UFHKkNAWbWMiGj7Wf5uMash7SyYq527Hqck2AxYysAA7xmALppuCkwQ";
+
+    fn wpkh(path: &str) -> String {
+        format!("wpkh([d34db33f/0'/1]{}/{}/*)", XPUB, path)
+    }
+
+    #[test]
+    fn checksum() {
+        let descriptor = with_checksum(&wpkh("0")).unwrap();
+        let (body, checksum) = descriptor.split_once('#').unwrap();
+        assert_eq!(body, wpkh("0"));
+        assert_eq!(checksum.len(), 8);
+        assert_eq!(parse(&descriptor).unwrap(), parse(&wpkh("0")).unwrap());
+        assert_eq!(with_checksum(&descriptor).unwrap(), descriptor);
+
+        let tampered = format!("{}#{}", body, checksum.chars().rev().collect::<String>());
+        assert!(parse(&tampered).is_err());
+        assert!(parse("wpkh(not a key)").is_err());
+    }
+
+    #[test]
+    fn multipath_roundtrip() {
+        let multipath = parse(&wpkh("<0;1>")).unwrap();
+        let (receive, change) = single_paths(&multipath).unwrap();
+        assert_eq!(receive, parse(&wpkh("0")).unwrap());
+        assert_eq!(change, parse(&wpkh("1")).unwrap());
+        assert_eq!(receive_descriptor(&multipath).unwrap(), receive);
+        assert_eq!(change_descriptor(&multipath).unwrap(), change);
+        assert_eq!(receive_descriptor(&receive).unwrap(), receive);
+        assert!(change_descriptor(&receive).is_err());
+        assert_eq!(from_single_paths(&receive, &change).unwrap(), multipath);
+    }
+
+    #[test]
+    fn receive_change_pair() {
+        let pair = format!(
+            "{}\n{}\n",
+            to_string(&parse(&wpkh("0")).unwrap()),
+            wpkh("1")
+        );
+        assert_eq!(
+            parse_descriptors(&pair).unwrap(),
+            parse(&wpkh("<0;1>")).unwrap()
+        );
+        assert_eq!(
+            parse_descriptors(&wpkh("<0;1>")).unwrap(),
+            parse(&wpkh("<0;1>")).unwrap()
+        );
+        assert!(parse_descriptors("").is_err());
+        assert!(
+            parse_descriptors(&format!("{}\n{}\n{}", wpkh("0"), wpkh("1"), wpkh("2"))).is_err()
+        );
+    }
+
+    #[test]
+    fn mismatched_pair() {
+        let receive = parse(&wpkh("0")).unwrap();
+        assert!(from_single_paths(&receive, &receive).is_err());
+        let other = parse(&format!("sh({})", wpkh("1"))).unwrap();
+        assert!(from_single_paths(&receive, &other).is_err());
+        let single = parse(&format!("wpkh({})", XPUB)).unwrap();
+        assert!(from_single_paths(&single, &single).is_err());
+    }
+
+    #[test]
+    fn merge_decoded_descriptors() {
+        let receive = DataType::Descriptor(Some(parse(&wpkh("0")).unwrap()));
+        let change = DataType::Descriptor(Some(parse(&wpkh("1")).unwrap()));
+        assert_eq!(
+            DataType::merge_descriptors(&receive, &change).unwrap(),
+            DataType::Descriptor(Some(parse(&wpkh("<0;1>")).unwrap()))
+        );
+        assert!(DataType::merge_descriptors(&receive, &DataType::Descriptor(None)).is_err());
+        assert!(DataType::merge_descriptors(&receive, &DataType::NoType(None)).is_err());
+    }
+
+    #[test]
+    fn error_source() {
+        use std::error::Error as _;
+        let err = parse("wpkh(not a key)").unwrap_err();
+        assert!(matches!(err, Error::DescriptorError { .. }));
+        assert!(err
+            .source()
+            .unwrap()
+            .downcast_ref::<miniscript::Error>()
+            .is_some());
+    }
+}
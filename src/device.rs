@@ -0,0 +1,580 @@
+//! Signing device profiles: the format and frame density each device scans
+//! reliably, and wallet registration exports in the shape it expects
+
+use crate::coldcard::MultisigConfig;
+use crate::qr::{QRData, QRMode};
+use crate::{bcr, descriptor, DataType, Encoding, Error, OutputType, QREncoder};
+use miniscript::descriptor::{ShInner, WshInner};
+use miniscript::{Descriptor, DescriptorPublicKey};
+
+/// A signing device
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Device {
+    ColdcardQ,
+    /// No camera, nothing can be sent to it over QR
+    ColdcardMk4,
+    SeedSigner,
+    Jade,
+    Keystone,
+    Passport,
+    SpecterDiy,
+    Krux,
+}
+
+/// Encoding defaults of a signing device
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DeviceProfile {
+    pub device: Device,
+    /// Max frame length scanned reliably, None for a single QRCode: characters
+    /// for Specter, fragment bytes for UR
+    pub max_len: Option<usize>,
+    /// Max QRCode version scanned reliably (error correction level L)
+    pub max_version: u8,
+}
+
+impl DeviceProfile {
+    /// Profiles of all the supported devices
+    pub fn all() -> Vec<DeviceProfile> {
+        [
+            Device::ColdcardQ,
+            Device::ColdcardMk4,
+            Device::SeedSigner,
+            Device::Jade,
+            Device::Keystone,
+            Device::Passport,
+            Device::SpecterDiy,
+            Device::Krux,
+        ]
+        .into_iter()
+        .map(DeviceProfile::get)
+        .collect()
+    }
+
+    pub fn get(device: Device) -> DeviceProfile {
+        let (max_len, max_version) = match device {
+            Device::ColdcardQ => (Some(500), 25),
+            Device::ColdcardMk4 => (None, 0),
+            Device::SeedSigner => (Some(95), 10),
+            Device::Jade => (Some(140), 12),
+            Device::Keystone => (Some(300), 18),
+            Device::Passport => (Some(180), 14),
+            Device::SpecterDiy => (Some(300), 16),
+            Device::Krux => (Some(95), 10),
+        };
+        DeviceProfile {
+            device,
+            max_len,
+            max_version,
+        }
+    }
+
+    /// Characters a QRCode of `max_version` holds in `mode` (error correction
+    /// level L), None if the device has no camera
+    pub fn capacity(&self, mode: QRMode) -> Option<usize> {
+        let capacity = match mode {
+            QRMode::Numeric => &NUMERIC_CAPACITY,
+            QRMode::Alphanumeric => &ALPHANUMERIC_CAPACITY,
+            QRMode::Byte => &BYTE_CAPACITY,
+        };
+        match self.max_version {
+            0 => None,
+            version => capacity.get(version as usize - 1).copied(),
+        }
+    }
+
+    /// Max frame length for `encoding`, error if the frames it gives do not
+    /// fit the QRCode version the device scans
+    pub fn frame_len(
+        &self,
+        encoding: Encoding,
+        max_len: Option<usize>,
+    ) -> Result<Option<usize>, Error> {
+        // Specter & UR frames are lowercase text, sent in byte mode
+        match (max_len.or(self.max_len), self.capacity(QRMode::Byte)) {
+            (Some(len), Some(capacity)) if encoded_len(encoding, len) > capacity => {
+                Err(Error::EncodingError(format!(
+                    "{:?} cannot scan {:?} frames of {} bytes!",
+                    self.device,
+                    encoding,
+                    encoded_len(encoding, len)
+                )))
+            }
+            (max_len, _) => Ok(max_len),
+        }
+    }
+
+    /// Error if `data` sent as a single QRCode is too large for the device
+    pub fn check_single(&self, data: &[u8]) -> Result<(), Error> {
+        match self.capacity(mode(data)) {
+            Some(capacity) if data.len() <= capacity => Ok(()),
+            _ => Err(Error::EncodingError(format!(
+                "{:?} cannot scan a QRCode of {} characters!",
+                self.device,
+                data.len()
+            ))),
+        }
+    }
+
+    /// Encoding and output type the device expects for `data`, None if the
+    /// device cannot receive it over QR
+    pub fn format(&self, data: &DataType) -> Option<(Encoding, OutputType)> {
+        use Device::*;
+        if self.device == ColdcardMk4 {
+            return None;
+        }
+        // devices animating UR for every payload
+        let ur = matches!(self.device, Jade | Keystone | Passport);
+        match data {
+            DataType::Psbt(_) | DataType::PsbtV2(_) => match self.device {
+                SpecterDiy => Some((Encoding::Specter, OutputType::SpecterPsbt)),
+                _ => Some((Encoding::Ur, OutputType::UrPsbt)),
+            },
+            DataType::Descriptor(_) | DataType::LianaDescriptor(_) => match self.device {
+                ColdcardQ => Some((Encoding::Raw, OutputType::Descriptor)),
+                // Coldcard multisig file in UR bytes
+                Passport => Some((Encoding::Ur, OutputType::UrBytes)),
+                SpecterDiy | SeedSigner => Some((Encoding::Specter, OutputType::SpecterDescriptor)),
+                // crypto-output has no miniscript (e.g. Liana policies), those
+                // are sent as the descriptor text in UR bytes
+                _ => match data {
+                    DataType::Descriptor(Some(descriptor)) if !crypto_output(descriptor) => {
+                        Some((Encoding::Ur, OutputType::UrBytes))
+                    }
+                    DataType::LianaDescriptor(_) => Some((Encoding::Ur, OutputType::UrBytes)),
+                    _ => Some((Encoding::Ur, OutputType::UrDescriptor)),
+                },
+            },
+            DataType::Xpub(_) | DataType::Keys(_) if ur => Some((Encoding::Ur, OutputType::UrXpub)),
+            DataType::Xpub(_) => Some((Encoding::Raw, OutputType::Xpub)),
+            DataType::Keys(_) => Some((Encoding::Raw, OutputType::SimpleQR(QRData::new()))),
+            // no UR type for a bare xpriv
+            DataType::Xpriv(_) => Some((Encoding::Raw, OutputType::Xpriv)),
+            DataType::Mnemonic(_) if ur => Some((Encoding::Ur, OutputType::UrMnemonic)),
+            DataType::Mnemonic(_) => Some((Encoding::Raw, OutputType::Mnemonic)),
+            DataType::PrivateKey(_) if ur => Some((Encoding::Ur, OutputType::UrPrivateKey)),
+            DataType::PrivateKey(_) => Some((Encoding::Raw, OutputType::PrivateKey)),
+            DataType::Transaction(_) if ur => Some((Encoding::Ur, OutputType::UrBytes)),
+            DataType::Transaction(_) => Some((Encoding::Raw, OutputType::Transaction)),
+            DataType::NoType(_) if ur => Some((Encoding::Ur, OutputType::UrBytes)),
+            DataType::NoType(_) => Some((Encoding::Raw, OutputType::SimpleQR(QRData::new()))),
+            DataType::Address() => None,
+        }
+    }
+}
+
+/// "p1000of1000 " header, `DecodeLimits` caps a sequence at 1000 parts
+const SPECTER_HEADER: usize = 12;
+/// "ur:crypto-account/1000-1000/" path, the longest UR type
+const UR_PATH: usize = 28;
+/// CBOR fragment header [seqNum, seqLen, messageLen, checksum, data] and CRC32
+const UR_OVERHEAD: usize = 24;
+
+/// Length of the frames `encoding` gives for a max frame length of `max_len`
+fn encoded_len(encoding: Encoding, max_len: usize) -> usize {
+    match encoding {
+        Encoding::Specter => SPECTER_HEADER + max_len,
+        // minimal bytewords, 2 characters per byte
+        Encoding::Ur => UR_PATH + 2 * (UR_OVERHEAD + max_len),
+        Encoding::Raw | Encoding::NotSelected => max_len,
+    }
+}
+
+/// Most compact QRCode mode encoding `data`
+fn mode(data: &[u8]) -> QRMode {
+    if data.iter().all(|b| b.is_ascii_digit()) {
+        QRMode::Numeric
+    } else if data
+        .iter()
+        .all(|b| b.is_ascii_digit() || b.is_ascii_uppercase() || b" $%*+-./:".contains(b))
+    {
+        QRMode::Alphanumeric
+    } else {
+        QRMode::Byte
+    }
+}
+
+/// Numeric mode capacity of QRCode versions 1 to 40, error correction level L
+const NUMERIC_CAPACITY: [usize; 40] = [
+    41, 77, 127, 187, 255, 322, 370, 461, 552, 652, 772, 883, 1022, 1101, 1250, 1408, 1548, 1725,
+    1903, 2061, 2232, 2409, 2620, 2812, 3057, 3283, 3517, 3669, 3909, 4158, 4417, 4686, 4965, 5253,
+    5529, 5836, 6153, 6479, 6743, 7089,
+];
+
+/// Alphanumeric mode capacity of QRCode versions 1 to 40, error correction
+/// level L
+const ALPHANUMERIC_CAPACITY: [usize; 40] = [
+    25, 47, 77, 114, 154, 195, 224, 279, 335, 395, 468, 535, 619, 667, 758, 854, 938, 1046, 1153,
+    1249, 1352, 1460, 1588, 1704, 1853, 1990, 2132, 2223, 2369, 2520, 2677, 2840, 3009, 3183, 3351,
+    3537, 3729, 3927, 4087, 4296,
+];
+
+/// Byte mode capacity of QRCode versions 1 to 40, error correction level L
+const BYTE_CAPACITY: [usize; 40] = [
+    17, 32, 53, 78, 106, 134, 154, 192, 230, 271, 321, 367, 425, 458, 520, 586, 644, 718, 792, 858,
+    929, 1003, 1091, 1171, 1273, 1367, 1465, 1528, 1628, 1732, 1840, 1952, 2068, 2188, 2303, 2431,
+    2563, 2699, 2809, 2953,
+];
+
+impl QREncoder {
+    /// Encode `data` in the format the device of `profile` expects, a multipath
+    /// descriptor sent as UR gives two encoders: receive then change. `name` is
+    /// the wallet name of a descriptor registration
+    pub fn from_profile(
+        profile: &DeviceProfile,
+        data: &DataType,
+        name: &str,
+    ) -> Result<Vec<Box<QREncoder>>, Error> {
+        match data {
+            DataType::LianaDescriptor(Some(descriptor)) => {
+                registration(descriptor.as_descriptor_public_key(), name, profile)
+            }
+            DataType::Descriptor(Some(descriptor)) => registration(descriptor, name, profile),
+            _ => {
+                let (encoding, _) = profile.format(data).ok_or_else(|| unsupported(profile))?;
+                if encoding == Encoding::Raw {
+                    profile.check_single(data.to_text()?.as_bytes())?;
+                }
+                let max_len = profile.frame_len(encoding, None)?;
+                Ok(vec![QREncoder::from_data(data, encoding, max_len)?])
+            }
+        }
+    }
+}
+
+fn unsupported(profile: &DeviceProfile) -> Error {
+    Error::EncodingError(format!("{:?} cannot receive this data!", profile.device))
+}
+
+fn is_sortedmulti(descriptor: &Descriptor<DescriptorPublicKey>) -> bool {
+    match descriptor {
+        Descriptor::Wsh(wsh) => matches!(wsh.as_inner(), WshInner::SortedMulti(_)),
+        Descriptor::Sh(sh) => match sh.as_inner() {
+            ShInner::SortedMulti(_) => true,
+            ShInner::Wsh(wsh) => matches!(wsh.as_inner(), WshInner::SortedMulti(_)),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Whether crypto-output can express the receive & change descriptors, it
+/// only holds single key and multisig scripts
+fn crypto_output(descriptor: &Descriptor<DescriptorPublicKey>) -> bool {
+    match descriptor::single_paths(descriptor) {
+        Ok((receive, change)) => [receive, change]
+            .iter()
+            .all(|single| bcr::descriptor_to_output(single).is_ok()),
+        Err(_) => bcr::descriptor_to_output(descriptor).is_ok(),
+    }
+}
+
+/// Coldcard multisig file for sortedmulti wallets, plain descriptor otherwise
+fn coldcard_payload(
+    descriptor: &Descriptor<DescriptorPublicKey>,
+    name: &str,
+) -> Result<String, Error> {
+    if is_sortedmulti(descriptor) {
+        Ok(MultisigConfig::from_descriptor(descriptor, name)?.to_string())
+    } else {
+        Ok(descriptor::to_string(descriptor))
+    }
+}
+
+/// Export a wallet descriptor for registration on the device of `profile`, a
+/// Liana descriptor is passed as `LianaDescriptor::as_descriptor_public_key()`
+pub fn registration(
+    descriptor: &Descriptor<DescriptorPublicKey>,
+    name: &str,
+    profile: &DeviceProfile,
+) -> Result<Vec<Box<QREncoder>>, Error> {
+    let data = DataType::Descriptor(Some(descriptor.clone()));
+    let (encoding, output_type) = profile.format(&data).ok_or_else(|| unsupported(profile))?;
+    let max_len = profile.frame_len(encoding, None)?;
+    let payload = match (encoding, output_type) {
+        (Encoding::Specter, _) if profile.device == Device::SpecterDiy => {
+            format!("addwallet {}&{}", name, descriptor::to_string(descriptor))
+        }
+        (Encoding::Raw, _) | (Encoding::Ur, OutputType::UrBytes) => {
+            coldcard_payload(descriptor, name)?
+        }
+        (Encoding::Ur, _) if descriptor.is_multipath() => {
+            let (receive, change) = descriptor::single_paths(descriptor)?;
+            return [receive, change]
+                .into_iter()
+                .map(|single| {
+                    QREncoder::from_data(&DataType::Descriptor(Some(single)), encoding, max_len)
+                })
+                .collect();
+        }
+        (Encoding::Ur, _) => {
+            return Ok(vec![QREncoder::from_data(&data, encoding, max_len)?]);
+        }
+        _ => descriptor::to_string(descriptor),
+    };
+    if encoding == Encoding::Raw {
+        profile.check_single(payload.as_bytes())?;
+    }
+    let data = DataType::NoType(Some(payload));
+    Ok(vec![QREncoder::from_data(&data, encoding, max_len)?])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::{QRDecoder, Registry};
+    use crate::Encode;
+    use liana::descriptors::{LianaDescriptor, LianaPolicy, PathInfo};
+    use std::collections::BTreeMap;
+    use std::str::FromStr;
+
+    const MASTER: &str = "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8";
+    const CHILD: &str = "xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw";
+    const XPUB: &str = "xpub6ASuArnXKPbfEwhqN6e3mwBcDTgzisQN1wXN9BJcM47sSikHjJf3UFHKkNAWbWMiGj7Wf5uMash7SyYq527Hqck2AxYysAA7xmALppuCkwQ";
+
+    fn sortedmulti() -> Descriptor<DescriptorPublicKey> {
+        descriptor::parse(&format!(
+            "wsh(sortedmulti(2,[0f056943/48'/0'/0'/2']{}/<0;1>/*,[5c1bd648/48'/0'/0'/2']{}/<0;1>/*,[bef5a2f9/48'/0'/1'/2']{}/<0;1>/*))",
+            MASTER, CHILD, XPUB
+        ))
+        .unwrap()
+    }
+
+    fn single_sig(path: &str) -> Descriptor<DescriptorPublicKey> {
+        descriptor::parse(&format!("wpkh([bef5a2f9/84'/0'/0']{}/{}/*)", XPUB, path)).unwrap()
+    }
+
+    /// 2-of-2 primary path, a single key recovery path after a year
+    fn liana() -> LianaDescriptor {
+        let key = |xpub: &str| DescriptorPublicKey::from_str(&format!("{}/<0;1>/*", xpub)).unwrap();
+        let primary = PathInfo::Multi(2, vec![key(MASTER), key(CHILD)]);
+        let recovery = BTreeMap::from([(52_596, PathInfo::Single(key(XPUB)))]);
+        LianaDescriptor::new(LianaPolicy::new(primary, recovery).unwrap())
+    }
+
+    /// Scan the frames of `encoder` until the decoder completes
+    fn scan(encoder: &mut QREncoder) -> DataType {
+        let mut decoder = QRDecoder::new(Registry::default());
+        for _ in 0..100 {
+            decoder.receive(&encoder.next().unwrap()).unwrap();
+            if decoder.is_complete() {
+                break;
+            }
+        }
+        decoder.result().unwrap()
+    }
+
+    fn export(descriptor: &Descriptor<DescriptorPublicKey>, device: Device) -> Vec<DataType> {
+        registration(descriptor, "Vault", &DeviceProfile::get(device))
+            .unwrap()
+            .iter_mut()
+            .map(|encoder| scan(encoder))
+            .collect()
+    }
+
+    #[test]
+    fn specter_addwallet() {
+        let descriptor = sortedmulti();
+        assert_eq!(
+            export(&descriptor, Device::SpecterDiy),
+            vec![DataType::NoType(Some(format!(
+                "addwallet Vault&{}",
+                descriptor::to_string(&descriptor)
+            )))]
+        );
+    }
+
+    #[test]
+    fn coldcard_multisig_file() {
+        let descriptor = sortedmulti();
+        let encoders =
+            registration(&descriptor, "Vault", &DeviceProfile::get(Device::ColdcardQ)).unwrap();
+        assert_eq!(encoders.len(), 1);
+        assert!(matches!(encoders[0].output(), OutputType::SimpleQR(_)));
+        let expected = MultisigConfig::from_descriptor(&descriptor, "Vault")
+            .unwrap()
+            .to_string();
+        assert_eq!(encoders[0].data.text(), Some(expected.as_str()));
+
+        let decoded = vec![DataType::Descriptor(Some(descriptor.clone()))];
+        assert_eq!(export(&descriptor, Device::ColdcardQ), decoded);
+        // Passport takes the same file in UR bytes
+        assert_eq!(export(&descriptor, Device::Passport), decoded);
+    }
+
+    #[test]
+    fn plain_descriptor() {
+        let descriptor = single_sig("<0;1>");
+        let decoded = vec![DataType::Descriptor(Some(descriptor.clone()))];
+        // not a sortedmulti wallet, Coldcard imports the descriptor itself
+        assert_eq!(export(&descriptor, Device::ColdcardQ), decoded);
+        assert_eq!(export(&descriptor, Device::SeedSigner), decoded);
+    }
+
+    #[test]
+    fn ur_receive_change() {
+        let descriptor = single_sig("<0;1>");
+        for device in [Device::Jade, Device::Keystone, Device::Krux] {
+            let exported = export(&descriptor, device);
+            assert_eq!(
+                exported,
+                vec![
+                    DataType::Descriptor(Some(single_sig("0"))),
+                    DataType::Descriptor(Some(single_sig("1"))),
+                ]
+            );
+            assert_eq!(
+                DataType::merge_descriptors(&exported[0], &exported[1]).unwrap(),
+                DataType::Descriptor(Some(descriptor.clone()))
+            );
+        }
+        let single = single_sig("0");
+        assert_eq!(
+            export(&single, Device::Jade),
+            vec![DataType::Descriptor(Some(single.clone()))]
+        );
+    }
+
+    #[test]
+    fn liana_ur_bytes() {
+        let data = DataType::LianaDescriptor(Some(liana()));
+        for device in [Device::Jade, Device::Keystone] {
+            let profile = DeviceProfile::get(device);
+            let mut encoders = QREncoder::from_profile(&profile, &data, "Vault").unwrap();
+            assert_eq!(encoders.len(), 1);
+            assert!(matches!(encoders[0].output(), OutputType::UrBytes));
+            assert_eq!(scan(&mut encoders[0]), data);
+        }
+    }
+
+    #[test]
+    fn no_camera() {
+        let profile = DeviceProfile::get(Device::ColdcardMk4);
+        assert!(registration(&sortedmulti(), "Vault", &profile).is_err());
+    }
+
+    #[test]
+    fn format_every_type() {
+        let data = [
+            DataType::Psbt(None),
+            DataType::PsbtV2(None),
+            DataType::Xpub(None),
+            DataType::Xpriv(None),
+            DataType::Keys(None),
+            DataType::Mnemonic(None),
+            DataType::PrivateKey(None),
+            DataType::Transaction(None),
+            DataType::NoType(None),
+        ];
+        for profile in DeviceProfile::all() {
+            for data in &data {
+                match profile.format(data) {
+                    // the builder encodes with the same output type
+                    Some((encoding, output_type)) => {
+                        assert_eq!(QREncoder::output_type(data, encoding).unwrap(), output_type)
+                    }
+                    None => assert_eq!(profile.device, Device::ColdcardMk4),
+                }
+            }
+            assert_eq!(profile.format(&DataType::Address()), None);
+        }
+
+        // registrations, UR encoders use the output type of the profile
+        let descriptors = [
+            DataType::Descriptor(None),
+            DataType::LianaDescriptor(None),
+            DataType::Descriptor(Some(sortedmulti())),
+            DataType::Descriptor(Some(single_sig("<0;1>"))),
+            DataType::LianaDescriptor(Some(liana())),
+        ];
+        for profile in DeviceProfile::all() {
+            for data in &descriptors {
+                match profile.format(data) {
+                    Some((encoding, output_type))
+                        if matches!(
+                            data,
+                            DataType::Descriptor(Some(_)) | DataType::LianaDescriptor(Some(_))
+                        ) =>
+                    {
+                        let encoders = QREncoder::from_profile(&profile, data, "Vault").unwrap();
+                        if encoding == Encoding::Ur {
+                            for encoder in encoders {
+                                assert_eq!(*encoder.output(), output_type);
+                            }
+                        }
+                    }
+                    Some(_) => {}
+                    None => assert_eq!(profile.device, Device::ColdcardMk4),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn qr_version() {
+        // the default frame length fits every encoding the device receives
+        for profile in DeviceProfile::all() {
+            for data in [
+                DataType::Psbt(None),
+                DataType::Descriptor(None),
+                DataType::Xpub(None),
+                DataType::NoType(None),
+            ] {
+                if let Some((encoding, _)) = profile.format(&data) {
+                    assert_eq!(profile.frame_len(encoding, None).unwrap(), profile.max_len);
+                }
+            }
+        }
+        let seedsigner = DeviceProfile::get(Device::SeedSigner);
+        assert_eq!(seedsigner.capacity(QRMode::Byte), Some(271));
+        // bytewords of the fragment, 2 characters per byte
+        assert_eq!(
+            seedsigner.frame_len(Encoding::Ur, Some(97)).unwrap(),
+            Some(97)
+        );
+        assert!(seedsigner.frame_len(Encoding::Ur, Some(98)).is_err());
+        assert_eq!(
+            seedsigner.frame_len(Encoding::Specter, Some(259)).unwrap(),
+            Some(259)
+        );
+        assert!(seedsigner.frame_len(Encoding::Specter, Some(260)).is_err());
+        assert!(DeviceProfile::get(Device::ColdcardMk4)
+            .check_single(b"1")
+            .is_err());
+
+        // actual UR frames fit
+        let jade = DeviceProfile::get(Device::Jade);
+        let text = |len| DataType::NoType(Some("a".repeat(len)));
+        let mut encoders = QREncoder::from_profile(&jade, &text(2000), "").unwrap();
+        for _ in 0..50 {
+            let frame = encoders[0].next().unwrap();
+            assert!(frame.len() <= jade.capacity(QRMode::Byte).unwrap());
+        }
+
+        // single QRCodes in the most compact mode
+        let coldcard = DeviceProfile::get(Device::ColdcardQ);
+        for (c, capacity) in [('a', 1273), ('A', 1853), ('1', 3057)] {
+            let data = |len| DataType::NoType(Some(c.to_string().repeat(len)));
+            assert!(QREncoder::from_profile(&coldcard, &data(capacity), "").is_ok());
+            assert!(QREncoder::from_profile(&coldcard, &data(capacity + 1), "").is_err());
+        }
+    }
+
+    #[test]
+    fn profile_wallet_name() {
+        let descriptor = sortedmulti();
+        let data = DataType::Descriptor(Some(descriptor.clone()));
+        let profile = DeviceProfile::get(Device::SpecterDiy);
+        let mut encoders = QREncoder::from_profile(&profile, &data, "Family").unwrap();
+        assert_eq!(
+            scan(&mut encoders[0]),
+            DataType::NoType(Some(format!(
+                "addwallet Family&{}",
+                descriptor::to_string(&descriptor)
+            )))
+        );
+        let profile = DeviceProfile::get(Device::ColdcardQ);
+        let encoders = QREncoder::from_profile(&profile, &data, "Family").unwrap();
+        let config = MultisigConfig::parse(encoders[0].data.text().unwrap()).unwrap();
+        assert_eq!(config.name, "Family");
+    }
+}
@@ -0,0 +1,56 @@
+use crate::Error;
+
+/// Encode bytes as lowercase hex
+pub fn encode(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decode a hex string (case insensitive)
+pub fn decode(data: &str) -> Result<Vec<u8>, Error> {
+    let data = data.trim();
+    if data.len() % 2 != 0 || !data.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(Error::ParsingError("Invalid hex string!".to_string()));
+    }
+    (0..data.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&data[i..i + 2], 16)
+                .map_err(|_| Error::ParsingError("Invalid hex string!".to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rfc4648_vectors() {
+        for (data, expected) in [
+            ("", ""),
+            ("f", "66"),
+            ("fo", "666f"),
+            ("foo", "666f6f"),
+            ("foob", "666f6f62"),
+            ("fooba", "666f6f6261"),
+            ("foobar", "666f6f626172"),
+        ] {
+            assert_eq!(encode(data.as_bytes()), expected);
+            assert_eq!(decode(expected).unwrap(), data.as_bytes());
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let data: Vec<u8> = (0..=255).collect();
+        assert_eq!(decode(&encode(&data)).unwrap(), data);
+        assert_eq!(decode(" 00FFaB\n").unwrap(), vec![0x00, 0xff, 0xab]);
+    }
+
+    #[test]
+    fn invalid_hex() {
+        for data in ["0", "abc", "zz", "0x00", "é0", "+1"] {
+            assert!(decode(data).is_err(), "{}", data);
+        }
+    }
+}
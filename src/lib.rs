@@ -2,17 +2,44 @@
 
 extern crate bitcoin;
 
+pub mod base43;
+pub mod bcr;
+pub mod bsms;
+pub mod builder;
+pub mod cbor;
+pub mod coldcard;
+pub mod combiner;
+pub mod descriptor;
+pub mod device;
+pub mod hex;
+pub mod minimiser;
+pub mod mnemonic;
+pub mod policy;
+pub mod psbt;
 pub mod qr;
+pub mod registry;
+pub mod seedqr;
 pub mod specter;
+pub mod summary;
+pub mod transaction;
 pub mod ur;
 
+use crate::builder::EncoderBuilder;
+use crate::minimiser::{MinimiserOptions, Savings};
+use crate::psbt::{PsbtEncoding, PsbtVersion};
 use crate::qr::*;
 use crate::specter::SpecterQR;
+use crate::transaction::TxEncoding;
+use crate::ur::UrData;
 use crate::Error::ParsingError;
 use crate::OutputType::*;
+use bip39::Mnemonic;
 use bitcoin::bip32::{ExtendedPrivKey as XPriv, ExtendedPubKey as XPub};
 use bitcoin::psbt::PartiallySignedTransaction as Psbt;
+use bitcoin::{Network, PrivateKey, Transaction};
 use liana::descriptors::LianaDescriptor;
+use miniscript::{Descriptor, DescriptorPublicKey};
+use std::fmt;
 use std::str::FromStr;
 
 #[derive(Debug)]
@@ -21,14 +48,202 @@ pub enum Error {
     DecodingError(String),
     ParsingError(String),
     NotImplementedError(String),
+    /// malformed MultiQR header or UR fragment path
+    InvalidHeader(String),
+    /// chunk index (1 based) outside of the sequence
+    IndexOutOfRange {
+        index: usize,
+        total: usize,
+    },
+    /// a frame belongs to a sequence of another length than the one scanned
+    TotalMismatch {
+        expected: usize,
+        found: usize,
+    },
+    /// a chunk (1 based index) received twice with different content
+    ConflictingDuplicate {
+        index: usize,
+    },
+    /// a UR fragment belongs to a message of another checksum than the one
+    /// scanned, or a descriptor template checksum does not match
+    ChecksumMismatch {
+        expected: String,
+        found: String,
+    },
+    /// unknown UR type, or a type this operation cannot handle
+    UnsupportedUrType(String),
+    /// invalid CBOR payload, `source` is the error of the underlying conversion
+    CborError {
+        reason: String,
+        source: Option<Box<dyn std::error::Error + Send + Sync>>,
+    },
+    /// invalid UR encoding, `source` is the error of the `ur` crate
+    UrError {
+        reason: String,
+        source: Option<Box<dyn std::error::Error + Send + Sync>>,
+    },
+    /// invalid descriptor or key expression, `source` is the miniscript error
+    DescriptorError {
+        reason: String,
+        source: Option<Box<dyn std::error::Error + Send + Sync>>,
+    },
+    /// invalid BIP39 mnemonic, `source` is the bip39 error
+    MnemonicError {
+        reason: String,
+        source: Option<Box<dyn std::error::Error + Send + Sync>>,
+    },
+    /// invalid PSBT, `source` is the error of the underlying parser
+    PsbtError {
+        reason: String,
+        source: Option<Box<dyn std::error::Error + Send + Sync>>,
+    },
+    /// a key or address is not for the expected network
+    NetworkMismatch {
+        expected: Network,
+        found: Network,
+    },
+    /// a resource cap of `DecodeLimits` was hit
+    LimitExceeded {
+        limit: Limit,
+        value: usize,
+        max: usize,
+    },
 }
 
-/// Struct holding a chunk of MultiQR
-#[derive(Debug, Clone, PartialEq)]
-pub struct MultiQRElement {
-    data: String,
-    index: usize,
-    total: usize,
+impl Error {
+    pub(crate) fn psbt(reason: &str) -> Error {
+        Error::PsbtError {
+            reason: reason.to_string(),
+            source: None,
+        }
+    }
+
+    pub(crate) fn psbt_with<E>(reason: &str, source: E) -> Error
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        Error::PsbtError {
+            reason: reason.to_string(),
+            source: Some(Box::new(source)),
+        }
+    }
+
+    pub(crate) fn cbor(reason: &str) -> Error {
+        Error::CborError {
+            reason: reason.to_string(),
+            source: None,
+        }
+    }
+
+    pub(crate) fn cbor_with<E>(reason: &str, source: E) -> Error
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        Error::CborError {
+            reason: reason.to_string(),
+            source: Some(Box::new(source)),
+        }
+    }
+
+    pub(crate) fn ur_with<E>(reason: &str, source: E) -> Error
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        Error::UrError {
+            reason: reason.to_string(),
+            source: Some(Box::new(source)),
+        }
+    }
+
+    pub(crate) fn descriptor_with<E>(reason: &str, source: E) -> Error
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        Error::DescriptorError {
+            reason: reason.to_string(),
+            source: Some(Box::new(source)),
+        }
+    }
+
+    pub(crate) fn mnemonic_with<E>(reason: &str, source: E) -> Error
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        Error::MnemonicError {
+            reason: reason.to_string(),
+            source: Some(Box::new(source)),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::EncodingError(e) => write!(f, "Encoding error: {}", e),
+            Error::DecodingError(e) => write!(f, "Decoding error: {}", e),
+            Error::ParsingError(e) => write!(f, "Parsing error: {}", e),
+            Error::NotImplementedError(e) => write!(f, "Not implemented: {}", e),
+            Error::InvalidHeader(e) => write!(f, "Invalid header: {}", e),
+            Error::IndexOutOfRange { index, total } => {
+                write!(
+                    f,
+                    "Part {} out of range, sequence has {} parts",
+                    index, total
+                )
+            }
+            Error::TotalMismatch { expected, found } => write!(
+                f,
+                "Sequence of {} parts while scanning a sequence of {} parts",
+                found, expected
+            ),
+            Error::ConflictingDuplicate { index } => {
+                write!(f, "Part {} received twice with different content", index)
+            }
+            Error::ChecksumMismatch { expected, found } => {
+                write!(f, "Checksum {} while expecting {}", found, expected)
+            }
+            Error::UnsupportedUrType(t) => write!(f, "Unsupported UR type: {}", t),
+            Error::CborError { reason, .. } => write!(f, "Invalid CBOR: {}", reason),
+            Error::UrError { reason, .. } => write!(f, "Invalid UR: {}", reason),
+            Error::DescriptorError { reason, .. } => write!(f, "Invalid descriptor: {}", reason),
+            Error::MnemonicError { reason, .. } => write!(f, "Invalid mnemonic: {}", reason),
+            Error::PsbtError { reason, .. } => write!(f, "Invalid PSBT: {}", reason),
+            Error::NetworkMismatch { expected, found } => {
+                write!(f, "Expected network {}, found {}", expected, found)
+            }
+            Error::LimitExceeded { limit, value, max } => {
+                write!(f, "{} is {}, limit is {}", limit, value, max)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::PsbtError {
+                source: Some(source),
+                ..
+            }
+            | Error::CborError {
+                source: Some(source),
+                ..
+            }
+            | Error::UrError {
+                source: Some(source),
+                ..
+            }
+            | Error::DescriptorError {
+                source: Some(source),
+                ..
+            }
+            | Error::MnemonicError {
+                source: Some(source),
+                ..
+            } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
 }
 
 /// Data Type
@@ -36,14 +251,181 @@ pub struct MultiQRElement {
 pub enum DataType {
     Address(),
     Psbt(Option<Psbt>),
+    /// PSBT received as version 2 (BIP370), held converted to v0
+    PsbtV2(Option<Psbt>),
     Xpub(Option<XPub>),
     Xpriv(Option<XPriv>),
     LianaDescriptor(Option<LianaDescriptor>),
-    Descriptor(),
+    Descriptor(Option<Descriptor<DescriptorPublicKey>>),
+    /// Key expressions with their origin (UR crypto-hdkey, crypto-account)
+    Keys(Option<Vec<DescriptorPublicKey>>),
+    /// BIP39 mnemonic (secret)
+    Mnemonic(Option<Mnemonic>),
+    /// Single private key, WIF encoded (secret)
+    PrivateKey(Option<PrivateKey>),
+    /// Signed transaction
+    Transaction(Option<Transaction>),
     NoType(Option<String>),
 }
 
-#[derive(Debug, Clone)]
+impl DataType {
+    /// Return true if the data is secret material that should not be logged or displayed
+    pub fn is_secret(&self) -> bool {
+        matches!(
+            self,
+            DataType::Xpriv(_) | DataType::Mnemonic(_) | DataType::PrivateKey(_)
+        )
+    }
+
+    /// Type a decoded PSBT according to its original version
+    pub fn from_psbt(psbt: Psbt, version: PsbtVersion) -> DataType {
+        match version {
+            PsbtVersion::V0 => DataType::Psbt(Some(psbt)),
+            PsbtVersion::V2 => DataType::PsbtV2(Some(psbt)),
+        }
+    }
+
+    /// Merge a receive and a change descriptor decoded separately (e.g. two UR
+    /// crypto-output) into a multipath descriptor
+    pub fn merge_descriptors(receive: &DataType, change: &DataType) -> Result<DataType, Error> {
+        match (receive, change) {
+            (DataType::Descriptor(Some(receive)), DataType::Descriptor(Some(change))) => Ok(
+                DataType::Descriptor(Some(descriptor::from_single_paths(receive, change)?)),
+            ),
+            _ => Err(ParsingError(
+                "Expected a receive and a change descriptor!".to_string(),
+            )),
+        }
+    }
+
+    /// Type a string payload (raw QRCode or reassembled MultiQR data)
+    pub fn from_string(data: &str) -> DataType {
+        let data = data.trim();
+        if let Ok(descriptor) = LianaDescriptor::from_str(data) {
+            DataType::LianaDescriptor(Some(descriptor))
+        } else if let Ok(descriptor) = descriptor::parse_descriptors(data) {
+            DataType::Descriptor(Some(descriptor))
+        } else if let Ok(descriptor) =
+            coldcard::MultisigConfig::parse(data).and_then(|config| config.to_descriptor())
+        {
+            DataType::Descriptor(Some(descriptor))
+        } else if let Ok(record) = bsms::DescriptorRecord::parse(data) {
+            DataType::Descriptor(Some(record.descriptor))
+        } else if let Ok(xpub) = XPub::from_str(data) {
+            DataType::Xpub(Some(xpub))
+        } else if let Ok(xpriv) = XPriv::from_str(data) {
+            DataType::Xpriv(Some(xpriv))
+        } else if let Ok(mnemonic) = mnemonic::parse(data) {
+            DataType::Mnemonic(Some(mnemonic))
+        } else if let Ok(mnemonic) = seedqr::decode_standard(data) {
+            // 48/96 digits are also valid hex, probe SeedQR first, the xpriv
+            // needs a network & passphrase, see seedqr::decode_seed()
+            DataType::Mnemonic(Some(mnemonic))
+        } else if let Ok(key) = PrivateKey::from_wif(data) {
+            DataType::PrivateKey(Some(key))
+        } else if let Ok(tx) = transaction::from_string(data) {
+            DataType::Transaction(Some(tx))
+        } else if let Ok((psbt, version)) = psbt::decode_versioned(data.as_bytes()) {
+            DataType::from_psbt(psbt, version)
+        } else {
+            DataType::NoType(Some(data.to_string()))
+        }
+    }
+
+    /// Parse a string as the same data type as `self`
+    pub fn parse_as(&self, data: &str) -> Result<DataType, Error> {
+        let data = data.trim();
+        match self {
+            DataType::Psbt(_) | DataType::PsbtV2(_) => {
+                let (psbt, version) = psbt::decode_versioned(data.as_bytes())?;
+                Ok(DataType::from_psbt(psbt, version))
+            }
+            DataType::Xpub(_) => XPub::from_str(data)
+                .map(|xpub| DataType::Xpub(Some(xpub)))
+                .map_err(|_| ParsingError("Cannot load this string into xpub".to_string())),
+            DataType::Xpriv(_) => XPriv::from_str(data)
+                .map(|xpriv| DataType::Xpriv(Some(xpriv)))
+                .map_err(|_| ParsingError("Cannot load this string into xpriv".to_string())),
+            DataType::LianaDescriptor(_) => LianaDescriptor::from_str(data)
+                .map(|descriptor| DataType::LianaDescriptor(Some(descriptor)))
+                .map_err(|_| {
+                    ParsingError("Cannot load this string into Liana descriptor".to_string())
+                }),
+            DataType::Descriptor(_) => Ok(DataType::Descriptor(Some(
+                descriptor::parse_descriptors(data)?,
+            ))),
+            DataType::Keys(_) => data
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| DescriptorPublicKey::from_str(line.trim()))
+                .collect::<Result<Vec<_>, _>>()
+                .map(|keys| DataType::Keys(Some(keys)))
+                .map_err(|_| ParsingError("Cannot load this string into keys".to_string())),
+            DataType::Mnemonic(_) => Ok(DataType::Mnemonic(Some(mnemonic::parse(data)?))),
+            DataType::PrivateKey(_) => PrivateKey::from_wif(data)
+                .map(|key| DataType::PrivateKey(Some(key)))
+                .map_err(|_| {
+                    ParsingError("Cannot load this string into WIF private key".to_string())
+                }),
+            DataType::Transaction(_) => {
+                Ok(DataType::Transaction(Some(transaction::from_string(data)?)))
+            }
+            DataType::NoType(_) => Ok(DataType::NoType(Some(data.to_string()))),
+            DataType::Address() => Err(Error::NotImplementedError(
+                "type not yet implemented!".to_string(),
+            )),
+        }
+    }
+
+    /// Text representation of the data, PSBTs as base64 in their original version
+    pub fn to_text(&self) -> Result<String, Error> {
+        let text = match self {
+            DataType::Psbt(Some(psbt)) => {
+                psbt::encode_version(psbt, PsbtEncoding::Base64, PsbtVersion::V0)?
+            }
+            DataType::PsbtV2(Some(psbt)) => {
+                psbt::encode_version(psbt, PsbtEncoding::Base64, PsbtVersion::V2)?
+            }
+            DataType::Xpub(Some(xpub)) => xpub.to_string().into_bytes(),
+            DataType::Xpriv(Some(xpriv)) => xpriv.to_string().into_bytes(),
+            DataType::LianaDescriptor(Some(descriptor)) => descriptor.to_string().into_bytes(),
+            DataType::Descriptor(Some(descriptor)) => {
+                descriptor::to_string(descriptor).into_bytes()
+            }
+            DataType::Keys(Some(keys)) => keys
+                .iter()
+                .map(|key| key.to_string())
+                .collect::<Vec<_>>()
+                .join("\n")
+                .into_bytes(),
+            DataType::Mnemonic(Some(mnemonic)) => mnemonic.to_string().into_bytes(),
+            DataType::PrivateKey(Some(key)) => key.to_wif().into_bytes(),
+            DataType::Transaction(Some(tx)) => transaction::encode(tx, TxEncoding::Hex),
+            DataType::NoType(Some(data)) => data.clone().into_bytes(),
+            _ => return Err(Error::EncodingError("No data to encode!".to_string())),
+        };
+        // base64 & hex are ASCII
+        Ok(String::from_utf8(text).unwrap())
+    }
+
+    /// Type a binary payload (byte mode QRCode, UR bytes)
+    ///
+    /// A compact SeedQR is raw entropy that cannot be told apart from other
+    /// bytes, it is decoded by `seedqr::decode_seed()`
+    pub fn from_bytes(data: &[u8]) -> DataType {
+        if let Ok(tx) = transaction::from_bytes(data) {
+            DataType::Transaction(Some(tx))
+        } else if let Ok((psbt, version)) = psbt::decode_versioned(data) {
+            DataType::from_psbt(psbt, version)
+        } else if let Ok(text) = std::str::from_utf8(data) {
+            DataType::from_string(text)
+        } else {
+            DataType::NoType(None)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Encoding {
     Raw,
     Specter,
@@ -66,6 +448,12 @@ pub enum OutputType {
     LianaDescriptor,
     ///Raw Bitcoin descriptor
     Descriptor,
+    /// Raw BIP39 mnemonic
+    Mnemonic,
+    /// Raw WIF private key
+    PrivateKey,
+    /// Raw signed transaction
+    Transaction,
     /// Specter animated QRCode (no data typing)
     Specter(SpecterQR),
     /// Specter animated PSBT
@@ -78,51 +466,130 @@ pub enum OutputType {
     UrBytes,
     /// UR encoded as crypto-psbt QRCode
     UrPsbt,
-    /// UR encoded as crypto-pubkeys QRCode
+    /// UR encoded as crypto-hdkey QRCode
     UrXpub,
     /// UR encoded as crypto-prvkeys QRCode
     UrXpriv,
     /// UR encoded as crypto-output QRCode
     UrDescriptor,
+    /// UR encoded as crypto-account QRCode
+    UrAccount,
     /// UR encoded as crypto-address QRCode
     UrAddress,
+    /// UR encoded as crypto-bip39 QRCode
+    UrMnemonic,
+    /// UR encoded as crypto-eckey QRCode
+    UrPrivateKey,
+    /// Format of the registry, by name
+    Custom(String),
     /// Encoding not selected
     NoType,
 }
 
-/// Trait for decoders
+/// Trait for decoders, object safe: formats are used as `Box<dyn Decode>`
+/// through the `registry`
 pub trait Decode {
     /// return the pattern(regex) used for detect each type (decoder)
-    fn pattern() -> &'static str;
+    fn pattern() -> &'static str
+    where
+        Self: Sized;
     /// return true if decoding process ended (decoder)
     fn is_complete(&self) -> bool;
+    /// set the resource caps checked on each frame received (decoder)
+    fn set_limits(&mut self, limits: DecodeLimits);
+    /// set what to do with a frame of another sequence (decoder)
+    fn set_reset_policy(&mut self, policy: ResetPolicy);
+    /// set the network of decoded keys that do not carry it (decoder)
+    fn set_network(&mut self, network: Network);
     /// load data chunk (decoder)
     fn receive(&mut self, data: &str) -> Result<bool, Error>;
     /// result of decoding
     fn result(&self) -> Result<DataType, Error>;
 }
 
-/// Trait for encoders
+/// Trait for encoders, constructors need a concrete type: a `Box<dyn Encode>`
+/// only serves frames
 pub trait Encode {
     fn max_len(&mut self) -> Option<usize>;
 
-    fn from_liana_descriptor(descriptor: &LianaDescriptor) -> Result<Box<Self>, Error>;
+    fn from_liana_descriptor(descriptor: &LianaDescriptor) -> Result<Box<Self>, Error>
+    where
+        Self: Sized;
+
+    /// encode a miniscript descriptor, checksum is appended
+    fn from_descriptor(descriptor: &Descriptor<DescriptorPublicKey>) -> Result<Box<Self>, Error>
+    where
+        Self: Sized;
+
+    /// encode a descriptor in the representation supported by the format: a
+    /// single (maybe multipath) descriptor, or one encoder per single path
+    fn from_descriptors(
+        descriptor: &Descriptor<DescriptorPublicKey>,
+    ) -> Result<Vec<Box<Self>>, Error>
+    where
+        Self: Sized,
+    {
+        Ok(vec![Self::from_descriptor(descriptor)?])
+    }
 
-    /// encode data from string (encoder)
-    fn load_string(&mut self, data: &str) -> Result<Box<Self>, Error>;
+    /// encode data from string (encoder), the output type must be set first,
+    /// `QREncoder::builder()` validates everything up front instead
+    fn load_string(&mut self, data: &str) -> Result<Box<Self>, Error>
+    where
+        Self: Sized;
 
+    /// select what `load_string()` encodes, error if the encoder does not
+    /// support the data type or the encoding
     fn set_output_type(
         &mut self,
         data_type: DataType,
         encoding: Encoding,
         max_len: Option<usize>,
-    ) -> &mut Self;
+    ) -> Result<&mut Self, Error>
+    where
+        Self: Sized;
+
+    /// encode a PSBT, stripped of the fields the signer doesn't need if
+    /// minimiser options are given
+    fn from_psbt(psbt: &Psbt, options: Option<&MinimiserOptions>) -> Result<Box<Self>, Error>
+    where
+        Self: Sized;
+
+    /// encode a PSBT stripped of the fields the signer doesn't need and report
+    /// the savings, use `minimiser::restore()` on the PSBT that comes back signed
+    fn from_psbt_minimised(
+        psbt: &Psbt,
+        options: &MinimiserOptions,
+    ) -> Result<(Box<Self>, Savings), Error>
+    where
+        Self: Sized,
+    {
+        let (minimised, savings) = minimiser::minimise(psbt, options);
+        Ok((Self::from_psbt(&minimised, None)?, savings))
+    }
+
+    fn from_xpub(xpub: &XPub) -> Result<Box<Self>, Error>
+    where
+        Self: Sized;
 
-    fn from_psbt(psbt: &Psbt) -> Result<Box<Self>, Error>;
+    fn from_xpriv(xpriv: &XPriv) -> Result<Box<Self>, Error>
+    where
+        Self: Sized;
 
-    fn from_xpub(xpub: &XPub) -> Result<Box<Self>, Error>;
+    /// encode a BIP39 mnemonic (secret)
+    fn from_mnemonic(mnemonic: &Mnemonic) -> Result<Box<Self>, Error>
+    where
+        Self: Sized;
 
-    fn from_xpriv(xpriv: &XPriv) -> Result<Box<Self>, Error>;
+    /// encode a single private key (secret)
+    fn from_private_key(key: &PrivateKey) -> Result<Box<Self>, Error>
+    where
+        Self: Sized;
+
+    /// encode a signed transaction
+    fn from_transaction(tx: &Transaction) -> Result<Box<Self>, Error>
+    where
+        Self: Sized;
 
     fn next(&mut self) -> Option<String>;
 
@@ -130,149 +597,305 @@ pub trait Encode {
     // TODO: add looping iterator?
 }
 
+/// Encoder a QREncoder delegates framing to
+enum Backend {
+    /// single QRCode, payload held in `QREncoder::data`/`QREncoder::binary`
+    Raw,
+    Specter(Box<SpecterQR>),
+    Ur(Box<UrData>),
+    /// format of the registry
+    Custom(Box<dyn Encode>),
+}
+
+impl fmt::Debug for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Backend::Raw => write!(f, "Raw"),
+            Backend::Specter(qr) => f.debug_tuple("Specter").field(qr).finish(),
+            Backend::Ur(ur) => f.debug_tuple("Ur").field(ur).finish(),
+            Backend::Custom(_) => write!(f, "Custom"),
+        }
+    }
+}
+
 /// A generic QRCode Encoder
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct QREncoder {
     encoder: OutputType,
+    encoding: Encoding,
+    data_type: DataType,
+    data: QRData,
+    backend: Backend,
 }
 
 impl QREncoder {
     pub fn new() -> QREncoder {
         let encoder = OutputType::NoType;
-        QREncoder { encoder }
+        let data = QRData::new();
+        QREncoder {
+            encoder,
+            encoding: Encoding::NotSelected,
+            data_type: DataType::NoType(None),
+            data,
+            backend: Backend::Raw,
+        }
     }
-}
 
-impl Encode for QREncoder {
-    fn max_len(&mut self) -> Option<usize> {
-        todo!()
+    /// Output type used to encode `data_type` with `encoding`, error if the
+    /// combination is not supported
+    pub fn output_type(data_type: &DataType, encoding: Encoding) -> Result<OutputType, Error> {
+        let output_type = match (encoding, data_type) {
+            (Encoding::NotSelected, _) => {
+                return Err(Error::EncodingError(
+                    "An encoding should be selected prior to load!".to_string(),
+                ))
+            }
+            (_, DataType::Address()) => None,
+            (Encoding::Raw, DataType::Psbt(_) | DataType::PsbtV2(_)) => Some(OutputType::Psbt),
+            (Encoding::Raw, DataType::Xpub(_)) => Some(OutputType::Xpub),
+            (Encoding::Raw, DataType::Xpriv(_)) => Some(OutputType::Xpriv),
+            (Encoding::Raw, DataType::LianaDescriptor(_)) => Some(OutputType::LianaDescriptor),
+            (Encoding::Raw, DataType::Descriptor(_)) => Some(OutputType::Descriptor),
+            (Encoding::Raw, DataType::Mnemonic(_)) => Some(OutputType::Mnemonic),
+            (Encoding::Raw, DataType::PrivateKey(_)) => Some(OutputType::PrivateKey),
+            (Encoding::Raw, DataType::Transaction(_)) => Some(OutputType::Transaction),
+            (Encoding::Raw, DataType::Keys(_) | DataType::NoType(_)) => {
+                Some(OutputType::SimpleQR(QRData::new()))
+            }
+            (Encoding::Specter, DataType::Psbt(_) | DataType::PsbtV2(_)) => {
+                Some(OutputType::SpecterPsbt)
+            }
+            (Encoding::Specter, DataType::Descriptor(_) | DataType::LianaDescriptor(_)) => {
+                Some(OutputType::SpecterDescriptor)
+            }
+            (Encoding::Specter, _) => Some(OutputType::Specter(SpecterQR::new())),
+            (Encoding::Ur, DataType::Psbt(_) | DataType::PsbtV2(_)) => Some(OutputType::UrPsbt),
+            (Encoding::Ur, DataType::Descriptor(_)) => Some(OutputType::UrDescriptor),
+            // a single xpub key expression
+            (Encoding::Ur, DataType::Xpub(_) | DataType::Keys(_)) => Some(OutputType::UrXpub),
+            (Encoding::Ur, DataType::Mnemonic(_)) => Some(OutputType::UrMnemonic),
+            (Encoding::Ur, DataType::PrivateKey(_)) => Some(OutputType::UrPrivateKey),
+            (
+                Encoding::Ur,
+                DataType::Transaction(_) | DataType::LianaDescriptor(_) | DataType::NoType(_),
+            ) => Some(OutputType::UrBytes),
+            (Encoding::Ur, DataType::Xpriv(_)) => None,
+        };
+        output_type.ok_or_else(|| {
+            Error::EncodingError(format!(
+                "{:?} encoding does not support this data type!",
+                encoding
+            ))
+        })
     }
 
-    fn from_liana_descriptor(descriptor: &LianaDescriptor) -> Result<Box<Self>, Error> {
-        todo!()
+    /// encode data with the given encoding and max frame length
+    pub fn from_data(
+        data: &DataType,
+        encoding: Encoding,
+        max_len: Option<usize>,
+    ) -> Result<Box<Self>, Error> {
+        let mut out = QREncoder::new();
+        out.set_output_type(data.clone(), encoding, max_len)?;
+        out.load(data)?;
+        Ok(Box::new(out))
     }
 
-    fn load_string(&mut self, data: &str) -> Result<Box<Self>, Error> {
-        match self.encoder {
-            OutputType::NoType => Err(Error::EncodingError(
-                "An output type should be define prior to load!".to_string(),
-            )),
-            OutputType::LianaDescriptor => {
-                // Import descriptor
-                let imported_descriptor = LianaDescriptor::from_str(data).map_err(|e| {
-                    Error::ParsingError("Cannot load this string into Liana descriptor".to_string())
-                })?;
-                let out = QREncoder::from_liana_descriptor(&imported_descriptor)?;
-                Ok(out)
+    /// Load data with the selected encoding
+    fn load(&mut self, data: &DataType) -> Result<(), Error> {
+        let output_type = QREncoder::output_type(data, self.encoding)?;
+        let max_len = self.data.max_len;
+        match self.encoding {
+            Encoding::Specter => {
+                let mut qr = SpecterQR::new();
+                qr.set_output_type(data.clone(), Encoding::Specter, max_len)?;
+                self.backend = Backend::Specter(qr.load_string(&data.to_text()?)?);
             }
-            OutputType::Specter(_) => Err(Error::NotImplementedError(
-                "type not yet implemented!".to_string(),
-            )),
-            OutputType::SpecterDescriptor => Err(Error::NotImplementedError(
-                "type not yet implemented!".to_string(),
-            )),
-            OutputType::SpecterPsbt => Err(Error::NotImplementedError(
-                "type not yet implemented!".to_string(),
-            )),
+            Encoding::Ur => {
+                let mut ur = UrData::new();
+                ur.set_output_type(data.clone(), Encoding::Ur, max_len)?;
+                ur.load(data)?;
+                self.backend = Backend::Ur(Box::new(ur));
+            }
+            _ => {
+                self.backend = Backend::Raw;
+                self.data.receive(data.to_text()?.as_bytes());
+                self.data.binary = false;
+            }
+        }
+        self.encoder = output_type;
+        self.data_type = data.clone();
+        Ok(())
+    }
 
-            OutputType::SimpleQR(_) => Err(Error::NotImplementedError(
-                "type not yet implemented!".to_string(),
-            )),
+    /// Delegate framing to an encoder of the registry
+    fn load_encoder(&mut self, output_type: OutputType, encoder: Box<dyn Encode>) {
+        self.encoder = output_type;
+        self.backend = Backend::Custom(encoder);
+    }
 
-            OutputType::Ur => Err(Error::NotImplementedError(
-                "type not yet implemented!".to_string(),
-            )),
-            OutputType::UrBytes => Err(Error::NotImplementedError(
-                "type not yet implemented!".to_string(),
-            )),
-            OutputType::UrDescriptor => Err(Error::NotImplementedError(
-                "type not yet implemented!".to_string(),
-            )),
-            OutputType::UrPsbt => Err(Error::NotImplementedError(
-                "type not yet implemented!".to_string(),
-            )),
-            OutputType::UrXpriv => Err(Error::NotImplementedError(
-                "type not yet implemented!".to_string(),
-            )),
-            OutputType::UrXpub => Err(Error::NotImplementedError(
-                "type not yet implemented!".to_string(),
-            )),
+    /// Load a raw payload, binary ones are served by next_bytes()
+    fn load_payload(&mut self, output_type: OutputType, payload: Vec<u8>, binary: bool) {
+        self.encoder = output_type;
+        self.encoding = Encoding::Raw;
+        self.backend = Backend::Raw;
+        self.data.receive(&payload);
+        self.data.binary = binary;
+    }
 
-            OutputType::Psbt => Err(Error::NotImplementedError(
-                "type not yet implemented!".to_string(),
-            )),
-            OutputType::Xpriv => Err(Error::NotImplementedError(
-                "type not yet implemented!".to_string(),
-            )),
-            OutputType::Xpub => Err(Error::NotImplementedError(
-                "type not yet implemented!".to_string(),
-            )),
-            OutputType::Descriptor => Err(Error::NotImplementedError(
-                "type not yet implemented!".to_string(),
-            )),
-            OutputType::LianaDescriptor => Err(Error::NotImplementedError(
-                "type not yet implemented!".to_string(),
-            )),
+    /// encode a signed transaction as hex, Base43 (Electrum) or binary
+    pub fn from_transaction_with(
+        tx: &Transaction,
+        encoding: TxEncoding,
+    ) -> Result<Box<Self>, Error> {
+        let mut out = QREncoder::new();
+        out.load_payload(
+            OutputType::Transaction,
+            transaction::encode(tx, encoding),
+            encoding == TxEncoding::Binary,
+        );
+        Ok(Box::new(out))
+    }
 
-            _ => Err(Error::NotImplementedError(
-                "type not yet implemented!".to_string(),
-            )),
-        }
+    /// encode a PSBT as base64 (Specter Desktop), hex or binary, converted to
+    /// the PSBT version the receiver expects
+    pub fn from_psbt_with(
+        psbt: &Psbt,
+        encoding: PsbtEncoding,
+        version: PsbtVersion,
+    ) -> Result<Box<Self>, Error> {
+        let mut out = QREncoder::new();
+        out.load_payload(
+            OutputType::Psbt,
+            psbt::encode_version(psbt, encoding, version)?,
+            encoding == PsbtEncoding::Binary,
+        );
+        Ok(Box::new(out))
     }
 
-    fn set_output_type(
+    /// Load a PSBT in a text or binary representation, Specter frames are text
+    /// and UR carries the PSBT itself
+    pub(crate) fn load_psbt(
         &mut self,
-        data_type: DataType,
-        encoding: Encoding,
-        max_len: Option<usize>,
-    ) -> &mut Self {
-        todo!()
+        psbt: &Psbt,
+        encoding: PsbtEncoding,
+        version: PsbtVersion,
+    ) -> Result<(), Error> {
+        let payload = psbt::encode_version(psbt, encoding, version)?;
+        let data = DataType::from_psbt(psbt.clone(), version);
+        match (self.encoding, encoding) {
+            (Encoding::Raw, _) => {
+                self.load_payload(OutputType::Psbt, payload, encoding == PsbtEncoding::Binary)
+            }
+            (Encoding::Specter, PsbtEncoding::Base64 | PsbtEncoding::Hex) => {
+                let mut qr = SpecterQR::new();
+                qr.set_output_type(data.clone(), Encoding::Specter, self.data.max_len)?;
+                // base64 & hex are ASCII
+                let payload = String::from_utf8(payload).unwrap();
+                self.backend = Backend::Specter(qr.load_string(&payload)?);
+                self.encoder = OutputType::SpecterPsbt;
+            }
+            _ => {
+                return Err(Error::EncodingError(format!(
+                    "{:?} encoding cannot carry a {:?} PSBT!",
+                    self.encoding, encoding
+                )))
+            }
+        }
+        self.data_type = data;
+        Ok(())
     }
 
-    fn from_psbt(psbt: &Psbt) -> Result<Box<Self>, Error> {
-        todo!()
+    /// Return the payload of a binary (byte mode) QRCode
+    pub fn next_bytes(&mut self) -> Option<Vec<u8>> {
+        if self.data.binary && self.data.is_loaded {
+            Some(self.data.data.clone())
+        } else {
+            None
+        }
     }
 
-    fn from_xpub(xpub: &XPub) -> Result<Box<Self>, Error> {
-        todo!()
+    /// Selected output type
+    pub fn output(&self) -> &OutputType {
+        &self.encoder
     }
+}
 
-    fn from_xpriv(xpriv: &XPriv) -> Result<Box<Self>, Error> {
-        todo!()
+impl Encode for QREncoder {
+    fn max_len(&mut self) -> Option<usize> {
+        self.data.max_len
     }
 
-    fn next(&mut self) -> Option<String> {
-        todo!()
+    fn from_liana_descriptor(descriptor: &LianaDescriptor) -> Result<Box<Self>, Error> {
+        let data = DataType::LianaDescriptor(Some(descriptor.clone()));
+        QREncoder::from_data(&data, Encoding::Raw, None)
     }
-}
 
-impl Decode for QREncoder {
-    fn data_init(&mut self, sequences: usize) {
-        todo!()
+    fn from_descriptor(descriptor: &Descriptor<DescriptorPublicKey>) -> Result<Box<Self>, Error> {
+        let data = DataType::Descriptor(Some(descriptor.clone()));
+        QREncoder::from_data(&data, Encoding::Raw, None)
     }
 
-    fn pattern() -> &'static str {
-        todo!()
+    /// Built by `QREncoder::builder()` with the settings of `set_output_type()`
+    fn load_string(&mut self, data: &str) -> Result<Box<Self>, Error> {
+        let builder = QREncoder::builder()
+            .encoding(self.encoding)
+            .payload(self.data_type.parse_as(data)?);
+        match self.data.max_len {
+            Some(max_len) => builder.max_frame_len(max_len).build(),
+            None => builder.build(),
+        }
     }
 
-    fn is_complete(&self) -> bool {
-        todo!()
+    /// Checked as `QREncoder::builder()` does
+    fn set_output_type(
+        &mut self,
+        data_type: DataType,
+        encoding: Encoding,
+        max_len: Option<usize>,
+    ) -> Result<&mut Self, Error> {
+        self.encoder = EncoderBuilder::check(&data_type, encoding, max_len)?;
+        self.encoding = encoding;
+        self.data_type = data_type;
+        self.data.max_len = max_len;
+        Ok(self)
     }
 
-    fn receive(&mut self, data: &str) -> Result<bool, String> {
-        todo!()
+    fn from_psbt(psbt: &Psbt, options: Option<&MinimiserOptions>) -> Result<Box<Self>, Error> {
+        let psbt = minimiser::apply(psbt, options);
+        QREncoder::from_psbt_with(&psbt, PsbtEncoding::Base64, PsbtVersion::V0)
     }
 
-    fn process(&mut self) {
-        todo!()
+    fn from_xpub(xpub: &XPub) -> Result<Box<Self>, Error> {
+        QREncoder::from_data(&DataType::Xpub(Some(*xpub)), Encoding::Raw, None)
     }
 
-    fn check_complete(&mut self) {
-        todo!()
+    fn from_xpriv(xpriv: &XPriv) -> Result<Box<Self>, Error> {
+        QREncoder::from_data(&DataType::Xpriv(Some(*xpriv)), Encoding::Raw, None)
     }
 
-    fn result() -> Result<DataType, Error> {
-        todo!()
+    fn from_mnemonic(mnemonic: &Mnemonic) -> Result<Box<Self>, Error> {
+        let data = DataType::Mnemonic(Some(mnemonic.clone()));
+        QREncoder::from_data(&data, Encoding::Raw, None)
+    }
+
+    fn from_private_key(key: &PrivateKey) -> Result<Box<Self>, Error> {
+        QREncoder::from_data(&DataType::PrivateKey(Some(*key)), Encoding::Raw, None)
+    }
+
+    fn from_transaction(tx: &Transaction) -> Result<Box<Self>, Error> {
+        QREncoder::from_transaction_with(tx, TxEncoding::Hex)
+    }
+
+    fn next(&mut self) -> Option<String> {
+        match &mut self.backend {
+            Backend::Specter(qr) => qr.next(),
+            Backend::Ur(ur) => ur.next(),
+            Backend::Custom(encoder) => encoder.next(),
+            // a raw payload fits in a single QRCode
+            Backend::Raw if self.data.is_loaded => self.data.text().map(|text| text.to_string()),
+            Backend::Raw => None,
+        }
     }
 }
 
@@ -359,3 +982,26 @@ impl Decode for QREncoder {
 //     let mut qr_encoder = QREncoder::new();
 //     qr_encoder.load_str("213216546842lkljbjkhbvhgv5654", Specter(SpecterQR::new()), 13).unwrap();
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seedqr_typing() {
+        let words = "forum undo fragile fade shy sign arrest garment culture tube off merit";
+        let expected = DataType::Mnemonic(Some(mnemonic::parse(words).unwrap()));
+        assert_eq!(
+            DataType::from_string("073318950739065415961602009907670428187212261116"),
+            expected
+        );
+        // bytes of a compact SeedQR length are not guessed as entropy
+        let entropy = hex::decode("5bbd9d71a8ec7990831aff359d426545").unwrap();
+        assert_eq!(DataType::from_bytes(&entropy), DataType::NoType(None));
+        assert_eq!(DataType::from_bytes(&[0xff; 32]), DataType::NoType(None));
+        assert_eq!(
+            DataType::from_bytes(b"0123456789abcdef"),
+            DataType::NoType(Some("0123456789abcdef".to_string()))
+        );
+    }
+}
@@ -0,0 +1,324 @@
+//! Strip the PSBT fields a signer does not need, to reduce animated QR frames
+
+use crate::Error;
+use bitcoin::psbt::{Input, PartiallySignedTransaction as Psbt};
+use std::borrow::Cow;
+
+/// What to do with `non_witness_utxo`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NonWitnessUtxo {
+    Keep,
+    /// keep it for legacy (non segwit) inputs only
+    LegacyOnly,
+    /// strip it from every segwit input, legacy inputs keep it and are
+    /// reported in `Savings::unstripped`
+    Strip,
+}
+
+/// Fields to strip, each one can be kept if the target signer needs it
+#[derive(Debug, Clone, PartialEq)]
+pub struct MinimiserOptions {
+    pub non_witness_utxo: NonWitnessUtxo,
+    /// global xpubs
+    pub global_xpubs: bool,
+    /// proprietary fields (global, inputs & outputs)
+    pub proprietary: bool,
+    /// unknown fields (global, inputs & outputs)
+    pub unknown: bool,
+    /// outputs key derivations, signers use them to detect change
+    pub output_derivations: bool,
+}
+
+impl Default for MinimiserOptions {
+    fn default() -> Self {
+        MinimiserOptions {
+            non_witness_utxo: NonWitnessUtxo::LegacyOnly,
+            global_xpubs: true,
+            proprietary: true,
+            unknown: true,
+            output_derivations: false,
+        }
+    }
+}
+
+/// Size of the PSBT before and after minimisation
+#[derive(Debug, Clone, PartialEq)]
+pub struct Savings {
+    pub original: usize,
+    pub minimised: usize,
+    /// Inputs whose `non_witness_utxo` could not be stripped with
+    /// `NonWitnessUtxo::Strip`: legacy signers need the whole transaction
+    pub unstripped: Vec<usize>,
+}
+
+impl Savings {
+    /// Saved bytes
+    pub fn saved(&self) -> usize {
+        self.original.saturating_sub(self.minimised)
+    }
+}
+
+/// The input spends a witness program (or a P2SH wrapped one), `witness_utxo`
+/// is enough to sign it
+fn is_segwit(input: &Input, vout: u32) -> bool {
+    let spent = input.witness_utxo.as_ref().or_else(|| {
+        input
+            .non_witness_utxo
+            .as_ref()
+            .and_then(|tx| tx.output.get(vout as usize))
+    });
+    let script_pubkey = match spent {
+        Some(txout) => &txout.script_pubkey,
+        None => return false,
+    };
+    if script_pubkey.is_witness_program() {
+        true
+    } else if script_pubkey.is_p2sh() {
+        input.witness_script.is_some()
+            || input
+                .redeem_script
+                .as_ref()
+                .map_or(false, |script| script.is_witness_program())
+    } else {
+        false
+    }
+}
+
+/// Return a minimised copy of the PSBT and the saved bytes, keep the original
+/// to `restore()` the stripped fields once the PSBT comes back signed
+pub fn minimise(psbt: &Psbt, options: &MinimiserOptions) -> (Psbt, Savings) {
+    let mut out = psbt.clone();
+
+    if options.global_xpubs {
+        out.xpub.clear();
+    }
+    if options.proprietary {
+        out.proprietary.clear();
+    }
+    if options.unknown {
+        out.unknown.clear();
+    }
+
+    let mut unstripped = Vec::new();
+    let inputs = out.inputs.iter_mut().zip(psbt.unsigned_tx.input.iter());
+    for (index, (input, txin)) in inputs.enumerate() {
+        let vout = txin.previous_output.vout;
+        if options.non_witness_utxo != NonWitnessUtxo::Keep && is_segwit(input, vout) {
+            if let Some(tx) = input.non_witness_utxo.take() {
+                // segwit signers still need the spent output
+                if input.witness_utxo.is_none() {
+                    input.witness_utxo = tx.output.get(vout as usize).cloned();
+                }
+            }
+        } else if options.non_witness_utxo == NonWitnessUtxo::Strip
+            && input.non_witness_utxo.is_some()
+        {
+            unstripped.push(index);
+        }
+        if options.proprietary {
+            input.proprietary.clear();
+        }
+        if options.unknown {
+            input.unknown.clear();
+        }
+    }
+
+    for output in out.outputs.iter_mut() {
+        if options.output_derivations {
+            output.bip32_derivation.clear();
+            output.tap_key_origins.clear();
+        }
+        if options.proprietary {
+            output.proprietary.clear();
+        }
+        if options.unknown {
+            output.unknown.clear();
+        }
+    }
+
+    let savings = Savings {
+        original: psbt.serialize().len(),
+        minimised: out.serialize().len(),
+        unstripped,
+    };
+    (out, savings)
+}
+
+/// The PSBT to encode: minimised if options are given, as is otherwise
+pub fn apply<'a>(psbt: &'a Psbt, options: Option<&MinimiserOptions>) -> Cow<'a, Psbt> {
+    match options {
+        Some(options) => Cow::Owned(minimise(psbt, options).0),
+        None => Cow::Borrowed(psbt),
+    }
+}
+
+/// Merge back the fields stripped by `minimise()` into the signed PSBT
+pub fn restore(signed: &Psbt, original: &Psbt) -> Result<Psbt, Error> {
+    let mut out = signed.clone();
+    out.combine(original.clone())
+        .map_err(|e| Error::ParsingError(format!("Cannot restore PSBT: {}", e)))?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::absolute::LockTime;
+    use bitcoin::bip32::{DerivationPath, ExtendedPubKey as XPub, Fingerprint};
+    use bitcoin::psbt::raw::ProprietaryKey;
+    use bitcoin::{OutPoint, PublicKey, ScriptBuf, Transaction, TxIn, TxOut};
+    use std::str::FromStr;
+
+    const XPUB: &str = "xpub6ASuArnXKPbfEwhqN6e3mwBcDTgzisQN1wXN9BJcM47sSikHjJf3UFHKkNAWbWMiGj7Wf5uMash7SyYq527Hqck2AxYysAA7xmALppuCkwQ";
+
+    fn transaction(input: Vec<TxIn>, output: Vec<TxOut>) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input,
+            output,
+        }
+    }
+
+    /// A PSBT spending a P2PKH, a P2WPKH and a P2SH-P2WPKH output, every
+    /// input only has the previous transaction
+    fn psbt() -> Psbt {
+        let xpub = XPub::from_str(XPUB).unwrap();
+        let key = PublicKey::new(xpub.public_key);
+        let p2wpkh = ScriptBuf::new_v0_p2wpkh(&key.wpubkey_hash().unwrap());
+        let scripts = [
+            ScriptBuf::new_p2pkh(&key.pubkey_hash()),
+            p2wpkh.clone(),
+            ScriptBuf::new_p2sh(&p2wpkh.script_hash()),
+        ];
+        let previous = transaction(
+            vec![TxIn::default()],
+            scripts
+                .iter()
+                .map(|script| TxOut {
+                    value: 10_000,
+                    script_pubkey: script.clone(),
+                })
+                .collect(),
+        );
+        let tx = transaction(
+            (0..3)
+                .map(|vout| TxIn {
+                    previous_output: OutPoint::new(previous.txid(), vout),
+                    ..Default::default()
+                })
+                .collect(),
+            vec![TxOut {
+                value: 29_000,
+                script_pubkey: p2wpkh.clone(),
+            }],
+        );
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        for input in psbt.inputs.iter_mut() {
+            input.non_witness_utxo = Some(previous.clone());
+        }
+        psbt.inputs[2].redeem_script = Some(p2wpkh);
+
+        let origin = (
+            Fingerprint::from_str("d34db33f").unwrap(),
+            DerivationPath::from_str("m/84'/0'/0'").unwrap(),
+        );
+        psbt.xpub.insert(xpub, origin.clone());
+        psbt.outputs[0]
+            .bip32_derivation
+            .insert(xpub.public_key, origin);
+        let proprietary = ProprietaryKey {
+            prefix: b"multiqr".to_vec(),
+            subtype: 0,
+            key: vec![],
+        };
+        psbt.proprietary.insert(proprietary.clone(), vec![1]);
+        psbt.inputs[0].proprietary.insert(proprietary, vec![2]);
+        psbt
+    }
+
+    #[test]
+    fn legacy_only() {
+        let psbt = psbt();
+        let (out, savings) = minimise(&psbt, &MinimiserOptions::default());
+        // P2PKH keeps the previous transaction
+        assert!(out.inputs[0].non_witness_utxo.is_some());
+        assert!(out.inputs[0].witness_utxo.is_none());
+        // segwit inputs get the spent output instead
+        for (input, vout) in out.inputs.iter().zip(0..).skip(1) {
+            assert!(input.non_witness_utxo.is_none());
+            assert_eq!(
+                input.witness_utxo.as_ref(),
+                psbt.inputs[0]
+                    .non_witness_utxo
+                    .as_ref()
+                    .unwrap()
+                    .output
+                    .get(vout)
+            );
+        }
+        assert!(savings.unstripped.is_empty());
+        assert!(out.xpub.is_empty());
+        assert!(out.proprietary.is_empty());
+        assert!(out.inputs[0].proprietary.is_empty());
+        // change detection still works
+        assert!(!out.outputs[0].bip32_derivation.is_empty());
+        assert_eq!(savings.original, psbt.serialize().len());
+        assert_eq!(savings.minimised, out.serialize().len());
+        assert!(savings.saved() > 0);
+    }
+
+    #[test]
+    fn strip_reports_legacy_inputs() {
+        let mut psbt = psbt();
+        // P2SH without redeem script, it cannot be told from a legacy one
+        psbt.inputs[2].redeem_script = None;
+        let options = MinimiserOptions {
+            non_witness_utxo: NonWitnessUtxo::Strip,
+            output_derivations: true,
+            ..Default::default()
+        };
+        let (out, savings) = minimise(&psbt, &options);
+        assert_eq!(savings.unstripped, vec![0, 2]);
+        assert!(out.inputs[0].non_witness_utxo.is_some());
+        assert!(out.inputs[1].non_witness_utxo.is_none());
+        assert!(out.inputs[2].non_witness_utxo.is_some());
+        assert!(out.inputs[2].witness_utxo.is_none());
+        assert!(out.outputs[0].bip32_derivation.is_empty());
+    }
+
+    #[test]
+    fn keep_everything() {
+        let psbt = psbt();
+        let options = MinimiserOptions {
+            non_witness_utxo: NonWitnessUtxo::Keep,
+            global_xpubs: false,
+            proprietary: false,
+            unknown: false,
+            output_derivations: false,
+        };
+        let (out, savings) = minimise(&psbt, &options);
+        assert_eq!(out, psbt);
+        assert_eq!(savings.saved(), 0);
+        assert!(savings.unstripped.is_empty());
+        assert_eq!(apply(&psbt, None).as_ref(), &psbt);
+        assert_eq!(apply(&psbt, Some(&options)).as_ref(), &psbt);
+    }
+
+    #[test]
+    fn restore_stripped_fields() {
+        let psbt = psbt();
+        let (out, _) = minimise(&psbt, &MinimiserOptions::default());
+        let restored = restore(&out, &psbt).unwrap();
+        assert_eq!(restored.xpub, psbt.xpub);
+        assert_eq!(restored.proprietary, psbt.proprietary);
+        for (restored, original) in restored.inputs.iter().zip(psbt.inputs.iter()) {
+            assert_eq!(restored.non_witness_utxo, original.non_witness_utxo);
+            assert_eq!(restored.proprietary, original.proprietary);
+        }
+        // another transaction
+        let mut other = psbt.clone();
+        other.unsigned_tx.lock_time = LockTime::from_consensus(1);
+        assert!(restore(&out, &other).is_err());
+    }
+}
@@ -0,0 +1,77 @@
+use crate::Error;
+use bip39::{Language, Mnemonic};
+use bitcoin::bip32::ExtendedPrivKey as XPriv;
+use bitcoin::Network;
+
+/// Parse an english BIP39 mnemonic, the checksum is verified
+pub fn parse(data: &str) -> Result<Mnemonic, Error> {
+    let words: Vec<&str> = data.split_whitespace().collect();
+    if !matches!(words.len(), 12 | 15 | 18 | 21 | 24) {
+        return Err(Error::ParsingError(
+            "Mnemonic must be 12 to 24 words!".to_string(),
+        ));
+    }
+    Mnemonic::parse_in_normalized(Language::English, &words.join(" ").to_lowercase())
+        .map_err(|e| Error::mnemonic_with("cannot parse", e))
+}
+
+/// Derive the master private key of a mnemonic, with an optional passphrase
+pub fn to_xpriv(mnemonic: &Mnemonic, passphrase: &str, network: Network) -> Result<XPriv, Error> {
+    XPriv::new_master(network, &mnemonic.to_seed(passphrase))
+        .map_err(|e| Error::ParsingError(format!("Cannot derive master key: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WORDS: &str = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+    #[test]
+    fn parse_mnemonic() {
+        let mnemonic = parse(WORDS).unwrap();
+        assert_eq!(mnemonic.to_string(), WORDS);
+        // case and whitespaces are normalized
+        let upper = format!("  {}\n", WORDS.to_uppercase().replace(' ', "   "));
+        assert_eq!(parse(&upper).unwrap(), mnemonic);
+    }
+
+    #[test]
+    fn invalid_mnemonic() {
+        // word count
+        assert!(parse("abandon abandon abandon").is_err());
+        assert!(parse(&format!("{} abandon", WORDS)).is_err());
+        // checksum
+        assert!(parse(&WORDS.replace("about", "abandon")).is_err());
+        // unknown word
+        assert!(parse(&WORDS.replace("about", "bitcoin1")).is_err());
+    }
+
+    // BIP39 test vector (passphrase "TREZOR")
+    #[test]
+    fn master_key() {
+        let mnemonic = parse(WORDS).unwrap();
+        let xpriv = to_xpriv(&mnemonic, "TREZOR", Network::Bitcoin).unwrap();
+        assert_eq!(
+            xpriv.to_string(),
+            "xprv9s21ZrQH143K3h3fDYiay8mocZ3afhfULfb5GX8kCBdno77K4HiA15Tg23wpbeF1pLfs1c5SPmYHrEpTuuRhxMwvKDwqdKiGJS9XFKzUsAF"
+        );
+        let tprv = to_xpriv(&mnemonic, "TREZOR", Network::Testnet).unwrap();
+        assert_eq!(tprv.network, Network::Testnet);
+        assert_eq!(tprv.private_key, xpriv.private_key);
+        assert_ne!(to_xpriv(&mnemonic, "", Network::Bitcoin).unwrap(), xpriv);
+    }
+
+    #[test]
+    fn error_source() {
+        use std::error::Error as _;
+        let err = parse(&WORDS.replace("about", "abandon")).unwrap_err();
+        assert!(matches!(err, Error::MnemonicError { .. }));
+        assert!(err
+            .source()
+            .unwrap()
+            .downcast_ref::<bip39::Error>()
+            .is_some());
+        assert!(parse("abandon").unwrap_err().source().is_none());
+    }
+}
@@ -0,0 +1,468 @@
+//! Liana wallet policy: summary of a decoded descriptor, and assembly of a
+//! descriptor from the keys collected over QR
+
+use crate::{DataType, Error};
+use bitcoin::bip32::{ChildNumber, DerivationPath, Fingerprint};
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::{Address, Network};
+use liana::descriptors::{LianaDescriptor, LianaPolicy, PathInfo};
+use miniscript::descriptor::{DerivPaths, DescriptorMultiXKey, DescriptorXKey, Wildcard};
+use miniscript::DescriptorPublicKey;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// Average time between two blocks, in minutes
+const BLOCK_INTERVAL: u32 = 10;
+
+/// A key of a spending path
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeySummary {
+    pub fingerprint: Fingerprint,
+    /// Derivation path from the master key, None if the key has no origin
+    pub origin: Option<DerivationPath>,
+    pub key: DescriptorPublicKey,
+}
+
+/// A spending path of a Liana wallet
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathSummary {
+    pub threshold: usize,
+    pub keys: Vec<KeySummary>,
+    /// Relative timelock in blocks, None for the primary path
+    pub timelock: Option<u16>,
+}
+
+/// Summary of a Liana wallet policy
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicySummary {
+    pub primary: PathSummary,
+    /// Recovery paths, ordered by timelock
+    pub recovery: Vec<PathSummary>,
+    pub first_address: Address,
+}
+
+fn origin(key: &DescriptorPublicKey) -> Option<DerivationPath> {
+    let origin = match key {
+        DescriptorPublicKey::Single(single) => &single.origin,
+        DescriptorPublicKey::XPub(xpub) => &xpub.origin,
+        DescriptorPublicKey::MultiXPub(xpub) => &xpub.origin,
+    };
+    origin.as_ref().map(|(_, path)| path.clone())
+}
+
+/// Network of an extended key, None for a single key
+fn key_network(key: &DescriptorPublicKey) -> Option<Network> {
+    match key {
+        DescriptorPublicKey::Single(_) => None,
+        DescriptorPublicKey::XPub(xpub) => Some(xpub.xkey.network),
+        DescriptorPublicKey::MultiXPub(xpub) => Some(xpub.xkey.network),
+    }
+}
+
+/// Check the extended keys are for `network`, test networks share the same keys
+fn check_network<'a>(
+    keys: impl IntoIterator<Item = &'a DescriptorPublicKey>,
+    network: Network,
+) -> Result<(), Error> {
+    for found in keys.into_iter().filter_map(key_network) {
+        if (found == Network::Bitcoin) != (network == Network::Bitcoin) {
+            return Err(Error::NetworkMismatch {
+                expected: network,
+                found,
+            });
+        }
+    }
+    Ok(())
+}
+
+impl PathSummary {
+    fn new(path: &PathInfo, timelock: Option<u16>) -> PathSummary {
+        let (threshold, keys) = match path {
+            PathInfo::Single(key) => (1, vec![key.clone()]),
+            PathInfo::Multi(threshold, keys) => (*threshold, keys.clone()),
+        };
+        let keys = keys
+            .into_iter()
+            .map(|key| KeySummary {
+                fingerprint: key.master_fingerprint(),
+                origin: origin(&key),
+                key,
+            })
+            .collect();
+        PathSummary {
+            threshold,
+            keys,
+            timelock,
+        }
+    }
+
+    /// Approximate timelock duration in days
+    pub fn days(&self) -> Option<u32> {
+        self.timelock.map(|blocks| blocks as u32 * BLOCK_INTERVAL / (60 * 24))
+    }
+}
+
+impl PolicySummary {
+    /// Summarize the policy of a Liana descriptor
+    pub fn new(descriptor: &LianaDescriptor, network: Network) -> Result<PolicySummary, Error> {
+        let policy = descriptor.policy();
+        let primary = PathSummary::new(policy.primary_path(), None);
+        let recovery = policy
+            .recovery_paths()
+            .iter()
+            .map(|(timelock, path)| PathSummary::new(path, Some(*timelock)))
+            .collect::<Vec<_>>();
+        check_network(
+            std::iter::once(&primary)
+                .chain(recovery.iter())
+                .flat_map(|path| path.keys.iter().map(|key| &key.key)),
+            network,
+        )?;
+
+        let secp = Secp256k1::verification_only();
+        let first_address = descriptor
+            .receive_descriptor()
+            .derive(0.into(), &secp)
+            .address(network);
+
+        Ok(PolicySummary {
+            primary,
+            recovery,
+            first_address,
+        })
+    }
+
+    /// Summarize a decoded Liana descriptor
+    pub fn from_data(data: &DataType, network: Network) -> Result<PolicySummary, Error> {
+        match data {
+            DataType::LianaDescriptor(Some(descriptor)) => PolicySummary::new(descriptor, network),
+            _ => Err(Error::ParsingError("Data is not a Liana descriptor!".to_string())),
+        }
+    }
+
+    /// Fingerprints of all the keys of the policy
+    pub fn fingerprints(&self) -> Vec<Fingerprint> {
+        let mut out: Vec<Fingerprint> = std::iter::once(&self.primary)
+            .chain(self.recovery.iter())
+            .flat_map(|path| path.keys.iter().map(|key| key.fingerprint))
+            .collect();
+        out.sort();
+        out.dedup();
+        out
+    }
+}
+
+/// Make a key expression usable in a Liana descriptor: an account xpub without
+/// children gets the receive/change `/<0;1>/*` suffix
+pub fn wallet_key(key: DescriptorPublicKey) -> Result<DescriptorPublicKey, Error> {
+    match key {
+        DescriptorPublicKey::MultiXPub(_) => Ok(key),
+        DescriptorPublicKey::XPub(DescriptorXKey {
+            origin,
+            xkey,
+            derivation_path,
+            wildcard: Wildcard::None,
+        }) if derivation_path.as_ref().is_empty() => {
+            let paths = [0, 1]
+                .iter()
+                .map(|index| DerivationPath::from(vec![ChildNumber::Normal { index: *index }]))
+                .collect();
+            Ok(DescriptorPublicKey::MultiXPub(DescriptorMultiXKey {
+                origin,
+                xkey,
+                derivation_paths: DerivPaths::new(paths).expect("two paths"),
+                wildcard: Wildcard::Unhardened,
+            }))
+        }
+        key => Err(Error::ParsingError(format!(
+            "Key cannot be used in a Liana descriptor: {}",
+            key
+        ))),
+    }
+}
+
+/// Parse a key expression (`[fingerprint/origin]xpub`, maybe with children)
+pub fn key_from_str(data: &str) -> Result<DescriptorPublicKey, Error> {
+    let key = DescriptorPublicKey::from_str(data.trim())
+        .map_err(|e| Error::descriptor_with("invalid key expression", e))?;
+    wallet_key(key)
+}
+
+/// Extract a key expression from a decoded QR, a bare xpub carries no origin
+pub fn key_from_data(data: &DataType) -> Result<DescriptorPublicKey, Error> {
+    match data {
+        DataType::Xpub(Some(xpub)) => wallet_key(DescriptorPublicKey::XPub(DescriptorXKey {
+            origin: None,
+            xkey: *xpub,
+            derivation_path: DerivationPath::master(),
+            wildcard: Wildcard::None,
+        })),
+        // UR crypto-hdkey
+        DataType::Keys(Some(keys)) if keys.len() == 1 => wallet_key(keys[0].clone()),
+        DataType::NoType(Some(data)) => key_from_str(data),
+        _ => Err(Error::ParsingError("Data is not a key expression!".to_string())),
+    }
+}
+
+fn path_info(threshold: usize, keys: Vec<DescriptorPublicKey>) -> Result<PathInfo, Error> {
+    if threshold == 0 || threshold > keys.len() {
+        return Err(Error::ParsingError(format!(
+            "Invalid threshold {} for {} keys!",
+            threshold,
+            keys.len()
+        )));
+    }
+    if keys.len() == 1 {
+        Ok(PathInfo::Single(keys.into_iter().next().unwrap()))
+    } else {
+        Ok(PathInfo::Multi(threshold, keys))
+    }
+}
+
+/// Assemble a Liana descriptor from the keys collected over QR
+#[derive(Debug, Clone, Default)]
+pub struct PolicyBuilder {
+    primary: Option<(usize, Vec<DescriptorPublicKey>)>,
+    recovery: BTreeMap<u16, (usize, Vec<DescriptorPublicKey>)>,
+}
+
+impl PolicyBuilder {
+    pub fn new() -> PolicyBuilder {
+        PolicyBuilder::default()
+    }
+
+    /// Set the keys and threshold of the primary path
+    pub fn primary(&mut self, threshold: usize, keys: Vec<DescriptorPublicKey>) -> &mut Self {
+        self.primary = Some((threshold, keys));
+        self
+    }
+
+    /// Add a recovery path, available after `timelock` blocks
+    pub fn recovery(
+        &mut self,
+        timelock: u16,
+        threshold: usize,
+        keys: Vec<DescriptorPublicKey>,
+    ) -> &mut Self {
+        self.recovery.insert(timelock, (threshold, keys));
+        self
+    }
+
+    /// Validate the policy and build the descriptor
+    pub fn build(&self) -> Result<LianaDescriptor, Error> {
+        let (threshold, keys) = self
+            .primary
+            .clone()
+            .ok_or_else(|| Error::ParsingError("Primary path not set!".to_string()))?;
+        if self.recovery.is_empty() {
+            return Err(Error::ParsingError("No recovery path set!".to_string()));
+        }
+        // all the keys must be for the network of the first extended key
+        let all_keys = keys
+            .iter()
+            .chain(self.recovery.values().flat_map(|(_, keys)| keys.iter()));
+        if let Some(network) = all_keys.clone().find_map(key_network) {
+            check_network(all_keys, network)?;
+        }
+        let primary = path_info(threshold, keys)?;
+        let mut recovery = BTreeMap::new();
+        for (timelock, (threshold, keys)) in &self.recovery {
+            recovery.insert(*timelock, path_info(*threshold, keys.clone())?);
+        }
+        let policy = LianaPolicy::new(primary, recovery)
+            .map_err(|e| Error::ParsingError(format!("Invalid Liana policy: {}", e)))?;
+        Ok(LianaDescriptor::new(policy))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::bip32::ExtendedPubKey as XPub;
+
+    // BIP32 test vector 1: m, m/0' and m/0'/1
+    const MASTER: &str = "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8";
+    const CHILD: &str = "xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw";
+    const XPUB: &str = "xpub6ASuArnXKPbfEwhqN6e3mwBcDTgzisQN1wXN9BJcM47sSikHjJf3UFHKkNAWbWMiGj7Wf5uMash7SyYq527Hqck2AxYysAA7xmALppuCkwQ";
+
+    fn key(xpub: &str) -> DescriptorPublicKey {
+        DescriptorPublicKey::from_str(&format!("{}/<0;1>/*", xpub)).unwrap()
+    }
+
+    fn testnet(xpub: &str) -> String {
+        XPub {
+            network: Network::Testnet,
+            ..XPub::from_str(xpub).unwrap()
+        }
+        .to_string()
+    }
+
+    /// 2-of-2 primary path, a single key recovery path after a year
+    fn descriptor(recovery: &str) -> LianaDescriptor {
+        let primary = PathInfo::Multi(2, vec![key(MASTER), key(CHILD)]);
+        let recovery = BTreeMap::from([(52_596, PathInfo::Single(key(recovery)))]);
+        LianaDescriptor::new(LianaPolicy::new(primary, recovery).unwrap())
+    }
+
+    #[test]
+    fn policy_summary() {
+        let summary = PolicySummary::new(&descriptor(XPUB), Network::Bitcoin).unwrap();
+        assert_eq!(summary.primary.threshold, 2);
+        assert_eq!(summary.primary.timelock, None);
+        assert_eq!(summary.primary.days(), None);
+        assert_eq!(
+            summary.primary.keys[0].fingerprint,
+            Fingerprint::from_str("3442193e").unwrap()
+        );
+        assert_eq!(summary.primary.keys[0].origin, None);
+        assert_eq!(summary.recovery.len(), 1);
+        assert_eq!(summary.recovery[0].threshold, 1);
+        assert_eq!(summary.recovery[0].timelock, Some(52_596));
+        assert_eq!(summary.recovery[0].days(), Some(365));
+        assert_eq!(summary.recovery[0].keys[0].key, key(XPUB));
+        assert_eq!(
+            summary.fingerprints(),
+            ["3442193e", "5c1bd648", "bef5a2f9"]
+                .iter()
+                .map(|fingerprint| Fingerprint::from_str(fingerprint).unwrap())
+                .collect::<Vec<_>>()
+        );
+        // P2WSH
+        assert!(summary.first_address.to_string().starts_with("bc1q"));
+        assert_eq!(summary.first_address.to_string().len(), 62);
+    }
+
+    #[test]
+    fn key_origin() {
+        let key =
+            DescriptorPublicKey::from_str(&format!("[d34db33f/48'/0'/0'/2']{}/<0;1>/*", XPUB))
+                .unwrap();
+        assert_eq!(
+            origin(&key),
+            Some(DerivationPath::from_str("m/48'/0'/0'/2'").unwrap())
+        );
+        assert_eq!(key_network(&key), Some(Network::Bitcoin));
+    }
+
+    #[test]
+    fn policy_network() {
+        let descriptor = descriptor(XPUB);
+        for network in [Network::Testnet, Network::Signet, Network::Regtest] {
+            assert!(matches!(
+                PolicySummary::new(&descriptor, network),
+                Err(Error::NetworkMismatch {
+                    expected,
+                    found: Network::Bitcoin,
+                }) if expected == network
+            ));
+        }
+        // test networks share their keys
+        let descriptor = LianaDescriptor::new(
+            LianaPolicy::new(
+                PathInfo::Single(key(&testnet(MASTER))),
+                BTreeMap::from([(144, PathInfo::Single(key(&testnet(CHILD))))]),
+            )
+            .unwrap(),
+        );
+        let summary = PolicySummary::new(&descriptor, Network::Signet).unwrap();
+        assert!(summary.first_address.to_string().starts_with("tb1q"));
+        assert!(PolicySummary::new(&descriptor, Network::Bitcoin).is_err());
+        // a policy mixing networks
+        assert!(PolicySummary::new(&self::descriptor(&testnet(XPUB)), Network::Bitcoin).is_err());
+    }
+
+    #[test]
+    fn not_a_liana_descriptor() {
+        let data = DataType::NoType(Some("wsh()".to_string()));
+        assert!(PolicySummary::from_data(&data, Network::Bitcoin).is_err());
+        let data = DataType::LianaDescriptor(Some(descriptor(XPUB)));
+        assert!(PolicySummary::from_data(&data, Network::Bitcoin).is_ok());
+    }
+
+    #[test]
+    fn wallet_keys() {
+        let account = format!("[d34db33f/48'/0'/0'/2']{}", XPUB);
+        let key = key_from_str(&format!(" {}\n", account)).unwrap();
+        assert_eq!(key.to_string(), format!("{}/<0;1>/*", account));
+        // multipath keys are kept
+        assert_eq!(key_from_str(&key.to_string()).unwrap(), key);
+        // other children cannot be used
+        assert!(key_from_str(&format!("{}/0/*", account)).is_err());
+        assert!(
+            key_from_str("02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5")
+                .is_err()
+        );
+        assert!(key_from_str("not a key").is_err());
+
+        let xpub = XPub::from_str(XPUB).unwrap();
+        assert_eq!(
+            key_from_data(&DataType::Xpub(Some(xpub))).unwrap(),
+            self::key(XPUB)
+        );
+        let origin = DescriptorPublicKey::from_str(&account).unwrap();
+        assert_eq!(
+            key_from_data(&DataType::Keys(Some(vec![origin.clone()]))).unwrap(),
+            key
+        );
+        assert!(key_from_data(&DataType::Keys(Some(vec![origin.clone(), origin]))).is_err());
+        assert_eq!(
+            key_from_data(&DataType::NoType(Some(account))).unwrap(),
+            key
+        );
+        assert!(key_from_data(&DataType::Xpub(None)).is_err());
+    }
+
+    #[test]
+    fn build_policy() {
+        let mut builder = PolicyBuilder::new();
+        assert!(builder.build().is_err());
+        builder.primary(2, vec![key(MASTER), key(CHILD)]);
+        // no recovery path
+        assert!(builder.build().is_err());
+        builder.recovery(52_596, 1, vec![key(XPUB)]);
+        let descriptor = builder.build().unwrap();
+        assert_eq!(descriptor.to_string(), self::descriptor(XPUB).to_string());
+        let summary = PolicySummary::new(&descriptor, Network::Bitcoin).unwrap();
+        assert_eq!(summary.primary.threshold, 2);
+        assert_eq!(summary.recovery[0].timelock, Some(52_596));
+    }
+
+    #[test]
+    fn invalid_policy() {
+        for threshold in [0, 3] {
+            let mut builder = PolicyBuilder::new();
+            builder
+                .primary(threshold, vec![key(MASTER), key(CHILD)])
+                .recovery(144, 1, vec![key(XPUB)]);
+            assert!(builder.build().is_err());
+        }
+        // the same key twice
+        let mut builder = PolicyBuilder::new();
+        builder
+            .primary(1, vec![key(MASTER)])
+            .recovery(144, 1, vec![key(MASTER)]);
+        assert!(builder.build().is_err());
+    }
+
+    #[test]
+    fn builder_network() {
+        let mut builder = PolicyBuilder::new();
+        builder.primary(2, vec![key(MASTER), key(CHILD)]).recovery(
+            144,
+            1,
+            vec![key(&testnet(XPUB))],
+        );
+        assert!(matches!(
+            builder.build(),
+            Err(Error::NetworkMismatch {
+                expected: Network::Bitcoin,
+                found: Network::Testnet,
+            })
+        ));
+        let mut builder = PolicyBuilder::new();
+        builder
+            .primary(1, vec![key(&testnet(MASTER))])
+            .recovery(144, 1, vec![key(&testnet(XPUB))]);
+        assert!(builder.build().is_ok());
+    }
+}
@@ -0,0 +1,589 @@
+//! PSBT serialization in QRCodes
+
+use crate::{hex, Error};
+use base64::Engine;
+use bitcoin::absolute::LockTime;
+use bitcoin::consensus;
+use bitcoin::hashes::Hash;
+use bitcoin::psbt::PartiallySignedTransaction as Psbt;
+use bitcoin::{OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness};
+
+/// PSBT magic bytes: `psbt` + 0xff
+pub const MAGIC: &[u8; 5] = b"psbt\xff";
+const MAGIC_HEX: &str = "70736274ff";
+const MAGIC_BASE64: &str = "cHNidP";
+
+/// Text/binary representation of a PSBT in a QRCode
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PsbtEncoding {
+    /// Specter Desktop
+    Base64,
+    Hex,
+    /// byte mode QRCode
+    Binary,
+}
+
+/// Serialize a PSBT (v0) in the given encoding
+pub fn encode(psbt: &Psbt, encoding: PsbtEncoding) -> Result<Vec<u8>, Error> {
+    encode_version(psbt, encoding, PsbtVersion::V0)
+}
+
+/// Serialize a PSBT in the given encoding, converted to the given version
+pub fn encode_version(
+    psbt: &Psbt,
+    encoding: PsbtEncoding,
+    version: PsbtVersion,
+) -> Result<Vec<u8>, Error> {
+    let bytes = serialize(psbt, version)?;
+    Ok(match encoding {
+        PsbtEncoding::Base64 => base64::engine::general_purpose::STANDARD
+            .encode(bytes)
+            .into_bytes(),
+        PsbtEncoding::Hex => hex::encode(&bytes).into_bytes(),
+        PsbtEncoding::Binary => bytes,
+    })
+}
+
+/// Detect the encoding of a PSBT payload from its magic
+pub fn detect(data: &[u8]) -> Option<PsbtEncoding> {
+    if data.starts_with(MAGIC) {
+        return Some(PsbtEncoding::Binary);
+    }
+    let text = std::str::from_utf8(data).ok()?.trim_start();
+    if text.starts_with(MAGIC_BASE64) {
+        Some(PsbtEncoding::Base64)
+    } else if text
+        .get(..MAGIC_HEX.len())
+        .map_or(false, |magic| magic.eq_ignore_ascii_case(MAGIC_HEX))
+    {
+        Some(PsbtEncoding::Hex)
+    } else {
+        None
+    }
+}
+
+/// Parse a PSBT, the encoding is detected from the magic, v2 is converted to v0
+pub fn decode(data: &[u8]) -> Result<Psbt, Error> {
+    Ok(decode_versioned(data)?.0)
+}
+
+/// Parse a PSBT and return its original version, v2 is converted to v0
+pub fn decode_versioned(data: &[u8]) -> Result<(Psbt, PsbtVersion), Error> {
+    let bytes = match detect(data) {
+        Some(PsbtEncoding::Binary) => data.to_vec(),
+        Some(PsbtEncoding::Base64) => base64::engine::general_purpose::STANDARD
+            .decode(String::from_utf8_lossy(data).trim())
+            .map_err(|e| Error::psbt_with("invalid base64", e))?,
+        Some(PsbtEncoding::Hex) => hex::decode(&String::from_utf8_lossy(data))?,
+        None => return Err(Error::psbt("not a PSBT")),
+    };
+    from_bytes(&bytes)
+}
+
+/// Parse a base64 or hex PSBT
+pub fn from_string(data: &str) -> Result<Psbt, Error> {
+    decode(data.as_bytes())
+}
+
+/// PSBT version, v2 is defined in BIP370
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PsbtVersion {
+    V0,
+    V2,
+}
+
+// BIP174/BIP370 key types
+const GLOBAL_UNSIGNED_TX: u8 = 0x00;
+const GLOBAL_TX_VERSION: u8 = 0x02;
+const GLOBAL_FALLBACK_LOCKTIME: u8 = 0x03;
+const GLOBAL_INPUT_COUNT: u8 = 0x04;
+const GLOBAL_OUTPUT_COUNT: u8 = 0x05;
+const GLOBAL_TX_MODIFIABLE: u8 = 0x06;
+const GLOBAL_VERSION: u8 = 0xfb;
+const IN_PREVIOUS_TXID: u8 = 0x0e;
+const IN_OUTPUT_INDEX: u8 = 0x0f;
+const IN_SEQUENCE: u8 = 0x10;
+const IN_REQUIRED_TIME_LOCKTIME: u8 = 0x11;
+const IN_REQUIRED_HEIGHT_LOCKTIME: u8 = 0x12;
+const OUT_AMOUNT: u8 = 0x03;
+const OUT_SCRIPT: u8 = 0x04;
+
+/// Locktimes below are block heights, above are timestamps
+const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+
+/// A key-value map, keys include their type byte
+type Map = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// A PSBT split in its key-value maps, without interpreting them
+struct RawPsbt {
+    global: Map,
+    inputs: Vec<Map>,
+    outputs: Vec<Map>,
+}
+
+fn invalid() -> Error {
+    Error::psbt("malformed key-value map")
+}
+
+fn read_compact(data: &[u8], position: &mut usize) -> Result<u64, Error> {
+    let take = |position: &mut usize, len: usize| -> Result<&[u8], Error> {
+        let end = position.checked_add(len).filter(|end| *end <= data.len()).ok_or_else(invalid)?;
+        let out = &data[*position..end];
+        *position = end;
+        Ok(out)
+    };
+    let first = take(position, 1)?[0];
+    Ok(match first {
+        0xfd => u16::from_le_bytes(take(position, 2)?.try_into().unwrap()) as u64,
+        0xfe => u32::from_le_bytes(take(position, 4)?.try_into().unwrap()) as u64,
+        0xff => u64::from_le_bytes(take(position, 8)?.try_into().unwrap()),
+        value => value as u64,
+    })
+}
+
+fn write_compact(out: &mut Vec<u8>, value: u64) {
+    if value < 0xfd {
+        out.push(value as u8);
+    } else if value <= u16::MAX as u64 {
+        out.push(0xfd);
+        out.extend_from_slice(&(value as u16).to_le_bytes());
+    } else if value <= u32::MAX as u64 {
+        out.push(0xfe);
+        out.extend_from_slice(&(value as u32).to_le_bytes());
+    } else {
+        out.push(0xff);
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+fn read_map(data: &[u8], position: &mut usize) -> Result<Map, Error> {
+    let mut map = Vec::new();
+    loop {
+        let key_len = read_compact(data, position)? as usize;
+        if key_len == 0 {
+            return Ok(map);
+        }
+        let key = data.get(*position..position.saturating_add(key_len)).ok_or_else(invalid)?;
+        *position += key_len;
+        let value_len = read_compact(data, position)? as usize;
+        let value = data
+            .get(*position..position.saturating_add(value_len))
+            .ok_or_else(invalid)?;
+        *position += value_len;
+        map.push((key.to_vec(), value.to_vec()));
+    }
+}
+
+fn write_map(out: &mut Vec<u8>, map: &Map) {
+    for (key, value) in map {
+        write_compact(out, key.len() as u64);
+        out.extend_from_slice(key);
+        write_compact(out, value.len() as u64);
+        out.extend_from_slice(value);
+    }
+    out.push(0x00);
+}
+
+fn get(map: &Map, key_type: u8) -> Option<&[u8]> {
+    map.iter()
+        .find(|(key, _)| key.as_slice() == [key_type])
+        .map(|(_, value)| value.as_slice())
+}
+
+fn remove(map: &mut Map, key_types: &[u8]) {
+    map.retain(|(key, _)| !(key.len() == 1 && key_types.contains(&key[0])));
+}
+
+fn get_u32(map: &Map, key_type: u8) -> Result<Option<u32>, Error> {
+    get(map, key_type)
+        .map(|value| value.try_into().map(u32::from_le_bytes).map_err(|_| invalid()))
+        .transpose()
+}
+
+fn get_count(map: &Map, key_type: u8) -> Result<usize, Error> {
+    let value = get(map, key_type).ok_or_else(invalid)?;
+    let mut position = 0;
+    let count = read_compact(value, &mut position)?;
+    usize::try_from(count).map_err(|_| invalid())
+}
+
+impl RawPsbt {
+    fn parse(data: &[u8]) -> Result<RawPsbt, Error> {
+        if !data.starts_with(MAGIC) {
+            return Err(Error::psbt("not a PSBT"));
+        }
+        let mut position = MAGIC.len();
+        let global = read_map(data, &mut position)?;
+        let (input_count, output_count) = match get(&global, GLOBAL_UNSIGNED_TX) {
+            Some(tx) => {
+                let tx: Transaction = consensus::deserialize(tx).map_err(|_| invalid())?;
+                (tx.input.len(), tx.output.len())
+            }
+            None => (
+                get_count(&global, GLOBAL_INPUT_COUNT)?,
+                get_count(&global, GLOBAL_OUTPUT_COUNT)?,
+            ),
+        };
+        // every map takes at least one byte
+        if input_count.saturating_add(output_count) > data.len() - position {
+            return Err(invalid());
+        }
+        let inputs = (0..input_count)
+            .map(|_| read_map(data, &mut position))
+            .collect::<Result<Vec<_>, _>>()?;
+        let outputs = (0..output_count)
+            .map(|_| read_map(data, &mut position))
+            .collect::<Result<Vec<_>, _>>()?;
+        if position != data.len() {
+            return Err(invalid());
+        }
+        Ok(RawPsbt {
+            global,
+            inputs,
+            outputs,
+        })
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        write_map(&mut out, &self.global);
+        self.inputs.iter().for_each(|map| write_map(&mut out, map));
+        self.outputs.iter().for_each(|map| write_map(&mut out, map));
+        out
+    }
+
+    fn version(&self) -> Result<PsbtVersion, Error> {
+        match get_u32(&self.global, GLOBAL_VERSION)? {
+            None | Some(0) => Ok(PsbtVersion::V0),
+            Some(2) => Ok(PsbtVersion::V2),
+            Some(v) => Err(Error::psbt(&format!("unsupported version {}", v))),
+        }
+    }
+
+    /// Rebuild the unsigned transaction of a v2 PSBT and strip the v2 fields
+    fn into_v0(mut self) -> Result<RawPsbt, Error> {
+        let version = get_u32(&self.global, GLOBAL_TX_VERSION)?.ok_or_else(invalid)?;
+        let fallback = get_u32(&self.global, GLOBAL_FALLBACK_LOCKTIME)?.unwrap_or(0);
+
+        // (height, time) requirements of the inputs having one
+        let mut locktimes = Vec::new();
+        let mut input = Vec::with_capacity(self.inputs.len());
+        for map in &self.inputs {
+            let txid = get(map, IN_PREVIOUS_TXID).ok_or_else(invalid)?;
+            let txid = Txid::from_slice(txid).map_err(|_| invalid())?;
+            let vout = get_u32(map, IN_OUTPUT_INDEX)?.ok_or_else(invalid)?;
+            let sequence = get_u32(map, IN_SEQUENCE)?.unwrap_or(u32::MAX);
+            let height = get_u32(map, IN_REQUIRED_HEIGHT_LOCKTIME)?;
+            let time = get_u32(map, IN_REQUIRED_TIME_LOCKTIME)?;
+            if height.map_or(false, |h| h == 0 || h >= LOCKTIME_THRESHOLD)
+                || time.map_or(false, |t| t < LOCKTIME_THRESHOLD)
+            {
+                return Err(Error::psbt("invalid required locktime"));
+            }
+            if height.is_some() || time.is_some() {
+                locktimes.push((height, time));
+            }
+            input.push(TxIn {
+                previous_output: OutPoint::new(txid, vout),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence(sequence),
+                witness: Witness::new(),
+            });
+        }
+        let mut output = Vec::with_capacity(self.outputs.len());
+        for map in &self.outputs {
+            let amount = get(map, OUT_AMOUNT).ok_or_else(invalid)?;
+            let amount = u64::from_le_bytes(amount.try_into().map_err(|_| invalid())?);
+            let script = get(map, OUT_SCRIPT).ok_or_else(invalid)?;
+            output.push(TxOut {
+                value: amount,
+                script_pubkey: ScriptBuf::from(script.to_vec()),
+            });
+        }
+        // BIP370: height based locktime is preferred when every input with a
+        // requirement supports it, then time based one
+        let heights: Option<Vec<u32>> = locktimes.iter().map(|(height, _)| *height).collect();
+        let times: Option<Vec<u32>> = locktimes.iter().map(|(_, time)| *time).collect();
+        let lock_time = match (heights, times) {
+            _ if locktimes.is_empty() => fallback,
+            (Some(heights), _) => heights.into_iter().max().unwrap_or(fallback),
+            (None, Some(times)) => times.into_iter().max().unwrap_or(fallback),
+            (None, None) => return Err(Error::psbt("inputs have no common locktime type")),
+        };
+        let tx = Transaction {
+            version: version as i32,
+            lock_time: LockTime::from_consensus(lock_time),
+            input,
+            output,
+        };
+
+        remove(
+            &mut self.global,
+            &[
+                GLOBAL_TX_VERSION,
+                GLOBAL_FALLBACK_LOCKTIME,
+                GLOBAL_INPUT_COUNT,
+                GLOBAL_OUTPUT_COUNT,
+                GLOBAL_TX_MODIFIABLE,
+                GLOBAL_VERSION,
+            ],
+        );
+        self.global
+            .insert(0, (vec![GLOBAL_UNSIGNED_TX], consensus::serialize(&tx)));
+        for map in self.inputs.iter_mut() {
+            remove(
+                map,
+                &[
+                    IN_PREVIOUS_TXID,
+                    IN_OUTPUT_INDEX,
+                    IN_SEQUENCE,
+                    IN_REQUIRED_TIME_LOCKTIME,
+                    IN_REQUIRED_HEIGHT_LOCKTIME,
+                ],
+            );
+        }
+        for map in self.outputs.iter_mut() {
+            remove(map, &[OUT_AMOUNT, OUT_SCRIPT]);
+        }
+        Ok(self)
+    }
+}
+
+/// Parse a binary PSBT of any supported version, v2 is converted to v0
+pub fn from_bytes(data: &[u8]) -> Result<(Psbt, PsbtVersion), Error> {
+    let raw = RawPsbt::parse(data)?;
+    let version = raw.version()?;
+    let bytes = match version {
+        PsbtVersion::V0 => data.to_vec(),
+        PsbtVersion::V2 => raw.into_v0()?.serialize(),
+    };
+    let psbt = Psbt::deserialize(&bytes).map_err(|e| Error::psbt_with("cannot deserialize", e))?;
+    Ok((psbt, version))
+}
+
+/// Serialize a PSBT as v2 (BIP370)
+pub fn to_v2(psbt: &Psbt) -> Result<Vec<u8>, Error> {
+    let mut raw = RawPsbt::parse(&psbt.serialize())?;
+    let tx = &psbt.unsigned_tx;
+
+    remove(&mut raw.global, &[GLOBAL_UNSIGNED_TX, GLOBAL_VERSION]);
+    let mut input_count = Vec::new();
+    write_compact(&mut input_count, tx.input.len() as u64);
+    let mut output_count = Vec::new();
+    write_compact(&mut output_count, tx.output.len() as u64);
+    let v2_fields = vec![
+        (vec![GLOBAL_TX_VERSION], tx.version.to_le_bytes().to_vec()),
+        (
+            vec![GLOBAL_FALLBACK_LOCKTIME],
+            tx.lock_time.to_consensus_u32().to_le_bytes().to_vec(),
+        ),
+        (vec![GLOBAL_INPUT_COUNT], input_count),
+        (vec![GLOBAL_OUTPUT_COUNT], output_count),
+    ];
+    raw.global.splice(0..0, v2_fields);
+    raw.global.push((vec![GLOBAL_VERSION], 2u32.to_le_bytes().to_vec()));
+
+    for (map, txin) in raw.inputs.iter_mut().zip(tx.input.iter()) {
+        let outpoint = txin.previous_output;
+        map.push((vec![IN_PREVIOUS_TXID], outpoint.txid.to_byte_array().to_vec()));
+        map.push((vec![IN_OUTPUT_INDEX], outpoint.vout.to_le_bytes().to_vec()));
+        map.push((vec![IN_SEQUENCE], txin.sequence.0.to_le_bytes().to_vec()));
+    }
+    for (map, txout) in raw.outputs.iter_mut().zip(tx.output.iter()) {
+        map.push((vec![OUT_AMOUNT], txout.value.to_le_bytes().to_vec()));
+        map.push((vec![OUT_SCRIPT], txout.script_pubkey.to_bytes()));
+    }
+    Ok(raw.serialize())
+}
+
+/// Serialize a PSBT in the given version
+pub fn serialize(psbt: &Psbt, version: PsbtVersion) -> Result<Vec<u8>, Error> {
+    match version {
+        PsbtVersion::V0 => Ok(psbt.serialize()),
+        PsbtVersion::V2 => to_v2(psbt),
+    }
+}
+
+/// Convert a binary PSBT to the given version
+pub fn convert(data: &[u8], version: PsbtVersion) -> Result<Vec<u8>, Error> {
+    let (psbt, _) = from_bytes(data)?;
+    serialize(&psbt, version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // BIP174 test vector: one P2PKH input, outputs are empty
+    const PSBT_HEX: &str = "70736274ff0100750200000001268171371edff285e937adeea4b37b78000c0566cbb3ad64641713ca42171bf60000000000feffffff02d3dff505000000001976a914d0c59903c5bac2868760e90fd521a4665aa7652088ac00e1f5050000000017a9143545e6e33b832c47050f24d3eeb93c9c03948bc787b32e1300000100fda5010100000000010289a3c71eab4d20e0371bbba4cc698fa295c9463afa2e397f8533ccb62f9567e50100000017160014be18d152a9b012039daf3da7de4f53349eecb985ffffffff86f8aa43a71dff1448893a530a7237ef6b4608bbb2dd2d0171e63aec6a4890b40100000017160014fe3e9ef1a745e974d902c4355943abcb34bd5353ffffffff0200c2eb0b000000001976a91485cff1097fd9e008bb34af709c62197b38978a4888ac72fef84e2c00000017a914339725ba21efd62ac753a9bcd067d6c7a6a39d05870247304402202712be22e0270f394f568311dc7ca9a68970b8025fdd3b240229f07f8a5f3a240220018b38d7dcd314e734c9276bd6fb40f673325bc4baa144c800d2f2f02db2765c012103d2e15674941bad4a996372cb87e1856d3652606d98562fe39c5e9e7e413f210502483045022100d12b852d85dcd961d2f5f4ab660654df6eedcc794c0c33ce5cc309ffb5fce58d022067338a8e0e1725c197fb1a88af59f51e44e4255b20167c8684031c05d1f2592a01210223b72beef0965d10be0778efecd61fcac6f79a4ea169393380734464f84f2ab300000000000000";
+    const PSBT_BASE64: &str = "cHNidP8BAHUCAAAAASaBcTce3/KF6Tet7qSze3gADAVmy7OtZGQXE8pCFxv2AAAAAAD+////AtPf9QUAAAAAGXapFNDFmQPFusKGh2DpD9UhpGZap2UgiKwA4fUFAAAAABepFDVF5uM7gyxHBQ8k0+65PJwDlIvHh7MuEwAAAQD9pQEBAAAAAAECiaPHHqtNIOA3G7ukzGmPopXJRjr6Ljl/hTPMti+VZ+UBAAAAFxYAFL4Y0VKpsBIDna89p95PUzSe7LmF/////4b4qkOnHf8USIk6UwpyN+9rRgi7st0tAXHmOuxqSJC0AQAAABcWABT+Pp7xp0XpdNkCxDVZQ6vLNL1TU/////8CAMLrCwAAAAAZdqkUhc/xCX/Z4Ai7NK9wnGIZeziXikiIrHL++E4sAAAAF6kUM5cluiHv1irHU6m80GfWx6ajnQWHAkcwRAIgJxK+IuAnDzlPVoMR3HyppolwuAJf3TskAinwf4pfOiQCIAGLONfc0xTnNMkna9b7QPZzMlvEuqFEyADS8vAtsnZcASED0uFWdJQbrUqZY3LLh+GFbTZSYG2YVi/jnF6efkE/IQUCSDBFAiEA0SuFLYXc2WHS9fSrZgZU327tzHlMDDPOXMMJ/7X85Y0CIGczio4OFyXBl/saiK9Z9R5E5CVbIBZ8hoQDHAXR8lkqASECI7cr7vCWXRC+B3jv7NYfysb3mk6haTkzgHNEZPhPKrMAAAAAAAAA";
+
+    #[test]
+    fn bip174_encodings() {
+        let bytes = hex::decode(PSBT_HEX).unwrap();
+        let psbt = decode(&bytes).unwrap();
+        assert_eq!(psbt.inputs.len(), 1);
+        assert_eq!(psbt.outputs.len(), 2);
+        assert_eq!(from_string(PSBT_HEX).unwrap(), psbt);
+        assert_eq!(from_string(PSBT_BASE64).unwrap(), psbt);
+        assert_eq!(
+            encode(&psbt, PsbtEncoding::Base64).unwrap(),
+            PSBT_BASE64.as_bytes()
+        );
+        assert_eq!(
+            encode(&psbt, PsbtEncoding::Hex).unwrap(),
+            PSBT_HEX.as_bytes()
+        );
+        assert_eq!(encode(&psbt, PsbtEncoding::Binary).unwrap(), bytes);
+    }
+
+    #[test]
+    fn detect_encoding() {
+        let bytes = hex::decode(PSBT_HEX).unwrap();
+        assert_eq!(detect(&bytes), Some(PsbtEncoding::Binary));
+        assert_eq!(detect(PSBT_HEX.as_bytes()), Some(PsbtEncoding::Hex));
+        assert_eq!(
+            detect(PSBT_HEX.to_uppercase().as_bytes()),
+            Some(PsbtEncoding::Hex)
+        );
+        assert_eq!(detect(PSBT_BASE64.as_bytes()), Some(PsbtEncoding::Base64));
+        let padded = format!("\n {}\n", PSBT_BASE64);
+        assert_eq!(detect(padded.as_bytes()), Some(PsbtEncoding::Base64));
+        assert_eq!(from_string(&padded).unwrap(), decode(&bytes).unwrap());
+        assert_eq!(detect(b"psbt"), None);
+        assert_eq!(detect(&[0xff, 0xfe]), None);
+    }
+
+    #[test]
+    fn invalid_psbt() {
+        assert!(from_string("hello").is_err());
+        assert!(from_string(&PSBT_HEX[..PSBT_HEX.len() - 2]).is_err());
+        assert!(from_string(&PSBT_BASE64.replace('A', "!")).is_err());
+        assert!(decode(MAGIC).is_err());
+        // trailing data
+        let mut bytes = hex::decode(PSBT_HEX).unwrap();
+        bytes.push(0x00);
+        assert!(decode(&bytes).is_err());
+    }
+
+    /// A BIP370 PSBT spending one output per (height, time) requirement
+    fn v2(fallback: Option<u32>, locktimes: &[(Option<u32>, Option<u32>)]) -> Vec<u8> {
+        let u32_field = |key: u8, value: u32| (vec![key], value.to_le_bytes().to_vec());
+        let mut global = vec![
+            u32_field(GLOBAL_TX_VERSION, 2),
+            (vec![GLOBAL_INPUT_COUNT], vec![locktimes.len() as u8]),
+            (vec![GLOBAL_OUTPUT_COUNT], vec![1]),
+            u32_field(GLOBAL_VERSION, 2),
+        ];
+        if let Some(fallback) = fallback {
+            global.push(u32_field(GLOBAL_FALLBACK_LOCKTIME, fallback));
+        }
+        let inputs = locktimes
+            .iter()
+            .enumerate()
+            .map(|(vout, (height, time))| {
+                let mut map = vec![
+                    (vec![IN_PREVIOUS_TXID], vec![0x11; 32]),
+                    u32_field(IN_OUTPUT_INDEX, vout as u32),
+                    u32_field(IN_SEQUENCE, 0xfffffffe),
+                ];
+                if let Some(height) = height {
+                    map.push(u32_field(IN_REQUIRED_HEIGHT_LOCKTIME, *height));
+                }
+                if let Some(time) = time {
+                    map.push(u32_field(IN_REQUIRED_TIME_LOCKTIME, *time));
+                }
+                map
+            })
+            .collect();
+        let output = vec![
+            (vec![OUT_AMOUNT], 10_000u64.to_le_bytes().to_vec()),
+            (
+                vec![OUT_SCRIPT],
+                hex::decode("0014d0c59903c5bac2868760e90fd521a4665aa76520").unwrap(),
+            ),
+        ];
+        RawPsbt {
+            global,
+            inputs,
+            outputs: vec![output],
+        }
+        .serialize()
+    }
+
+    fn lock_time(data: &[u8]) -> Result<u32, Error> {
+        let (psbt, version) = from_bytes(data)?;
+        assert_eq!(version, PsbtVersion::V2);
+        Ok(psbt.unsigned_tx.lock_time.to_consensus_u32())
+    }
+
+    #[test]
+    fn bip370_unsigned_tx() {
+        let (psbt, version) = from_bytes(&v2(None, &[(None, None), (None, None)])).unwrap();
+        assert_eq!(version, PsbtVersion::V2);
+        let tx = &psbt.unsigned_tx;
+        assert_eq!(tx.version, 2);
+        assert_eq!(tx.input.len(), 2);
+        assert_eq!(tx.input[1].previous_output.vout, 1);
+        assert_eq!(
+            tx.input[1].previous_output.txid,
+            Txid::from_byte_array([0x11; 32])
+        );
+        assert_eq!(tx.input[0].sequence, Sequence(0xfffffffe));
+        assert_eq!(tx.output[0].value, 10_000);
+        // the v2 fields are not kept in the maps
+        assert!(psbt.inputs[0].unknown.is_empty());
+        assert!(psbt.unknown.is_empty());
+    }
+
+    // BIP370 locktime determination
+    #[test]
+    fn bip370_locktime() {
+        const TIME: u32 = 600_000_000;
+        // no requirement: fallback, 0 by default
+        assert_eq!(lock_time(&v2(None, &[(None, None)])).unwrap(), 0);
+        assert_eq!(lock_time(&v2(Some(100), &[(None, None)])).unwrap(), 100);
+        // every input with a requirement supports height
+        let both = v2(
+            Some(100),
+            &[(Some(1000), None), (Some(2000), Some(TIME)), (None, None)],
+        );
+        assert_eq!(lock_time(&both).unwrap(), 2000);
+        // an input only supports time
+        let time = v2(None, &[(None, Some(TIME)), (Some(2000), Some(TIME + 1))]);
+        assert_eq!(lock_time(&time).unwrap(), TIME + 1);
+        // no common type
+        assert!(lock_time(&v2(None, &[(Some(1000), None), (None, Some(TIME))])).is_err());
+        // out of range requirements
+        assert!(lock_time(&v2(None, &[(Some(0), None)])).is_err());
+        assert!(lock_time(&v2(None, &[(Some(TIME), None)])).is_err());
+        assert!(lock_time(&v2(None, &[(None, Some(1000))])).is_err());
+    }
+
+    #[test]
+    fn v0_v2_conversion() {
+        let psbt = decode(&hex::decode(PSBT_HEX).unwrap()).unwrap();
+        let data = to_v2(&psbt).unwrap();
+        assert_eq!(from_bytes(&data).unwrap(), (psbt.clone(), PsbtVersion::V2));
+        assert_eq!(
+            convert(&data, PsbtVersion::V0).unwrap(),
+            hex::decode(PSBT_HEX).unwrap()
+        );
+        assert_eq!(
+            convert(&hex::decode(PSBT_HEX).unwrap(), PsbtVersion::V2).unwrap(),
+            data
+        );
+        let base64 = encode_version(&psbt, PsbtEncoding::Base64, PsbtVersion::V2).unwrap();
+        assert_eq!(decode_versioned(&base64).unwrap(), (psbt, PsbtVersion::V2));
+        // unsupported version
+        let mut raw = RawPsbt::parse(&data).unwrap();
+        remove(&mut raw.global, &[GLOBAL_VERSION]);
+        raw.global
+            .push((vec![GLOBAL_VERSION], 3u32.to_le_bytes().to_vec()));
+        assert!(from_bytes(&raw.serialize()).is_err());
+        // v2 without its input fields
+        let mut raw = RawPsbt::parse(&data).unwrap();
+        remove(&mut raw.inputs[0], &[IN_PREVIOUS_TXID]);
+        assert!(from_bytes(&raw.serialize()).is_err());
+    }
+}
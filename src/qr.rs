@@ -1,32 +1,272 @@
-use crate::MultiQRElement;
+use crate::Error;
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// A resource cap of `DecodeLimits`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    FrameLength,
+    Parts,
+    Payload,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Limit::FrameLength => write!(f, "Frame length"),
+            Limit::Parts => write!(f, "Number of parts"),
+            Limit::Payload => write!(f, "Payload size"),
+        }
+    }
+}
+
+/// Resource caps of a decoder, frames may come from an untrusted party
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DecodeLimits {
+    /// Max number of parts of a MultiQR
+    pub max_parts: usize,
+    /// Max size (bytes) of the reassembled payload
+    pub max_payload: usize,
+    /// Max length of a single frame
+    pub max_frame_len: usize,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        DecodeLimits {
+            max_parts: 1_000,
+            max_payload: 1 << 20,
+            // numeric capacity of a version 40 QRCode
+            max_frame_len: 7_089,
+        }
+    }
+}
+
+impl DecodeLimits {
+    pub fn check_frame(&self, frame: &str) -> Result<(), Error> {
+        if frame.len() > self.max_frame_len {
+            return Err(Error::LimitExceeded {
+                limit: Limit::FrameLength,
+                value: frame.len(),
+                max: self.max_frame_len,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn check_parts(&self, parts: usize) -> Result<(), Error> {
+        if parts > self.max_parts {
+            return Err(Error::LimitExceeded {
+                limit: Limit::Parts,
+                value: parts,
+                max: self.max_parts,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn check_payload(&self, len: usize) -> Result<(), Error> {
+        if len > self.max_payload {
+            return Err(Error::LimitExceeded {
+                limit: Limit::Payload,
+                value: len,
+                max: self.max_payload,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// What a decoder does with a frame of another sequence than the one being
+/// scanned, e.g. the user points the scanner at another animation
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ResetPolicy {
+    /// Return an error, the frame is ignored
+    #[default]
+    Error,
+    /// Drop the parts received and scan the new sequence
+    Restart,
+    /// Scan both sequences, the first one completed is the result
+    KeepBoth,
+}
+
+/// Chunks of a multi-part payload, shared by the multi-part formats
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ChunkStore {
+    chunks: Vec<Option<Vec<u8>>>,
+    /// chunks received & their total size, maintained on insert
+    received: usize,
+    len: usize,
+    /// indexes of the chunks not yet received, maintained on insert
+    missing: BTreeSet<usize>,
+}
+
+impl ChunkStore {
+    /// An empty store expecting `total` chunks
+    pub fn new(total: usize) -> ChunkStore {
+        ChunkStore {
+            chunks: vec![None; total],
+            received: 0,
+            len: 0,
+            missing: (0..total).collect(),
+        }
+    }
+
+    /// Split a binary payload in chunks of at most `max_len` bytes
+    pub fn from_bytes(data: &[u8], max_len: usize) -> ChunkStore {
+        let chunks: Vec<Option<Vec<u8>>> = data
+            .chunks(max_len.max(1))
+            .map(|chunk| Some(chunk.to_vec()))
+            .collect();
+        ChunkStore {
+            received: chunks.len(),
+            len: data.len(),
+            chunks,
+            missing: BTreeSet::new(),
+        }
+    }
+
+    /// Split a text payload in chunks of at most `max_len` bytes, a multi-byte
+    /// character is never split
+    pub fn from_text(data: &str, max_len: usize) -> ChunkStore {
+        let max_len = max_len.max(1);
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        while start < data.len() {
+            let mut end = (start + max_len).min(data.len());
+            while !data.is_char_boundary(end) {
+                end -= 1;
+            }
+            // a character longer than max_len
+            if end == start {
+                end = start + data[start..].chars().next().unwrap().len_utf8();
+            }
+            chunks.push(Some(data.as_bytes()[start..end].to_vec()));
+            start = end;
+        }
+        ChunkStore {
+            received: chunks.len(),
+            len: data.len(),
+            chunks,
+            missing: BTreeSet::new(),
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Number of chunks received
+    pub fn received(&self) -> usize {
+        self.received
+    }
+
+    /// Size (bytes) of the chunks received
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Indexes (0 based) of the chunks not yet received, in order
+    pub fn missing(&self) -> Vec<usize> {
+        self.missing.iter().copied().collect()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&[u8]> {
+        self.chunks.get(index)?.as_deref()
+    }
+
+    /// Whether the chunk (0 based index) was already received
+    pub fn contains(&self, index: usize) -> bool {
+        matches!(self.chunks.get(index), Some(Some(_)))
+    }
+
+    /// Check a chunk (0 based index) of a sequence of `total` chunks belongs to
+    /// this store
+    pub fn check(&self, index: usize, chunk: &[u8], total: usize) -> Result<(), Error> {
+        if self.total() != total {
+            return Err(Error::TotalMismatch {
+                expected: self.total(),
+                found: total,
+            });
+        }
+        match self.chunks.get(index) {
+            None => Err(Error::IndexOutOfRange {
+                index: index + 1,
+                total,
+            }),
+            Some(Some(value)) if value.as_slice() != chunk => {
+                Err(Error::ConflictingDuplicate { index: index + 1 })
+            }
+            Some(_) => Ok(()),
+        }
+    }
+
+    /// Store a chunk (0 based index), return false if it was already received
+    pub fn insert(&mut self, index: usize, chunk: &[u8]) -> Result<bool, Error> {
+        let total = self.chunks.len();
+        let slot = self.chunks.get_mut(index).ok_or(Error::IndexOutOfRange {
+            index: index + 1,
+            total,
+        })?;
+        match slot {
+            None => {
+                *slot = Some(chunk.to_vec());
+                self.received += 1;
+                self.len += chunk.len();
+                self.missing.remove(&index);
+                Ok(true)
+            }
+            Some(value) if value.as_slice() == chunk => Ok(false),
+            Some(_) => Err(Error::ConflictingDuplicate { index: index + 1 }),
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        !self.chunks.is_empty() && self.received == self.chunks.len()
+    }
+
+    /// Concatenate the chunks, None until all are received
+    pub fn assemble(&self) -> Option<Vec<u8>> {
+        if !self.is_complete() {
+            return None;
+        }
+        let mut out = Vec::with_capacity(self.len);
+        for chunk in self.chunks.iter().flatten() {
+            out.extend_from_slice(chunk);
+        }
+        Some(out)
+    }
+}
 
 /// A Generic container for QRCode data
 #[derive(Debug, Clone, PartialEq)]
 pub struct QRData {
-    pub data: String,
+    /// whole payload
+    pub data: Vec<u8>,
+    /// the payload is sent as byte mode QRCode
+    pub binary: bool,
     pub total_sequences: usize,
-    sequences_count: usize,
     pub is_completed: bool,
     pub is_loaded: bool,
-    is_init: bool,
     current: usize,
-    pub data_stack: Vec<Option<String>>,
-    pub chunks: Vec<Option<MultiQRElement>>,
+    pub chunks: ChunkStore,
     pub max_len: Option<usize>,
 }
 
 impl QRData {
     pub fn new() -> QRData {
         QRData {
-            data: String::new(),
+            data: vec![],
+            binary: false,
             total_sequences: 0,
-            sequences_count: 0,
             is_completed: false,
             is_loaded: false,
-            is_init: false,
             current: 0,
-            data_stack: vec![],
-            chunks: vec![],
+            chunks: ChunkStore::default(),
             max_len: None,
         }
     }
@@ -37,53 +277,257 @@ impl QRData {
     ///
     pub fn data_init(&mut self, sequences: usize) {
         self.total_sequences = sequences;
-        self.sequences_count = 0;
-        self.current = sequences;
+        self.chunks = ChunkStore::new(sequences);
+        self.current = 0;
+        self.is_completed = false;
         self.is_loaded = true;
     }
 
     ///  Append data from a single QRCode received without formatting
     ///
-    pub fn receive(&mut self, data: &String) -> bool {
-        self.data = data.clone();
-        self.sequences_count = 1;
+    pub fn receive(&mut self, data: &[u8]) -> bool {
+        self.data = data.to_vec();
+        self.chunks = ChunkStore::from_bytes(data, data.len());
         self.total_sequences = 1;
-        self.current = 1;
+        self.current = 0;
         self.is_loaded = true;
         true
     }
 
-    pub fn check_complete(&mut self) {
-        let mut fill_sequences: usize = 0;
-        for sequence in &self.data_stack {
-            if let Some(_result) = sequence {
-                fill_sequences += 1;
-            }
-        }
-        self.sequences_count = fill_sequences;
-        if fill_sequences == self.total_sequences {
-            self.is_completed = true;
+    /// Load a payload to send, split in chunks of `max_len` if set
+    pub fn load(&mut self, data: &[u8], binary: bool) -> Result<(), Error> {
+        let max_len = self.max_len.unwrap_or(data.len());
+        self.chunks = if binary {
+            ChunkStore::from_bytes(data, max_len)
+        } else {
+            let text = std::str::from_utf8(data)
+                .map_err(|_| Error::EncodingError("Text payload is not UTF-8!".to_string()))?;
+            ChunkStore::from_text(text, max_len)
+        };
+        self.data = data.to_vec();
+        self.binary = binary;
+        self.total_sequences = self.chunks.total();
+        self.current = 0;
+        self.is_loaded = true;
+        Ok(())
+    }
+
+    /// The payload as text, None for a binary payload
+    pub fn text(&self) -> Option<&str> {
+        if self.binary {
+            return None;
         }
+        std::str::from_utf8(&self.data).ok()
     }
 
+    pub fn check_complete(&mut self) {
+        self.is_completed = self.chunks.is_complete();
+    }
+
+    /// Next frame of a text payload, binary payloads are read with
+    /// `QREncoder::next_bytes()`
     pub fn next(&mut self) -> Result<String, String> {
+        if self.binary {
+            return Err("binary payload, use next_bytes()!".to_string());
+        }
         if self.is_loaded {
             if self.current >= self.total_sequences {
                 self.current = 0;
             }
-            if let Some(result) = &self.data_stack[self.current] {
+            if let Some(result) = self.chunks.get(self.current) {
                 let a = (self.current + 1).to_string();
                 let b = self.total_sequences.to_string();
                 let c = "p".to_string() + &a + &"of".to_string() + &b + &" ".to_string();
-                let d = result;
-                let out = c + d;
+                let d = String::from_utf8_lossy(result);
+                let out = c + &d;
                 self.current += 1;
                 Ok(out)
             } else {
-                Err("data_stack element is None".to_string())
+                Err("chunk is None".to_string())
             }
         } else {
             Err("QRData not yet loaded!".to_string())
         }
     }
 }
+
+/// QRCode encoding mode of a payload
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QRMode {
+    Numeric,
+    Alphanumeric,
+    Byte,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_bytes() {
+        let store = ChunkStore::from_bytes(b"abcdefgh", 3);
+        assert_eq!(store.total(), 3);
+        assert_eq!(store.get(2), Some(&b"gh"[..]));
+        assert_eq!(store.len(), 8);
+        assert!(store.is_complete());
+        assert_eq!(store.assemble().unwrap(), b"abcdefgh");
+        // a zero max length is taken as 1
+        assert_eq!(ChunkStore::from_bytes(b"ab", 0).total(), 2);
+    }
+
+    #[test]
+    fn split_text() {
+        // "é" is 2 bytes, never split
+        let store = ChunkStore::from_text("aébcd", 2);
+        let chunks: Vec<&[u8]> = (0..store.total()).map(|i| store.get(i).unwrap()).collect();
+        assert_eq!(chunks, vec![&b"a"[..], "é".as_bytes(), b"bc", b"d"]);
+        // a character longer than max_len
+        let store = ChunkStore::from_text("€a", 1);
+        assert_eq!(store.get(0), Some("€".as_bytes()));
+        assert_eq!(store.assemble().unwrap(), "€a".as_bytes());
+    }
+
+    #[test]
+    fn insert_chunks() {
+        let mut store = ChunkStore::new(3);
+        assert!(store.is_empty());
+        assert!(!store.is_complete());
+        assert_eq!(store.assemble(), None);
+        assert!(store.insert(2, b"gh").unwrap());
+        assert!(store.insert(0, b"abc").unwrap());
+        // duplicate
+        assert!(!store.insert(0, b"abc").unwrap());
+        assert!(matches!(
+            store.insert(0, b"xyz"),
+            Err(Error::ConflictingDuplicate { index: 1 })
+        ));
+        assert!(matches!(
+            store.insert(3, b"x"),
+            Err(Error::IndexOutOfRange { index: 4, total: 3 })
+        ));
+        assert_eq!(store.received(), 2);
+        assert_eq!(store.len(), 5);
+        assert!(store.contains(0) && !store.contains(1) && !store.contains(3));
+        assert!(store.insert(1, b"def").unwrap());
+        assert_eq!(store.assemble().unwrap(), b"abcdefgh");
+        // an empty store is never complete
+        assert!(!ChunkStore::new(0).is_complete());
+    }
+
+    #[test]
+    fn missing_chunks() {
+        let mut store = ChunkStore::new(4);
+        assert_eq!(store.missing(), vec![0, 1, 2, 3]);
+        store.insert(2, b"c").unwrap();
+        store.insert(0, b"a").unwrap();
+        assert_eq!(store.missing(), vec![1, 3]);
+        // duplicates and errors leave the set untouched
+        store.insert(0, b"a").unwrap();
+        assert!(store.insert(0, b"x").is_err());
+        assert!(store.insert(4, b"x").is_err());
+        assert_eq!(store.missing(), vec![1, 3]);
+        store.insert(3, b"d").unwrap();
+        store.insert(1, b"b").unwrap();
+        assert!(store.missing().is_empty());
+        assert!(store.is_complete());
+        assert!(ChunkStore::from_text("abcd", 1).missing().is_empty());
+        assert!(ChunkStore::default().missing().is_empty());
+    }
+
+    #[test]
+    fn check_chunks() {
+        let mut store = ChunkStore::new(2);
+        store.insert(0, b"ab").unwrap();
+        assert!(store.check(0, b"ab", 2).is_ok());
+        assert!(store.check(1, b"cd", 2).is_ok());
+        assert!(matches!(
+            store.check(1, b"cd", 3),
+            Err(Error::TotalMismatch {
+                expected: 2,
+                found: 3
+            })
+        ));
+        assert!(matches!(
+            store.check(0, b"xy", 2),
+            Err(Error::ConflictingDuplicate { index: 1 })
+        ));
+        assert!(matches!(
+            store.check(2, b"ef", 2),
+            Err(Error::IndexOutOfRange { index: 3, total: 2 })
+        ));
+    }
+
+    #[test]
+    fn qrdata_frames() {
+        let mut data = QRData::new();
+        assert!(data.next().is_err());
+        data.max_len = Some(4);
+        data.load(b"abcdefghij", false).unwrap();
+        let frames: Vec<String> = (0..4).map(|_| data.next().unwrap()).collect();
+        // frames loop
+        assert_eq!(
+            frames,
+            vec!["p1of3 abcd", "p2of3 efgh", "p3of3 ij", "p1of3 abcd"]
+        );
+        assert_eq!(data.text(), Some("abcdefghij"));
+        assert!(data.load(&[0xff, 0xfe], false).is_err());
+        data.load(&[0xff, 0xfe], true).unwrap();
+        assert_eq!(data.text(), None);
+        // binary frames are not text
+        assert!(data.next().is_err());
+    }
+
+    #[test]
+    fn decode_limits() {
+        let limits = DecodeLimits {
+            max_parts: 2,
+            max_payload: 4,
+            max_frame_len: 3,
+        };
+        assert!(limits.check_frame("abc").is_ok());
+        assert!(matches!(
+            limits.check_frame("abcd"),
+            Err(Error::LimitExceeded {
+                limit: Limit::FrameLength,
+                value: 4,
+                max: 3
+            })
+        ));
+        assert!(limits.check_parts(2).is_ok());
+        assert!(matches!(
+            limits.check_parts(3),
+            Err(Error::LimitExceeded {
+                limit: Limit::Parts,
+                ..
+            })
+        ));
+        assert!(limits.check_payload(4).is_ok());
+        assert!(matches!(
+            limits.check_payload(5),
+            Err(Error::LimitExceeded {
+                limit: Limit::Payload,
+                ..
+            })
+        ));
+        // a version 40 numeric QRCode fits the default limits
+        assert!(DecodeLimits::default()
+            .check_frame(&"1".repeat(7_089))
+            .is_ok());
+    }
+
+    #[test]
+    fn restart_sequence() {
+        assert_eq!(ResetPolicy::default(), ResetPolicy::Error);
+        let mut data = QRData::new();
+        data.data_init(2);
+        data.chunks.insert(0, b"ab").unwrap();
+        data.chunks.insert(1, b"cd").unwrap();
+        data.is_completed = true;
+        // ResetPolicy::Restart drops the parts received
+        data.data_init(3);
+        assert_eq!(data.total_sequences, 3);
+        assert!(!data.is_completed);
+        assert_eq!(data.chunks.missing(), vec![0, 1, 2]);
+        assert!(data.chunks.check(0, b"xy", 3).is_ok());
+    }
+}
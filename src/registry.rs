@@ -0,0 +1,449 @@
+//! Registry of the QR formats known to the auto-detecting decoder and to the
+//! encoder façade, applications can register their own formats next to the
+//! built-in UR, Specter and raw ones
+
+use crate::qr::{DecodeLimits, ResetPolicy};
+use crate::specter::SpecterQR;
+use crate::ur::UrData;
+use crate::{DataType, Decode, Encode, Encoding, Error, OutputType, QREncoder};
+use bitcoin::Network;
+use regex::Regex;
+
+/// A QR format
+pub trait Format {
+    /// Unique name of the format
+    fn name(&self) -> &str;
+    /// Return true if the frame belongs to this format
+    fn detect(&self, frame: &str) -> bool;
+    /// A new decoder for this format
+    fn decoder(&self) -> Box<dyn Decode>;
+    /// An encoder serving `data`, error if the format cannot encode it
+    fn encoder(&self, data: &DataType, max_len: Option<usize>) -> Result<Box<dyn Encode>, Error>;
+}
+
+/// Decoder of a single QRCode carrying raw data
+#[derive(Debug, Clone, Default)]
+pub struct RawQR {
+    data: Option<String>,
+    limits: DecodeLimits,
+}
+
+impl Decode for RawQR {
+    fn pattern() -> &'static str {
+        r"(?s)^.+$"
+    }
+
+    fn is_complete(&self) -> bool {
+        self.data.is_some()
+    }
+
+    fn set_limits(&mut self, limits: DecodeLimits) {
+        self.limits = limits;
+    }
+
+    // a single frame, each one replaces the previous
+    fn set_reset_policy(&mut self, _policy: ResetPolicy) {}
+
+    fn set_network(&mut self, _network: Network) {}
+
+    fn receive(&mut self, data: &str) -> Result<bool, Error> {
+        self.limits.check_frame(data)?;
+        self.limits.check_payload(data.len())?;
+        self.data = Some(data.to_string());
+        Ok(true)
+    }
+
+    fn result(&self) -> Result<DataType, Error> {
+        match &self.data {
+            Some(data) => Ok(DataType::from_string(data)),
+            None => Err(Error::DecodingError("No QRCode received!".to_string())),
+        }
+    }
+}
+
+/// A built-in format, frames are detected with the `pattern()` of its decoder
+struct Builtin {
+    name: &'static str,
+    pattern: Regex,
+    encoding: Encoding,
+    decoder: fn() -> Box<dyn Decode>,
+}
+
+impl Format for Builtin {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn detect(&self, frame: &str) -> bool {
+        self.pattern.is_match(frame)
+    }
+
+    fn decoder(&self) -> Box<dyn Decode> {
+        (self.decoder)()
+    }
+
+    fn encoder(&self, data: &DataType, max_len: Option<usize>) -> Result<Box<dyn Encode>, Error> {
+        Ok(QREncoder::from_data(data, self.encoding, max_len)?)
+    }
+}
+
+/// The formats known to the decoder and encoder façades
+pub struct Registry {
+    formats: Vec<Box<dyn Format>>,
+}
+
+fn builtin(
+    name: &'static str,
+    pattern: &str,
+    encoding: Encoding,
+    decoder: fn() -> Box<dyn Decode>,
+) -> Box<dyn Format> {
+    Box::new(Builtin {
+        name,
+        pattern: Regex::new(pattern).unwrap(),
+        encoding,
+        decoder,
+    })
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Registry {
+            formats: vec![
+                builtin("ur", UrData::pattern(), Encoding::Ur, || Box::new(UrData::new())),
+                builtin("specter", SpecterQR::pattern(), Encoding::Specter, || {
+                    Box::new(SpecterQR::new())
+                }),
+                // matches any frame, tried last
+                builtin("raw", RawQR::pattern(), Encoding::Raw, || Box::new(RawQR::default())),
+            ],
+        }
+    }
+}
+
+impl Registry {
+    /// A registry holding the built-in formats
+    pub fn new() -> Registry {
+        Registry::default()
+    }
+
+    /// Register a format, it is tried before the built-in ones
+    pub fn register(&mut self, format: Box<dyn Format>) -> Result<(), Error> {
+        if self.get(format.name()).is_some() {
+            return Err(Error::ParsingError(format!(
+                "Format {} already registered!",
+                format.name()
+            )));
+        }
+        self.formats.insert(0, format);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Format> {
+        self.formats
+            .iter()
+            .find(|format| format.name() == name)
+            .map(|format| format.as_ref())
+    }
+
+    /// The format a frame belongs to
+    pub fn detect(&self, frame: &str) -> Option<&dyn Format> {
+        self.formats
+            .iter()
+            .find(|format| format.detect(frame))
+            .map(|format| format.as_ref())
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.formats.iter().map(|format| format.name()).collect()
+    }
+}
+
+/// A decoder detecting the format of the first frame received
+pub struct QRDecoder {
+    registry: Registry,
+    format: Option<String>,
+    decoder: Option<Box<dyn Decode>>,
+    limits: DecodeLimits,
+    policy: ResetPolicy,
+    network: Network,
+}
+
+impl QRDecoder {
+    pub fn new(registry: Registry) -> QRDecoder {
+        QRDecoder {
+            registry,
+            format: None,
+            decoder: None,
+            limits: DecodeLimits::default(),
+            policy: ResetPolicy::default(),
+            network: Network::Bitcoin,
+        }
+    }
+
+    /// Resource caps passed to the decoder of the detected format
+    pub fn with_limits(registry: Registry, limits: DecodeLimits) -> QRDecoder {
+        QRDecoder {
+            limits,
+            ..QRDecoder::new(registry)
+        }
+    }
+
+    /// What to do with a frame of another sequence, with `ResetPolicy::Restart`
+    /// a frame of another format restarts the detection
+    pub fn set_reset_policy(&mut self, policy: ResetPolicy) {
+        self.policy = policy;
+        if let Some(decoder) = self.decoder.as_mut() {
+            decoder.set_reset_policy(policy);
+        }
+    }
+
+    /// Network of the decoded keys that do not carry it (UR crypto-eckey,
+    /// crypto-hdkey without coin-info), mainnet by default
+    pub fn set_network(&mut self, network: Network) {
+        self.network = network;
+        if let Some(decoder) = self.decoder.as_mut() {
+            decoder.set_network(network);
+        }
+    }
+
+    /// Name of the detected format
+    pub fn format(&self) -> Option<&str> {
+        self.format.as_deref()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.decoder
+            .as_ref()
+            .map(|decoder| decoder.is_complete())
+            .unwrap_or(false)
+    }
+
+    /// Load a frame, the first one selects the format
+    pub fn receive(&mut self, frame: &str) -> Result<bool, Error> {
+        if self.policy == ResetPolicy::Restart && self.decoder.is_some() {
+            let format = self.registry.detect(frame).map(|format| format.name());
+            if format.is_some() && format != self.format.as_deref() {
+                self.decoder = None;
+            }
+        }
+        if self.decoder.is_none() {
+            // do not run the detection patterns on an oversized frame
+            self.limits.check_frame(frame)?;
+            let format = self
+                .registry
+                .detect(frame)
+                .ok_or_else(|| Error::DecodingError("Unknown QR format!".to_string()))?;
+            self.format = Some(format.name().to_string());
+            let mut decoder = format.decoder();
+            decoder.set_limits(self.limits);
+            decoder.set_reset_policy(self.policy);
+            decoder.set_network(self.network);
+            self.decoder = Some(decoder);
+        }
+        self.decoder.as_mut().unwrap().receive(frame)
+    }
+
+    pub fn result(&self) -> Result<DataType, Error> {
+        match &self.decoder {
+            Some(decoder) => decoder.result(),
+            None => Err(Error::DecodingError("No QRCode received!".to_string())),
+        }
+    }
+}
+
+impl QREncoder {
+    /// encode data with a format of the registry
+    pub fn from_registry(
+        registry: &Registry,
+        name: &str,
+        data: &DataType,
+        max_len: Option<usize>,
+    ) -> Result<Box<Self>, Error> {
+        let format = registry
+            .get(name)
+            .ok_or_else(|| Error::EncodingError(format!("Unknown QR format {}!", name)))?;
+        let encoder = format.encoder(data, max_len)?;
+        let mut out = QREncoder::new();
+        out.load_encoder(OutputType::Custom(name.to_string()), encoder);
+        out.data.max_len = max_len;
+        out.data_type = data.clone();
+        Ok(Box::new(out))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::bip32::ExtendedPubKey as XPub;
+    use std::str::FromStr;
+
+    const XPUB: &str = "xpub6ASuArnXKPbfEwhqN6e3mwBcDTgzisQN1wXN9BJcM47sSikHjJf3UFHKkNAWbWMiGj7Wf5uMash7SyYq527Hqck2AxYysAA7xmALppuCkwQ";
+
+    /// Raw frames behind a `x:` prefix
+    struct Prefixed;
+
+    impl Format for Prefixed {
+        fn name(&self) -> &str {
+            "prefixed"
+        }
+
+        fn detect(&self, frame: &str) -> bool {
+            frame.starts_with("x:")
+        }
+
+        fn decoder(&self) -> Box<dyn Decode> {
+            Box::new(RawQR::default())
+        }
+
+        fn encoder(
+            &self,
+            data: &DataType,
+            max_len: Option<usize>,
+        ) -> Result<Box<dyn Encode>, Error> {
+            Ok(QREncoder::from_data(data, Encoding::Raw, max_len)?)
+        }
+    }
+
+    fn xpub() -> DataType {
+        DataType::Xpub(Some(XPub::from_str(XPUB).unwrap()))
+    }
+
+    #[test]
+    fn builtin_detection() {
+        let registry = Registry::new();
+        assert_eq!(registry.names(), vec!["ur", "specter", "raw"]);
+        let detect = |frame| registry.detect(frame).map(|format| format.name());
+        assert_eq!(
+            detect("UR:BYTES/HDCXLKAHSSQZWFVSLOFZOENTIYBEMTA"),
+            Some("ur")
+        );
+        assert_eq!(detect("p1of2 cHNidP8B"), Some("specter"));
+        assert_eq!(detect(XPUB), Some("raw"));
+        assert_eq!(detect(""), None);
+    }
+
+    #[test]
+    fn register_format() {
+        let mut registry = Registry::new();
+        registry.register(Box::new(Prefixed)).unwrap();
+        assert!(registry.register(Box::new(Prefixed)).is_err());
+        assert_eq!(registry.names()[0], "prefixed");
+        assert_eq!(registry.detect("x:data").unwrap().name(), "prefixed");
+        assert_eq!(registry.detect("data").unwrap().name(), "raw");
+        assert!(registry.get("unknown").is_none());
+
+        let mut encoder = QREncoder::from_registry(&registry, "prefixed", &xpub(), None).unwrap();
+        assert_eq!(
+            *encoder.output(),
+            OutputType::Custom("prefixed".to_string())
+        );
+        assert_eq!(encoder.next().unwrap(), XPUB);
+        assert!(QREncoder::from_registry(&registry, "unknown", &xpub(), None).is_err());
+    }
+
+    #[test]
+    fn decode_detected_format() {
+        let registry = Registry::new();
+        let mut encoder =
+            QREncoder::from_registry(&registry, "specter", &xpub(), Some(30)).unwrap();
+        let mut decoder = QRDecoder::new(registry);
+        assert!(decoder.result().is_err());
+        for _ in 0..4 {
+            decoder.receive(&encoder.next().unwrap()).unwrap();
+        }
+        assert!(decoder.is_complete());
+        assert_eq!(decoder.format(), Some("specter"));
+        assert_eq!(decoder.result().unwrap(), xpub());
+
+        let mut decoder = QRDecoder::new(Registry::new());
+        assert!(decoder.receive("").is_err());
+        decoder.receive(XPUB).unwrap();
+        assert_eq!(decoder.format(), Some("raw"));
+        assert_eq!(decoder.result().unwrap(), xpub());
+    }
+
+    #[test]
+    fn decoder_limits() {
+        let limits = DecodeLimits {
+            max_frame_len: 100,
+            ..DecodeLimits::default()
+        };
+        // checked before the detection
+        let mut decoder = QRDecoder::with_limits(Registry::new(), limits);
+        assert!(decoder.receive(XPUB).is_err());
+        assert_eq!(decoder.format(), None);
+        let limits = DecodeLimits {
+            max_payload: 100,
+            ..DecodeLimits::default()
+        };
+        let mut decoder = QRDecoder::with_limits(Registry::new(), limits);
+        assert!(decoder.receive(XPUB).is_err());
+        assert!(decoder.receive(&XPUB[..100]).is_ok());
+    }
+
+    /// Replay the fuzz corpus, one frame per line, decoding must not panic
+    fn replay(target: &str, mut decoder: impl FnMut() -> Box<dyn Decode>) {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fuzz/corpus")
+            .join(target);
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let data = std::fs::read(entry.unwrap().path()).unwrap();
+            if let Ok(data) = std::str::from_utf8(&data) {
+                let mut decoder = decoder();
+                for frame in data.lines() {
+                    let _ = decoder.receive(frame);
+                }
+                if decoder.is_complete() {
+                    let _ = decoder.result();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fuzz_corpus() {
+        replay("raw", || Box::new(RawQR::default()));
+        replay("specter", || Box::new(SpecterQR::new()));
+        replay("ur", || Box::new(UrData::new()));
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz/corpus/registry");
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let data = std::fs::read_to_string(entry.unwrap().path()).unwrap();
+            let mut decoder = QRDecoder::new(Registry::new());
+            for frame in data.lines() {
+                let _ = decoder.receive(frame);
+            }
+            if decoder.is_complete() {
+                let _ = decoder.result();
+            }
+        }
+    }
+
+    #[test]
+    fn reset_policy() {
+        // the frame goes to the decoder of the format detected first
+        let mut decoder = QRDecoder::new(Registry::new());
+        decoder.receive("p1of2 foo").unwrap();
+        assert!(decoder.receive(XPUB).is_err());
+        assert!(decoder.receive("p1of3 one").is_err());
+        assert_eq!(decoder.format(), Some("specter"));
+
+        let mut decoder = QRDecoder::new(Registry::new());
+        decoder.receive("p1of2 foo").unwrap();
+        // also applied to the decoder already selected
+        decoder.set_reset_policy(ResetPolicy::Restart);
+        decoder.receive("p1of3 one").unwrap();
+        // a frame of another format restarts the detection
+        decoder.receive(XPUB).unwrap();
+        assert_eq!(decoder.format(), Some("raw"));
+        assert_eq!(decoder.result().unwrap(), xpub());
+        decoder.receive("p1of2 foo").unwrap();
+        assert_eq!(decoder.format(), Some("specter"));
+        assert!(!decoder.is_complete());
+        decoder.receive("p2of2 bar").unwrap();
+        assert_eq!(
+            decoder.result().unwrap(),
+            DataType::NoType(Some("foobar".to_string()))
+        );
+    }
+}
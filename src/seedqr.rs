@@ -0,0 +1,207 @@
+//! SeedSigner SeedQR, standard (digits) and compact (entropy bytes) formats
+//!
+//! A compact SeedQR is scanned in byte mode and is not text, scanners pass the
+//! raw payload to `decode_seed()` rather than to a `Decode` implementation.
+
+use crate::qr::QRMode;
+use crate::{mnemonic, DataType, Error};
+use bip39::{Language, Mnemonic};
+use bitcoin::Network;
+
+/// SeedQR format
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// 4 digits wordlist index per word, numeric mode
+    Standard,
+    /// raw entropy bytes, byte mode
+    Compact,
+}
+
+/// An encoded SeedQR with the QRCode parameters the spec requires
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeedQR {
+    pub format: Format,
+    pub mode: QRMode,
+    /// QRCode version (21x21 is version 1), error correction level is L
+    pub version: u8,
+    pub payload: Vec<u8>,
+}
+
+/// Decode a standard SeedQR
+pub fn decode_standard(data: &str) -> Result<Mnemonic, Error> {
+    let data = data.trim();
+    if !(data.len() == 48 || data.len() == 96) || !data.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(Error::DecodingError(
+            "Standard SeedQR must be 48 or 96 digits!".to_string(),
+        ));
+    }
+    let wordlist = Language::English.word_list();
+    let words = (0..data.len())
+        .step_by(4)
+        .map(|i| {
+            let index = data[i..i + 4].parse::<usize>().unwrap();
+            wordlist.get(index).copied().ok_or_else(|| {
+                Error::DecodingError(format!("Invalid SeedQR word index: {}", index))
+            })
+        })
+        .collect::<Result<Vec<&str>, Error>>()?;
+    Mnemonic::parse_in(Language::English, words.join(" "))
+        .map_err(|e| Error::mnemonic_with("invalid SeedQR words", e))
+}
+
+/// Decode a compact SeedQR
+pub fn decode_compact(data: &[u8]) -> Result<Mnemonic, Error> {
+    if !(data.len() == 16 || data.len() == 32) {
+        return Err(Error::DecodingError(
+            "Compact SeedQR must be 16 or 32 bytes!".to_string(),
+        ));
+    }
+    Mnemonic::from_entropy_in(Language::English, data)
+        .map_err(|e| Error::mnemonic_with("invalid SeedQR entropy", e))
+}
+
+/// Decode a SeedQR payload, the format is detected
+pub fn decode(data: &[u8]) -> Result<Mnemonic, Error> {
+    match std::str::from_utf8(data) {
+        Ok(digits) if (digits.len() == 48 || digits.len() == 96) => decode_standard(digits),
+        _ => decode_compact(data),
+    }
+}
+
+/// Decode a SeedQR payload (digits or entropy bytes) into its mnemonic and
+/// the master private key of `network`
+pub fn decode_seed(data: &[u8], network: Network) -> Result<(Mnemonic, DataType), Error> {
+    let seed = decode(data)?;
+    let xpriv = mnemonic::to_xpriv(&seed, "", network)?;
+    Ok((seed, DataType::Xpriv(Some(xpriv))))
+}
+
+fn version(format: Format, words: usize) -> u8 {
+    match (format, words) {
+        (Format::Standard, 12) => 2,
+        (Format::Standard, _) => 3,
+        (Format::Compact, 12) => 1,
+        (Format::Compact, _) => 2,
+    }
+}
+
+fn check_len(mnemonic: &Mnemonic) -> Result<usize, Error> {
+    match mnemonic.word_count() {
+        12 | 24 => Ok(mnemonic.word_count()),
+        _ => Err(Error::EncodingError(
+            "SeedQR only support 12 or 24 words!".to_string(),
+        )),
+    }
+}
+
+/// Encode a mnemonic as a standard SeedQR
+pub fn encode_standard(mnemonic: &Mnemonic) -> Result<SeedQR, Error> {
+    let words = check_len(mnemonic)?;
+    let wordlist = Language::English.word_list();
+    let digits: String = mnemonic
+        .word_iter()
+        .map(|word| {
+            // words come from the english wordlist
+            let index = wordlist.iter().position(|w| *w == word).unwrap();
+            format!("{:04}", index)
+        })
+        .collect();
+    Ok(SeedQR {
+        format: Format::Standard,
+        mode: QRMode::Numeric,
+        version: version(Format::Standard, words),
+        payload: digits.into_bytes(),
+    })
+}
+
+/// Encode a mnemonic as a compact SeedQR
+pub fn encode_compact(mnemonic: &Mnemonic) -> Result<SeedQR, Error> {
+    let words = check_len(mnemonic)?;
+    Ok(SeedQR {
+        format: Format::Compact,
+        mode: QRMode::Byte,
+        version: version(Format::Compact, words),
+        payload: mnemonic.to_entropy(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex;
+
+    // SeedQR specification examples
+    const WORDS_12: &str = "forum undo fragile fade shy sign arrest garment culture tube off merit";
+    const DIGITS_12: &str = "073318950739065415961602009907670428187212261116";
+    const ENTROPY_12: &str = "5bbd9d71a8ec7990831aff359d426545";
+    const WORDS_24: &str = "attack pizza motion avocado network gather crop fresh patrol unusual wild holiday candy pony ranch winter theme error hybrid van cereal salon goddess expire";
+    const DIGITS_24: &str = "011513251154012711900771041507421289190620080870026613431420201617920614089619290300152408010643";
+
+    fn mnemonic(words: &str) -> Mnemonic {
+        Mnemonic::parse_in(Language::English, words).unwrap()
+    }
+
+    #[test]
+    fn standard_vectors() {
+        for (words, digits, version) in [(WORDS_12, DIGITS_12, 2), (WORDS_24, DIGITS_24, 3)] {
+            assert_eq!(decode_standard(digits).unwrap(), mnemonic(words));
+            assert_eq!(decode(digits.as_bytes()).unwrap(), mnemonic(words));
+            let qr = encode_standard(&mnemonic(words)).unwrap();
+            assert_eq!(qr.payload, digits.as_bytes());
+            assert_eq!(qr.mode, QRMode::Numeric);
+            assert_eq!(qr.version, version);
+        }
+    }
+
+    #[test]
+    fn compact_vectors() {
+        let entropy = hex::decode(ENTROPY_12).unwrap();
+        assert_eq!(decode_compact(&entropy).unwrap(), mnemonic(WORDS_12));
+        assert_eq!(decode(&entropy).unwrap(), mnemonic(WORDS_12));
+        let qr = encode_compact(&mnemonic(WORDS_12)).unwrap();
+        assert_eq!(qr.payload, entropy);
+        assert_eq!(qr.mode, QRMode::Byte);
+        assert_eq!(qr.version, 1);
+
+        let qr = encode_compact(&mnemonic(WORDS_24)).unwrap();
+        assert_eq!(qr.payload.len(), 32);
+        assert_eq!(qr.version, 2);
+        assert_eq!(decode(&qr.payload).unwrap(), mnemonic(WORDS_24));
+    }
+
+    #[test]
+    fn seed_xpriv() {
+        let (seed, xpriv) = decode_seed(DIGITS_12.as_bytes(), Network::Bitcoin).unwrap();
+        assert_eq!(seed, mnemonic(WORDS_12));
+        match xpriv {
+            DataType::Xpriv(Some(xpriv)) => assert_eq!(
+                xpriv.to_string(),
+                "xprv9s21ZrQH143K2iLPXS1fdAvPFYYr2i4e6hgZjWBxfTSNyQw6DrTHg9qoaHkq7ZTc22PHqM3gb5QucTFvpPMAToaDYmAwDRoey1WZFi6oegb"
+            ),
+            other => panic!("unexpected {:?}", other),
+        }
+        let entropy = hex::decode(ENTROPY_12).unwrap();
+        let (_, testnet) = decode_seed(&entropy, Network::Testnet).unwrap();
+        match testnet {
+            DataType::Xpriv(Some(xpriv)) => assert_eq!(xpriv.network, Network::Testnet),
+            other => panic!("unexpected {:?}", other),
+        }
+        // entropy that happens to be UTF-8
+        let (seed, _) = decode_seed(b"0123456789abcdef", Network::Bitcoin).unwrap();
+        assert_eq!(seed.to_entropy(), b"0123456789abcdef");
+    }
+
+    #[test]
+    fn invalid() {
+        // word index above 2047
+        assert!(decode_standard(&DIGITS_12.replace("0733", "2048")).is_err());
+        // wrong checksum word
+        assert!(decode_standard(&DIGITS_12.replace("1116", "1117")).is_err());
+        assert!(decode_standard(&DIGITS_12[4..]).is_err());
+        assert!(decode_standard(&DIGITS_12.replace('0', "a")).is_err());
+        assert!(decode_compact(&[0u8; 20]).is_err());
+        let words_15 = "abandon ".repeat(14) + "address";
+        assert!(encode_standard(&mnemonic(&words_15)).is_err());
+        assert!(encode_compact(&mnemonic(&words_15)).is_err());
+    }
+}
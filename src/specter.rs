@@ -1,48 +1,97 @@
-use crate::qr::QRData;
-use crate::{qr, DataType, Decode, Encode, Encoding, Error, MultiQRElement, OutputType};
+use crate::minimiser::{self, MinimiserOptions};
+use crate::psbt::{PsbtEncoding, PsbtVersion};
+use crate::qr::{ChunkStore, DecodeLimits, QRData, ResetPolicy};
+use crate::{
+    descriptor, hex, psbt, qr, DataType, Decode, Encode, Encoding, Error, OutputType, QREncoder,
+};
+use bip39::Mnemonic;
 use bitcoin::bip32::{ExtendedPrivKey as XPriv, ExtendedPubKey as XPub};
+use bitcoin::consensus::serialize;
 use bitcoin::psbt::PartiallySignedTransaction as Psbt;
-use liana::descriptors::LianaDescriptor as Descriptor;
+use bitcoin::{Network, PrivateKey, Transaction};
+use liana::descriptors::LianaDescriptor;
+use miniscript::{Descriptor, DescriptorPublicKey};
 use regex::Regex;
+use std::sync::OnceLock;
+
+/// Header regex, compiled once
+fn header() -> &'static Regex {
+    static HEADER: OnceLock<Regex> = OnceLock::new();
+    HEADER.get_or_init(|| Regex::new(SpecterQR::pattern()).unwrap())
+}
 
 /// A decoder for Specter MultiQR
 #[derive(Debug, Clone, PartialEq)]
 pub struct SpecterQR {
     pub data: QRData,
+    limits: DecodeLimits,
+    policy: ResetPolicy,
+    /// sequence scanned before the current one, with `ResetPolicy::KeepBoth`
+    other: Option<ChunkStore>,
 }
 
 impl SpecterQR {
     pub fn new() -> SpecterQR {
         let data = QRData::new();
-        SpecterQR { data }
+        SpecterQR {
+            data,
+            limits: DecodeLimits::default(),
+            policy: ResetPolicy::default(),
+            other: None,
+        }
+    }
+
+    pub fn with_limits(limits: DecodeLimits) -> SpecterQR {
+        SpecterQR {
+            limits,
+            ..SpecterQR::new()
+        }
     }
     pub fn is_multi(data: &str) -> bool {
-        let re: Regex = Regex::new(SpecterQR::pattern()).unwrap();
-        re.is_match(data)
+        header().is_match(data)
     }
     pub fn data_init(&mut self, sequences: usize) {
-        todo!()
+        self.data.data_init(sequences)
+    }
+
+    /// encode a PSBT as base64 (Specter Desktop) or hex, Specter frames are text
+    pub fn from_psbt_with(
+        psbt: &Psbt,
+        encoding: PsbtEncoding,
+        version: PsbtVersion,
+    ) -> Result<Box<Self>, Error> {
+        if encoding == PsbtEncoding::Binary {
+            return Err(Error::EncodingError(
+                "Specter MultiQR cannot carry binary data!".to_string(),
+            ));
+        }
+        let payload = String::from_utf8(psbt::encode_version(psbt, encoding, version)?).unwrap();
+        let mut qr = SpecterQR::new();
+        qr.load_string(&payload)
     }
     fn process(&mut self) {
-        let mut buffer = String::new();
-        // for each element of data chunks
-        for i in &self.data.chunks {
-            match i {
-                Some(result) => {
-                    buffer += &result.data;
-                }
-                None => {
-                    self.data.is_completed = false;
-                    return;
-                }
+        match self.data.chunks.assemble() {
+            Some(data) => {
+                self.data.data = data;
+                self.data.is_completed = true;
             }
+            None => self.data.is_completed = false,
         }
-        self.data.data = buffer;
-        self.data.is_completed = true;
     }
 
     fn check_complete(&mut self) {
-        todo!()
+        self.data.check_complete()
+    }
+
+    /// Scan the other sequence if the chunk belongs to it, else start a new
+    /// one, the current sequence is kept as the other one
+    fn switch_sequence(&mut self, index: usize, chunk: &[u8], total: usize) {
+        let other = match self.other.take() {
+            Some(other) if other.check(index - 1, chunk, total).is_ok() => other,
+            _ => ChunkStore::new(total),
+        };
+        self.other = Some(std::mem::replace(&mut self.data.chunks, other));
+        self.data.total_sequences = total;
     }
 }
 
@@ -55,10 +104,22 @@ impl Decode for SpecterQR {
         self.data.is_completed
     }
 
+    fn set_limits(&mut self, limits: DecodeLimits) {
+        self.limits = limits;
+    }
+
+    fn set_reset_policy(&mut self, policy: ResetPolicy) {
+        self.policy = policy;
+    }
+
+    // decoded keys carry their network
+    fn set_network(&mut self, _network: Network) {}
+
     fn receive(&mut self, raw_data: &str) -> Result<bool, Error> {
+        self.limits.check_frame(raw_data)?;
         if SpecterQR::is_multi(raw_data) {
             // header pattern
-            let regex = Regex::new(SpecterQR::pattern()).unwrap();
+            let regex = header();
 
             // fetch data
             let data = regex.replace_all(raw_data, "".to_string()).to_string();
@@ -69,137 +130,122 @@ impl Decode for SpecterQR {
                 let found = found.as_str().to_string();
                 header = found;
             };
-            let parts: Vec<&str> = header.split("of").collect();
+            let (index_part, total_part) = header
+                .split_once("of")
+                .ok_or_else(|| Error::InvalidHeader(header.clone()))?;
 
             // index
             let index: usize;
-            let a = parts[0].replace("p", "").parse::<usize>();
+            let a = index_part.replace("p", "").parse::<usize>();
             match a {
                 Ok(value) => {
                     if value > 0 {
                         index = value;
                     } else {
-                        return Err("Index cannot be 0!".to_string());
+                        return Err(Error::InvalidHeader("Index cannot be 0!".to_string()));
                     }
                 }
                 Err(e) => {
-                    return Err(e.to_string());
+                    return Err(Error::InvalidHeader(e.to_string()));
                 }
             }
 
             // total
             let total: usize;
-            let b = parts[1].trim().parse::<usize>();
+            let b = total_part.trim().parse::<usize>();
             match b {
                 Ok(value) => {
                     if value > 1 {
                         total = value;
                     } else {
-                        return Err("Total might be > 1!".to_string());
+                        return Err(Error::InvalidHeader("Total might be > 1!".to_string()));
                     }
                 }
                 Err(e) => {
-                    return Err(e.to_string());
+                    return Err(Error::InvalidHeader(e.to_string()));
                 }
             }
 
-            // if first append
-            if self.data.chunks.len() == 0 {
-                self.data.chunks = vec![None; total];
-            }
+            // the header is checked before allocating the chunks
+            self.limits.check_parts(total)?;
 
-            // load element to the right position
-            let d2 = data.clone();
-            let element = MultiQRElement {
-                data: d2,
-                index,
-                total,
-            };
+            if index > total {
+                return Err(Error::IndexOutOfRange { index, total });
+            }
 
-            let idx = index - 1;
+            // if first append
+            if self.data.chunks.total() == 0 {
+                self.data.data_init(total);
+            }
 
-            // check if this chunk already loaded
-            match &self.data.chunks[idx] {
-                None => {
-                    // load the chunk
-                    self.data.chunks[idx] = Some(element);
-                }
-                Some(value) => {
-                    // check if loaded & actual value match
-                    if value.data != data {
-                        return Err("Value and data are different!".to_string());
-                    }
+            // frame of another sequence
+            if let Err(e) = self.data.chunks.check(index - 1, data.as_bytes(), total) {
+                match self.policy {
+                    ResetPolicy::Error => return Err(e),
+                    // the first sequence completed is the result
+                    ResetPolicy::KeepBoth if self.data.is_completed => return Err(e),
+                    ResetPolicy::Restart => self.data.data_init(total),
+                    ResetPolicy::KeepBoth => self.switch_sequence(index, data.as_bytes(), total),
                 }
             }
 
-            self.process();
+            // a chunk scanned again adds nothing
+            if !self.data.chunks.contains(index - 1) {
+                let other = self.other.as_ref().map_or(0, |other| other.len());
+                self.limits
+                    .check_payload(self.data.chunks.len() + other + data.len())?;
+            }
+
+            // load chunk to the right position, check a chunk already loaded matches
+            let inserted = self.data.chunks.insert(index - 1, data.as_bytes())?;
+
+            // assemble once, when the last missing chunk arrives
+            if inserted && self.data.chunks.is_complete() {
+                self.process();
+            }
 
             Ok(true)
         } else {
-            Err("data is not MultiQR type!".to_string())
+            Err(Error::InvalidHeader(
+                "data is not MultiQR type!".to_string(),
+            ))
         }
     }
 
     fn result(&self) -> Result<DataType, Error> {
-        todo!()
+        if !self.is_complete() {
+            return Err(Error::DecodingError(
+                "Specter data not yet complete!".to_string(),
+            ));
+        }
+        match std::str::from_utf8(&self.data.data) {
+            Ok(data) => Ok(DataType::from_string(data)),
+            Err(_) => Err(Error::DecodingError("Specter data is not UTF-8!".to_string())),
+        }
     }
 }
 
 impl Encode for SpecterQR {
     fn max_len(&mut self) -> Option<usize> {
-        todo!()
+        self.data.max_len
+    }
+
+    fn from_liana_descriptor(descriptor: &LianaDescriptor) -> Result<Box<Self>, Error> {
+        let mut qr = SpecterQR::new();
+        qr.load_string(&descriptor.to_string())
     }
 
-    fn from_liana_descriptor(descriptor: &Descriptor) -> Result<Box<Self>, Error> {
-        todo!()
+    fn from_descriptor(descriptor: &Descriptor<DescriptorPublicKey>) -> Result<Box<Self>, Error> {
+        let mut qr = SpecterQR::new();
+        qr.load_string(&descriptor::to_string(descriptor))
     }
 
     fn load_string(&mut self, data: &str) -> Result<Box<Self>, Error> {
         let mut out = QRData::new();
-
-        out.data = data.to_string();
-
-        // if multi
-        if data.len() > out.max_len.unwrap() {
-            let mut end: bool = false;
-            let mut buff = data.to_string();
-
-            //data.len() > max_len : split and load
-            while !end {
-                //if len(data) > max_len : return (chunk, data) else return (data, None)
-                let (sequence, data) = if buff.len() > 0 {
-                    if buff.len() > out.max_len.unwrap() {
-                        let (sequence, data) = buff.split_at(out.max_len.unwrap());
-                        // (chunk, data)
-                        (sequence.to_owned().to_string(), data.to_owned().to_string())
-                    } else {
-                        // (data, None)
-                        (buff, "".to_string())
-                    }
-                } else {
-                    let sequence = data.clone().to_string();
-                    // (data, None)
-                    (sequence, "".to_string())
-                };
-
-                // load remaining data in buff
-                buff = data.clone().to_string();
-
-                // QR::append(&mut out, &sequence.to_string());
-                let option = Some(sequence.to_string());
-                out.data_stack.push(option);
-
-                // stop when buffer empty
-                if buff.len() == 0 {
-                    end = true;
-                }
-            }
-        } else {
-            out.data_init(1);
-        }
-        out.total_sequences = out.data_stack.len();
-        out.is_loaded = true;
-        Ok(self)
+        out.max_len = self.data.max_len;
+        out.load(data.as_bytes(), false)?;
+        self.data = out;
+        Ok(Box::new(self.clone()))
     }
 
     fn set_output_type(
@@ -207,23 +253,164 @@ impl Encode for SpecterQR {
         data_type: DataType,
         encoding: Encoding,
         max_len: Option<usize>,
-    ) -> &mut Self {
-        todo!()
+    ) -> Result<&mut Self, Error> {
+        if encoding != Encoding::Specter {
+            return Err(Error::EncodingError(format!(
+                "SpecterQR cannot encode as {:?}!",
+                encoding
+            )));
+        }
+        QREncoder::output_type(&data_type, encoding)?;
+        self.data.max_len = max_len;
+        Ok(self)
     }
 
-    fn from_psbt(psbt: &Psbt) -> Result<Box<Self>, Error> {
-        todo!()
+    fn from_psbt(psbt: &Psbt, options: Option<&MinimiserOptions>) -> Result<Box<Self>, Error> {
+        let psbt = minimiser::apply(psbt, options);
+        SpecterQR::from_psbt_with(&psbt, PsbtEncoding::Base64, PsbtVersion::V0)
     }
 
     fn from_xpub(xpub: &XPub) -> Result<Box<Self>, Error> {
-        todo!()
+        let mut qr = SpecterQR::new();
+        qr.load_string(&xpub.to_string())
     }
 
     fn from_xpriv(xpriv: &XPriv) -> Result<Box<Self>, Error> {
-        todo!()
+        let mut qr = SpecterQR::new();
+        qr.load_string(&xpriv.to_string())
+    }
+
+    fn from_mnemonic(mnemonic: &Mnemonic) -> Result<Box<Self>, Error> {
+        let mut qr = SpecterQR::new();
+        qr.load_string(&mnemonic.to_string())
+    }
+
+    fn from_private_key(key: &PrivateKey) -> Result<Box<Self>, Error> {
+        let mut qr = SpecterQR::new();
+        qr.load_string(&key.to_wif())
+    }
+
+    fn from_transaction(tx: &Transaction) -> Result<Box<Self>, Error> {
+        let mut qr = SpecterQR::new();
+        qr.load_string(&hex::encode(&serialize(tx)))
     }
 
     fn next(&mut self) -> Option<String> {
-        todo!()
+        if self.data.total_sequences == 1 {
+            // a single QRCode does not carry the pMofN header
+            self.data.text().map(|text| text.to_string())
+        } else {
+            self.data.next().ok()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qr::Limit;
+
+    fn limit(result: Result<bool, Error>) -> Option<Limit> {
+        match result {
+            Err(Error::LimitExceeded { limit, .. }) => Some(limit),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn decode_limits() {
+        let mut qr = SpecterQR::with_limits(DecodeLimits {
+            max_parts: 3,
+            max_payload: 10,
+            max_frame_len: 20,
+        });
+        let frame = format!("p1of2 {}", "a".repeat(20));
+        assert_eq!(limit(qr.receive(&frame)), Some(Limit::FrameLength));
+        assert_eq!(limit(qr.receive("p1of4 abcd")), Some(Limit::Parts));
+        qr.receive("p1of3 abcdef").unwrap();
+        assert_eq!(limit(qr.receive("p2of3 abcde")), Some(Limit::Payload));
+        qr.receive("p2of3 abcd").unwrap();
+        assert!(!qr.is_complete());
+        // at the limit, frames scanned again are not counted twice
+        qr.receive("p1of3 abcdef").unwrap();
+        qr.receive("p2of3 abcd").unwrap();
+        assert_eq!(limit(qr.receive("p3of3 a")), Some(Limit::Payload));
+    }
+
+    #[test]
+    fn malformed_frames() {
+        for frame in [
+            "p0of2 abc",
+            "p3of2 abc",
+            "p1of1 abc",
+            "p99999999999999999999999of2 abc",
+            "p1of99999999999999999999999 abc",
+            "p1of999999999 abc",
+            "abc",
+            "",
+        ] {
+            assert!(SpecterQR::new().receive(frame).is_err(), "{}", frame);
+        }
+        assert!(SpecterQR::new().result().is_err());
+
+        let mut qr = SpecterQR::new();
+        qr.receive("p1of2 abc").unwrap();
+        assert!(matches!(
+            qr.receive("p3of5 def"),
+            Err(Error::TotalMismatch {
+                expected: 2,
+                found: 5
+            })
+        ));
+        assert!(matches!(
+            qr.receive("p1of2 xyz"),
+            Err(Error::ConflictingDuplicate { index: 1 })
+        ));
+        assert!(qr.result().is_err());
+
+        // chunks split on characters
+        let mut qr = SpecterQR::new();
+        qr.receive("p1of2 é").unwrap();
+        qr.receive("p2of2 ü").unwrap();
+        assert_eq!(
+            qr.result().unwrap(),
+            DataType::NoType(Some("éü".to_string()))
+        );
+    }
+
+    #[test]
+    fn reset_policy() {
+        let text = |data: &str| DataType::NoType(Some(data.to_string()));
+
+        // the frame of another sequence is refused
+        let mut qr = SpecterQR::new();
+        qr.receive("p1of2 foo").unwrap();
+        assert!(qr.receive("p1of3 one").is_err());
+        qr.receive("p2of2 bar").unwrap();
+        assert_eq!(qr.result().unwrap(), text("foobar"));
+
+        let mut qr = SpecterQR::new();
+        qr.set_reset_policy(ResetPolicy::Restart);
+        qr.receive("p1of2 foo").unwrap();
+        qr.receive("p1of3 one").unwrap();
+        qr.receive("p2of3 two").unwrap();
+        // a conflicting chunk restarts the sequence too
+        qr.receive("p2of3 six").unwrap();
+        assert!(!qr.is_complete());
+        qr.receive("p1of3 one").unwrap();
+        qr.receive("p3of3 ten").unwrap();
+        assert_eq!(qr.result().unwrap(), text("onesixten"));
+
+        // the first sequence completed is the result
+        let mut qr = SpecterQR::new();
+        qr.set_reset_policy(ResetPolicy::KeepBoth);
+        qr.receive("p1of2 foo").unwrap();
+        qr.receive("p1of3 one").unwrap();
+        qr.receive("p2of3 two").unwrap();
+        assert!(!qr.is_complete());
+        qr.receive("p2of2 bar").unwrap();
+        assert!(qr.is_complete());
+        assert!(qr.receive("p3of3 ten").is_err());
+        assert_eq!(qr.result().unwrap(), text("foobar"));
     }
 }
@@ -0,0 +1,384 @@
+//! PSBT summary and signing status, for display before/after signing
+
+use crate::{descriptor, DataType, Error};
+use bitcoin::bip32::{ChildNumber, Fingerprint};
+use bitcoin::psbt::{Input, Output, PartiallySignedTransaction as Psbt};
+use bitcoin::{Address, Network, OutPoint, ScriptBuf, TxOut};
+use miniscript::{Descriptor, DescriptorPublicKey};
+
+/// Summary of a PSBT input
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputSummary {
+    pub outpoint: OutPoint,
+    /// Amount in sats, None if the PSBT lacks the spent utxo
+    pub amount: Option<u64>,
+    pub address: Option<Address>,
+    /// Fingerprints of the keys that already signed this input
+    pub signed_by: Vec<Fingerprint>,
+}
+
+/// Summary of a PSBT output
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputSummary {
+    /// Amount in sats
+    pub amount: u64,
+    pub address: Option<Address>,
+    /// The output pays to the change path of the supplied descriptor
+    pub is_change: bool,
+}
+
+/// Summary of a PSBT
+#[derive(Debug, Clone, PartialEq)]
+pub struct PsbtSummary {
+    pub inputs: Vec<InputSummary>,
+    pub outputs: Vec<OutputSummary>,
+    /// Fee in sats, None if an input amount is unknown, a PSBT whose outputs
+    /// exceed its inputs is rejected
+    pub fee: Option<u64>,
+    /// Virtual size, estimated from the descriptor if the PSBT is not finalized
+    pub vsize: Option<u64>,
+    /// Feerate in sat/vB
+    pub feerate: Option<f64>,
+}
+
+fn spent_utxo(input: &Input, outpoint: &OutPoint) -> Result<Option<TxOut>, Error> {
+    if let Some(tx) = &input.non_witness_utxo {
+        if tx.txid() != outpoint.txid {
+            return Err(Error::psbt(
+                "non_witness_utxo does not match the spent outpoint",
+            ));
+        }
+    }
+    if let Some(utxo) = &input.witness_utxo {
+        return Ok(Some(utxo.clone()));
+    }
+    Ok(input
+        .non_witness_utxo
+        .as_ref()
+        .and_then(|tx| tx.output.get(outpoint.vout as usize).cloned()))
+}
+
+fn signed_by(input: &Input) -> Vec<Fingerprint> {
+    let mut out = Vec::new();
+    for key in input.partial_sigs.keys() {
+        if let Some((fingerprint, _)) = input.bip32_derivation.get(&key.inner) {
+            out.push(*fingerprint);
+        }
+    }
+    let tap_keys = input
+        .tap_script_sigs
+        .keys()
+        .map(|(key, _)| *key)
+        .chain(input.tap_key_sig.and(input.tap_internal_key));
+    for key in tap_keys {
+        if let Some((_, (fingerprint, _))) = input.tap_key_origins.get(&key) {
+            out.push(*fingerprint);
+        }
+    }
+    out.sort();
+    out.dedup();
+    out
+}
+
+/// Derivation indexes of the keys of an output
+fn derivation_indexes(output: &Output) -> Vec<u32> {
+    output
+        .bip32_derivation
+        .values()
+        .chain(output.tap_key_origins.values().map(|(_, origin)| origin))
+        .filter_map(|(_, path)| match path.as_ref().last() {
+            Some(ChildNumber::Normal { index }) => Some(*index),
+            _ => None,
+        })
+        .collect()
+}
+
+fn is_change(
+    output: &Output,
+    script: &ScriptBuf,
+    change: Option<&Descriptor<DescriptorPublicKey>>,
+) -> bool {
+    let change = match change {
+        Some(change) => change,
+        None => return false,
+    };
+    derivation_indexes(output).into_iter().any(|index| {
+        change
+            .at_derivation_index(index)
+            .map(|derived| derived.script_pubkey() == *script)
+            .unwrap_or(false)
+    })
+}
+
+impl PsbtSummary {
+    /// Summarize a PSBT, outputs are flagged as change against the change path
+    /// of `descriptor` (a single path descriptor is used as the change one)
+    pub fn new(
+        psbt: &Psbt,
+        network: Network,
+        descriptor: Option<&Descriptor<DescriptorPublicKey>>,
+    ) -> Result<PsbtSummary, Error> {
+        let change = match descriptor {
+            Some(descriptor) if descriptor.is_multipath() => {
+                Some(descriptor::change_descriptor(descriptor)?)
+            }
+            Some(descriptor) => Some(descriptor.clone()),
+            None => None,
+        };
+
+        let tx = &psbt.unsigned_tx;
+        let inputs: Vec<InputSummary> = tx
+            .input
+            .iter()
+            .zip(psbt.inputs.iter())
+            .map(|(txin, input)| {
+                let utxo = spent_utxo(input, &txin.previous_output)?;
+                Ok(InputSummary {
+                    outpoint: txin.previous_output,
+                    amount: utxo.as_ref().map(|utxo| utxo.value),
+                    address: utxo
+                        .as_ref()
+                        .and_then(|utxo| Address::from_script(&utxo.script_pubkey, network).ok()),
+                    signed_by: signed_by(input),
+                })
+            })
+            .collect::<Result<_, Error>>()?;
+        let outputs: Vec<OutputSummary> = tx
+            .output
+            .iter()
+            .zip(psbt.outputs.iter())
+            .map(|(txout, output)| OutputSummary {
+                amount: txout.value,
+                address: Address::from_script(&txout.script_pubkey, network).ok(),
+                is_change: is_change(output, &txout.script_pubkey, change.as_ref()),
+            })
+            .collect();
+
+        let overflow = || Error::psbt("amounts overflow");
+        let spent = inputs
+            .iter()
+            .map(|input| input.amount)
+            .collect::<Option<Vec<u64>>>()
+            .map(|amounts| {
+                amounts
+                    .into_iter()
+                    .try_fold(0u64, u64::checked_add)
+                    .ok_or_else(overflow)
+            })
+            .transpose()?;
+        let sent = outputs
+            .iter()
+            .try_fold(0u64, |sent, output| sent.checked_add(output.amount))
+            .ok_or_else(overflow)?;
+        let fee = match spent {
+            Some(spent) => Some(spent.checked_sub(sent).ok_or_else(|| {
+                Error::psbt(&format!("outputs ({}) exceed inputs ({})", sent, spent))
+            })?),
+            None => None,
+        };
+
+        let finalized = psbt
+            .inputs
+            .iter()
+            .all(|input| input.final_script_witness.is_some() || input.final_script_sig.is_some());
+        let vsize = if finalized {
+            Some(psbt.clone().extract_tx().vsize() as u64)
+        } else {
+            descriptor.and_then(|descriptor| {
+                let satisfaction = descriptor.max_weight_to_satisfy().ok()? as u64;
+                // segwit marker & flag
+                let weight = tx.weight().to_wu() + 2 + satisfaction * tx.input.len() as u64;
+                Some((weight + 3) / 4)
+            })
+        };
+        let feerate = match (fee, vsize) {
+            (Some(fee), Some(vsize)) if vsize > 0 => Some(fee as f64 / vsize as f64),
+            _ => None,
+        };
+
+        Ok(PsbtSummary {
+            inputs,
+            outputs,
+            fee,
+            vsize,
+            feerate,
+        })
+    }
+
+    /// Summarize a decoded PSBT
+    pub fn from_data(
+        data: &DataType,
+        network: Network,
+        descriptor: Option<&Descriptor<DescriptorPublicKey>>,
+    ) -> Result<PsbtSummary, Error> {
+        match data {
+            DataType::Psbt(Some(psbt)) | DataType::PsbtV2(Some(psbt)) => {
+                PsbtSummary::new(psbt, network, descriptor)
+            }
+            _ => Err(Error::ParsingError("Data is not a PSBT!".to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::absolute::LockTime;
+    use bitcoin::bip32::{DerivationPath, ExtendedPubKey as XPub};
+    use bitcoin::hashes::Hash;
+    use bitcoin::secp256k1::{Message, Secp256k1, SecretKey};
+    use bitcoin::{Transaction, TxIn, Txid};
+    use std::str::FromStr;
+
+    const XPUB: &str = "xpub6ASuArnXKPbfEwhqN6e3mwBcDTgzisQN1wXN9BJcM47sSikHjJf3UFHKkNAWbWMiGj7Wf5uMash7SyYq527Hqck2AxYysAA7xmALppuCkwQ";
+    const FINGERPRINT: &str = "d34db33f";
+
+    fn descriptor() -> Descriptor<DescriptorPublicKey> {
+        descriptor::parse(&format!(
+            "wpkh([{}/84'/0'/0']{}/<0;1>/*)",
+            FINGERPRINT, XPUB
+        ))
+        .unwrap()
+    }
+
+    fn script(descriptor: &Descriptor<DescriptorPublicKey>, index: u32) -> ScriptBuf {
+        descriptor
+            .at_derivation_index(index)
+            .unwrap()
+            .script_pubkey()
+    }
+
+    fn transaction(outpoint: OutPoint, outputs: Vec<TxOut>) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: outpoint,
+                ..Default::default()
+            }],
+            output: outputs,
+        }
+    }
+
+    /// Spend 10000 sats to 5000 sats of payment and `change` sats of change
+    fn psbt(change: u64) -> Psbt {
+        let (receive, change_path) = descriptor::single_paths(&descriptor()).unwrap();
+        let outpoint = OutPoint::new(Txid::from_byte_array([1; 32]), 0);
+        let external = XPub::from_str(XPUB).unwrap().public_key;
+        let tx = transaction(
+            outpoint,
+            vec![
+                TxOut {
+                    value: 5_000,
+                    script_pubkey: ScriptBuf::new_v0_p2wpkh(
+                        &bitcoin::PublicKey::new(external).wpubkey_hash().unwrap(),
+                    ),
+                },
+                TxOut {
+                    value: change,
+                    script_pubkey: script(&change_path, 3),
+                },
+            ],
+        );
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo = Some(TxOut {
+            value: 10_000,
+            script_pubkey: script(&receive, 0),
+        });
+        psbt.outputs[1].bip32_derivation.insert(
+            external,
+            (
+                Fingerprint::from_str(FINGERPRINT).unwrap(),
+                DerivationPath::from_str("m/84'/0'/0'/1/3").unwrap(),
+            ),
+        );
+        psbt
+    }
+
+    #[test]
+    fn summary() {
+        let descriptor = descriptor();
+        let summary = PsbtSummary::new(&psbt(4_000), Network::Bitcoin, Some(&descriptor)).unwrap();
+        assert_eq!(summary.inputs[0].amount, Some(10_000));
+        assert!(summary.inputs[0].address.is_some());
+        assert!(summary.inputs[0].signed_by.is_empty());
+        assert_eq!(summary.outputs.len(), 2);
+        assert!(!summary.outputs[0].is_change);
+        assert!(summary.outputs[1].is_change);
+        assert_eq!(summary.fee, Some(1_000));
+        let vsize = summary.vsize.unwrap();
+        assert_eq!(summary.feerate, Some(1_000.0 / vsize as f64));
+
+        // without descriptor
+        let summary = PsbtSummary::new(&psbt(4_000), Network::Bitcoin, None).unwrap();
+        assert!(!summary.outputs[1].is_change);
+        assert_eq!(summary.vsize, None);
+        assert_eq!(summary.feerate, None);
+    }
+
+    #[test]
+    fn signed_by_fingerprint() {
+        let secp = Secp256k1::new();
+        let key = SecretKey::from_slice(&[1; 32]).unwrap();
+        let public_key = bitcoin::PublicKey::new(key.public_key(&secp));
+        let signature = secp.sign_ecdsa(&Message::from_slice(&[2; 32]).unwrap(), &key);
+        let mut psbt = psbt(4_000);
+        let input = &mut psbt.inputs[0];
+        input.partial_sigs.insert(
+            public_key,
+            bitcoin::ecdsa::Signature::sighash_all(signature),
+        );
+        input.bip32_derivation.insert(
+            public_key.inner,
+            (
+                Fingerprint::from_str(FINGERPRINT).unwrap(),
+                DerivationPath::from_str("m/84'/0'/0'/0/0").unwrap(),
+            ),
+        );
+        let summary = PsbtSummary::new(&psbt, Network::Bitcoin, None).unwrap();
+        assert_eq!(
+            summary.inputs[0].signed_by,
+            vec![Fingerprint::from_str(FINGERPRINT).unwrap()]
+        );
+    }
+
+    #[test]
+    fn outputs_exceed_inputs() {
+        assert!(PsbtSummary::new(&psbt(6_000), Network::Bitcoin, None).is_err());
+        // unknown input amount: no fee
+        let mut psbt = psbt(6_000);
+        psbt.inputs[0].witness_utxo = None;
+        let summary = PsbtSummary::new(&psbt, Network::Bitcoin, None).unwrap();
+        assert_eq!(summary.inputs[0].amount, None);
+        assert_eq!(summary.fee, None);
+    }
+
+    #[test]
+    fn non_witness_utxo() {
+        let previous = transaction(
+            OutPoint::null(),
+            vec![TxOut {
+                value: 10_000,
+                script_pubkey: ScriptBuf::new(),
+            }],
+        );
+        let mut psbt = psbt(4_000);
+        psbt.inputs[0].witness_utxo = None;
+        psbt.inputs[0].non_witness_utxo = Some(previous.clone());
+        // spends Txid [1; 32]
+        assert!(PsbtSummary::new(&psbt, Network::Bitcoin, None).is_err());
+
+        psbt.unsigned_tx.input[0].previous_output = OutPoint::new(previous.txid(), 0);
+        let summary = PsbtSummary::new(&psbt, Network::Bitcoin, None).unwrap();
+        assert_eq!(summary.inputs[0].amount, Some(10_000));
+        assert_eq!(summary.fee, Some(1_000));
+    }
+
+    #[test]
+    fn not_a_psbt() {
+        let data = DataType::NoType(Some("psbt".to_string()));
+        assert!(PsbtSummary::from_data(&data, Network::Bitcoin, None).is_err());
+        let data = DataType::PsbtV2(Some(psbt(4_000)));
+        assert!(PsbtSummary::from_data(&data, Network::Bitcoin, None).is_ok());
+    }
+}
@@ -0,0 +1,88 @@
+//! Signed transaction payloads
+
+use crate::{base43, hex, Error};
+use bitcoin::consensus::{deserialize, serialize};
+use bitcoin::Transaction;
+
+/// Text/binary representation of a transaction in a QRCode
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TxEncoding {
+    Hex,
+    /// Electrum
+    Base43,
+    /// byte mode QRCode
+    Binary,
+}
+
+/// Serialize a transaction in the given encoding
+pub fn encode(tx: &Transaction, encoding: TxEncoding) -> Vec<u8> {
+    let bytes = serialize(tx);
+    match encoding {
+        TxEncoding::Hex => hex::encode(&bytes).into_bytes(),
+        TxEncoding::Base43 => base43::encode(&bytes).into_bytes(),
+        TxEncoding::Binary => bytes,
+    }
+}
+
+/// Parse a transaction from raw bytes
+pub fn from_bytes(data: &[u8]) -> Result<Transaction, Error> {
+    deserialize(data).map_err(|e| Error::ParsingError(format!("Invalid transaction: {}", e)))
+}
+
+/// Parse a hex or Base43 encoded transaction
+pub fn from_string(data: &str) -> Result<Transaction, Error> {
+    let data = data.trim();
+    hex::decode(data)
+        .and_then(|bytes| from_bytes(&bytes))
+        .or_else(|_| from_bytes(&base43::decode(data)?))
+}
+
+/// Parse a transaction, the encoding is detected
+pub fn decode(data: &[u8]) -> Result<Transaction, Error> {
+    match std::str::from_utf8(data) {
+        Ok(text) => from_string(text).or_else(|_| from_bytes(data)),
+        Err(_) => from_bytes(data),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // rust-bitcoin non segwit transaction vector
+    const TX: &str = "0100000001a15d57094aa7a21a28cb20b59aab8fc7d1149a3bdbcddba9c622e4f5f6a99ece010000006c493046022100f93bb0e7d8db7bd46e40132d1f8242026e045f03a0efe71bbb8e3f475e970d790221009337cd7f1f929f00cc6ff01f03729b069a7c21b59b1736ddfee5db5946c5da8c0121033b9b137ee87d5a812d6f506efdd37f0affa7ffc310711c06c7f3e097c9447c52ffffffff0100e1f505000000001976a9140389035a9225b3839e2bbf32d826a1e222031fd888ac00000000";
+    const TXID: &str = "a6eab3c14ab5272a58a5ba91505ba1a4b6d7a3a9fcbd187b6cd99a7b6d548cb7";
+    // Electrum's base_encode(tx, base=43)
+    const TX_BASE43: &str = "1CJU.NS$T7NEL0T+3J$E/WVQ03IMMU:3A:U289OM95P5GLRSAR3ZTN/B2D+Z2AT8FVD99PNN2E7O2Z/P*KU4NWMTSPOY$8*SLDY6VN74QHB9$2:3C*FVEKO*..LOU1-NRHGLAZY-3-50UR0.L:W5QJ622$Y.0UXMJZVJYAVZPPPP+5T+ZI49-R4.G397IA0N4CYX+-6D/628SQ45X3.6VG447IM56BFQIJH.RI+-U3MK9-*UR.9VX*2SN4MVI/5+A-23D4887Z30E5UEW+.4D$ALYL*U";
+
+    fn tx() -> Transaction {
+        from_string(TX).unwrap()
+    }
+
+    #[test]
+    fn encodings() {
+        let tx = tx();
+        assert_eq!(tx.txid().to_string(), TXID);
+        assert_eq!(encode(&tx, TxEncoding::Hex), TX.as_bytes());
+        assert_eq!(encode(&tx, TxEncoding::Base43), TX_BASE43.as_bytes());
+        assert_eq!(encode(&tx, TxEncoding::Binary), hex::decode(TX).unwrap());
+    }
+
+    #[test]
+    fn decode_detects_encoding() {
+        let tx = tx();
+        for encoding in [TxEncoding::Hex, TxEncoding::Base43, TxEncoding::Binary] {
+            assert_eq!(decode(&encode(&tx, encoding)).unwrap(), tx);
+        }
+        assert_eq!(from_string(&format!(" {}\n", TX_BASE43)).unwrap(), tx);
+        assert_eq!(from_string(&TX.to_uppercase()).unwrap(), tx);
+    }
+
+    #[test]
+    fn invalid_transaction() {
+        assert!(from_string(&TX[..TX.len() - 2]).is_err());
+        assert!(from_string("not a transaction").is_err());
+        assert!(from_bytes(&[]).is_err());
+        assert!(decode(&hex::decode(&TX[..100]).unwrap()).is_err());
+    }
+}
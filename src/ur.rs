@@ -1,13 +1,47 @@
 extern crate regex;
 extern crate ur;
 
-use crate::qr::QRData;
+use crate::minimiser::{self, MinimiserOptions};
+use crate::psbt::PsbtVersion;
+use crate::qr::{DecodeLimits, QRData, ResetPolicy};
 use crate::Error::DecodingError;
-use crate::{qr, DataType, Decode, Encode, Encoding, Error, OutputType};
+use crate::{
+    bcr, cbor, descriptor, psbt, qr, DataType, Decode, Encode, Encoding, Error, OutputType,
+    QREncoder,
+};
+use bip39::Mnemonic;
 use bitcoin::bip32::{ExtendedPrivKey as XPriv, ExtendedPubKey as XPub};
+use bitcoin::consensus::serialize;
 use bitcoin::psbt::PartiallySignedTransaction as Psbt;
-use liana::descriptors::LianaDescriptor as Descriptor;
+use bitcoin::{Network, PrivateKey, Transaction};
+use liana::descriptors::LianaDescriptor;
+use miniscript::descriptor::{DescriptorXKey, Wildcard};
+use miniscript::{Descriptor, DescriptorPublicKey};
 use regex::Regex;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// Default max fragment length (bytes) for UR encoding
+pub const DEFAULT_MAX_LEN: usize = 200;
+
+/// Header of a fragment of a multi-part UR
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fragment {
+    /// number of parts of the message
+    pub parts: usize,
+    /// message length (bytes)
+    pub message_len: usize,
+    /// CRC32 of the message
+    pub checksum: u32,
+}
+
+/// A UR sequence put aside with `ResetPolicy::KeepBoth`
+struct Sequence {
+    decoder: Option<ur::Decoder>,
+    output_type: OutputType,
+    multi: Option<bool>,
+    fragment: Option<Fragment>,
+}
 
 pub struct UrData {
     decoder: Option<ur::Decoder>,
@@ -16,6 +50,24 @@ pub struct UrData {
     data_type: DataType,
     max_len: Option<usize>,
     multi: Option<bool>,
+    limits: DecodeLimits,
+    policy: ResetPolicy,
+    /// network of the decoded keys that do not carry it
+    network: Network,
+    /// header of the first fragment of the sequence being scanned
+    fragment: Option<Fragment>,
+    other: Option<Sequence>,
+}
+
+// the payload may be secret, it is not shown
+impl fmt::Debug for UrData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UrData")
+            .field("output_type", &self.output_type)
+            .field("max_len", &self.max_len)
+            .field("multi", &self.multi)
+            .finish()
+    }
 }
 
 impl UrData {
@@ -24,67 +76,170 @@ impl UrData {
             decoder: None,
             encoder: None,
             output_type: OutputType::NoType,
-            data_type: DataType::NoType,
+            data_type: DataType::NoType(None),
             max_len: None,
             multi: None,
+            limits: DecodeLimits::default(),
+            policy: ResetPolicy::default(),
+            network: Network::Bitcoin,
+            fragment: None,
+            other: None,
         }
     }
 
-    pub fn is_ur(&data: &String) -> bool {
-        return if data[..2].to_lowercase() == "ur:" {
-            true
-        } else {
-            false
-        };
+    pub fn with_limits(limits: DecodeLimits) -> Self {
+        let mut ur = UrData::new();
+        ur.limits = limits;
+        ur
     }
 
-    pub fn get_type(&data: &String) -> Result<OutputType, Error> {
-        let t: Vec<&str> = data.clone().split('/').collect();
-        let _type: &str = t[0];
-        return match _type.to_lowercase().as_str() {
-            "ur:bytes" => Ok(OutputType::NoType),
-            "ur:crypto-psbt" => Ok(OutputType::UrPsbt),
-            "ur:crypto-output" => Ok(OutputType::UrDescriptor),
-            "ur:crypto-account" => Ok(OutputType::UrXpub),
-            "ur:crypto-address" => Ok(OutputType::UrAddress),
-            "ur:crypto-hdkey" => Ok(OutputType::UrXpriv),
-            _ => Err(Error::DecodingError("Unknown UR type!".to_string())),
+    /// Header of a fragment of a multi-part UR, the declared sizes are checked
+    /// before the fragment reaches the decoder, which trusts the CBOR header
+    /// rather than the path
+    pub fn fragment_header(data: &str) -> Result<Fragment, Error> {
+        let invalid = || Error::InvalidHeader("Invalid UR fragment!".to_string());
+        let mut t = data.split('/').skip(1);
+        let sequence = t.next().ok_or_else(invalid)?;
+        let payload = t.next().ok_or_else(invalid)?;
+        let (index, parts) = sequence.split_once('-').ok_or_else(invalid)?;
+        let index = index.parse::<u64>().map_err(|_| invalid())?;
+        let parts = parts.parse::<u64>().map_err(|_| invalid())?;
+        let payload = ur::bytewords::decode(payload, ur::bytewords::Style::Minimal)
+            .map_err(|e| Error::ur_with("invalid bytewords", e))?;
+        // [seqNum, seqLen, messageLen, checksum, data]
+        let items = match cbor::decode(&payload)? {
+            cbor::Value::Array(items) => items,
+            _ => return Err(invalid()),
         };
+        match (items.first(), items.get(1), items.get(2), items.get(3)) {
+            (
+                Some(cbor::Value::Unsigned(seq_num)),
+                Some(cbor::Value::Unsigned(seq_len)),
+                Some(cbor::Value::Unsigned(len)),
+                Some(cbor::Value::Unsigned(checksum)),
+            ) => {
+                if *seq_num == 0 || *seq_num != index || *seq_len != parts {
+                    return Err(Error::InvalidHeader(format!(
+                        "UR fragment {}-{} declares {}-{}!",
+                        index, parts, seq_num, seq_len
+                    )));
+                }
+                Ok(Fragment {
+                    parts: usize::try_from(*seq_len).unwrap_or(usize::MAX),
+                    message_len: usize::try_from(*len).unwrap_or(usize::MAX),
+                    checksum: u32::try_from(*checksum).map_err(|_| invalid())?,
+                })
+            }
+            _ => Err(invalid()),
+        }
+    }
+
+    pub fn is_ur(data: &str) -> bool {
+        data.get(..3)
+            .map_or(false, |scheme| scheme.eq_ignore_ascii_case("ur:"))
     }
 
-    pub fn is_multi(&data: &String) -> bool {
-        let t: Vec<&str> = data.clone().split('/').collect();
-        let multi: &str = t[1];
-        let re = Regex::new(r"^\d+-\d+$").unwrap();
-        return if re.is_match(multi) { true } else { false };
+    pub fn get_type(data: &str) -> Result<OutputType, Error> {
+        let _type: &str = data.split('/').next().unwrap_or_default();
+        let name = _type.to_lowercase();
+        UR_TYPES
+            .iter()
+            .find(|(ur_type, _)| name.strip_prefix("ur:") == Some(*ur_type))
+            .map(|(_, output_type)| output_type.clone())
+            .ok_or_else(|| Error::UnsupportedUrType(_type.to_string()))
     }
 
-    pub fn type_check(&self, &data: &String) -> bool {
-        let data_type = UrData::get_type(&data);
+    pub fn is_multi(data: &str) -> bool {
+        let multi: &str = match data.split('/').nth(1) {
+            Some(multi) => multi,
+            None => return false,
+        };
+        static RE: OnceLock<Regex> = OnceLock::new();
+        let re = RE.get_or_init(|| Regex::new(r"^\d+-\d+$").unwrap());
+        re.is_match(multi)
+    }
+
+    pub fn type_check(&self, data: &str) -> bool {
         // if not UR type
-        return if !UrData::is_ur(&data) {
-            false
+        if !UrData::is_ur(data) {
+            return false;
         }
         // if data type unknown
-        else if data_type.is_err() {
-            false
-        }
+        let data_type = match UrData::get_type(data) {
+            Ok(data_type) => data_type,
+            Err(_) => return false,
+        };
         // decoder not init
-        else if self.multi.is_none() & (self.output_type == OutputType::NoType) {
+        if self.multi.is_none() & (self.output_type == OutputType::NoType) {
             true
         }
         // multi not match
-        else if self.multi != Some(UrData::is_multi(&data)) {
+        else if self.multi != Some(UrData::is_multi(data)) {
             false
         }
         // output not match
-        else if self.output_type != data_type.unwrap() {
-            false
-        }
-        // any other case
         else {
-            true
+            self.output_type == data_type
+        }
+    }
+
+    /// Check a frame belongs to the sequence being scanned
+    fn check_sequence(
+        &self,
+        output_type: &OutputType,
+        multi: bool,
+        fragment: Option<Fragment>,
+    ) -> Result<(), Error> {
+        if self.output_type != *output_type || self.multi != Some(multi) {
+            return Err(Error::DecodingError(
+                "Frame of another UR sequence!".to_string(),
+            ));
+        }
+        match (self.fragment, fragment) {
+            (Some(expected), Some(found)) if expected.parts != found.parts => {
+                Err(Error::TotalMismatch {
+                    expected: expected.parts,
+                    found: found.parts,
+                })
+            }
+            (Some(expected), Some(found))
+                if expected.checksum != found.checksum
+                    || expected.message_len != found.message_len =>
+            {
+                Err(Error::ChecksumMismatch {
+                    expected: format!("{:08x}", expected.checksum),
+                    found: format!("{:08x}", found.checksum),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Scan the other sequence if the frame belongs to it, else start a new
+    /// one, the current sequence is kept as the other one
+    fn switch_sequence(
+        &mut self,
+        output_type: &OutputType,
+        multi: bool,
+        fragment: Option<Fragment>,
+    ) {
+        let current = Sequence {
+            decoder: self.decoder.take(),
+            output_type: std::mem::replace(&mut self.output_type, OutputType::NoType),
+            multi: self.multi.take(),
+            fragment: self.fragment.take(),
         };
+        if let Some(other) = self.other.take() {
+            self.decoder = other.decoder;
+            self.output_type = other.output_type;
+            self.multi = other.multi;
+            self.fragment = other.fragment;
+            if self.check_sequence(output_type, multi, fragment).is_err() {
+                // neither sequence, the new decoder is initialized by receive()
+                self.decoder = None;
+            }
+        }
+        self.other = Some(current);
     }
 
     pub fn is_encoder(&self) -> bool {
@@ -92,51 +247,227 @@ impl UrData {
     }
 
     pub fn is_decoder(&self) -> bool {
-        self.encoder.is_none() & self.encoder.is_some()
+        self.encoder.is_none() & self.decoder.is_some()
+    }
+
+    /// encode a PSBT as crypto-psbt, converted to the PSBT version the receiver
+    /// expects, with the given max fragment length
+    pub fn from_psbt_with(
+        psbt: &Psbt,
+        version: PsbtVersion,
+        max_len: Option<usize>,
+    ) -> Result<Box<Self>, Error> {
+        let mut ur = UrData::new();
+        ur.max_len = max_len;
+        ur.load(&DataType::from_psbt(psbt.clone(), version))?;
+        Ok(Box::new(ur))
+    }
+
+    /// encode a single path descriptor as crypto-output, with the given max
+    /// fragment length
+    pub fn from_descriptor_with(
+        descriptor: &Descriptor<DescriptorPublicKey>,
+        max_len: Option<usize>,
+    ) -> Result<Box<Self>, Error> {
+        let mut ur = UrData::new();
+        ur.max_len = max_len;
+        ur.load(&DataType::Descriptor(Some(descriptor.clone())))?;
+        Ok(Box::new(ur))
+    }
+
+    /// encode a descriptor as crypto-output, a multipath one is split in
+    /// receive then change
+    pub fn from_descriptors_with(
+        descriptor: &Descriptor<DescriptorPublicKey>,
+        max_len: Option<usize>,
+    ) -> Result<Vec<Box<Self>>, Error> {
+        if descriptor.is_multipath() {
+            let (receive, change) = descriptor::single_paths(descriptor)?;
+            Ok(vec![
+                UrData::from_descriptor_with(&receive, max_len)?,
+                UrData::from_descriptor_with(&change, max_len)?,
+            ])
+        } else {
+            Ok(vec![UrData::from_descriptor_with(descriptor, max_len)?])
+        }
+    }
+
+    /// encode a payload as UR bytes, with the given max fragment length
+    pub fn from_bytes_with(data: &[u8], max_len: Option<usize>) -> Result<Box<Self>, Error> {
+        let mut ur = UrData::new();
+        ur.max_len = max_len;
+        ur.load_bytes(&bcr::bytes(data), "bytes")?;
+        ur.output_type = OutputType::UrBytes;
+        Ok(Box::new(ur))
+    }
+
+    /// Load data to encode, the UR type follows the data type: Liana descriptors,
+    /// transactions and strings are sent as bytes
+    pub fn load(&mut self, data: &DataType) -> Result<(), Error> {
+        let (message, output_type) = match data {
+            DataType::Psbt(Some(psbt)) => (
+                bcr::bytes(&psbt::serialize(psbt, PsbtVersion::V0)?),
+                OutputType::UrPsbt,
+            ),
+            DataType::PsbtV2(Some(psbt)) => (
+                bcr::bytes(&psbt::serialize(psbt, PsbtVersion::V2)?),
+                OutputType::UrPsbt,
+            ),
+            DataType::Descriptor(Some(descriptor)) => {
+                if descriptor.is_multipath() {
+                    return Err(Error::EncodingError(
+                        "crypto-output cannot hold a multipath descriptor, use from_descriptors()!"
+                            .to_string(),
+                    ));
+                }
+                (
+                    bcr::descriptor_to_output(descriptor)?,
+                    OutputType::UrDescriptor,
+                )
+            }
+            DataType::Xpub(Some(xpub)) => (bcr::xpub_to_hdkey_bytes(xpub), OutputType::UrXpub),
+            DataType::Keys(Some(keys)) => (bcr::key_to_hdkey_bytes(keys)?, OutputType::UrXpub),
+            DataType::Mnemonic(Some(mnemonic)) => {
+                (bcr::mnemonic_to_bip39(mnemonic), OutputType::UrMnemonic)
+            }
+            DataType::PrivateKey(Some(key)) => {
+                (bcr::private_key_to_eckey(key), OutputType::UrPrivateKey)
+            }
+            DataType::Transaction(Some(tx)) => (bcr::bytes(&serialize(tx)), OutputType::UrBytes),
+            DataType::LianaDescriptor(Some(descriptor)) => (
+                bcr::bytes(descriptor.to_string().as_bytes()),
+                OutputType::UrBytes,
+            ),
+            DataType::NoType(Some(data)) => (bcr::bytes(data.as_bytes()), OutputType::UrBytes),
+            _ => {
+                return Err(Error::NotImplementedError(
+                    "UR encoding not implemented for this data type!".to_string(),
+                ))
+            }
+        };
+        self.load_bytes(&message, type_name(&output_type).unwrap_or("bytes"))?;
+        self.output_type = output_type;
+        self.data_type = data.clone();
+        Ok(())
+    }
+
+    /// Return the reassembled message of a complete decoder
+    fn message(&self) -> Result<Vec<u8>, Error> {
+        match self.decoder.as_ref().map(|decoder| decoder.message()) {
+            Some(Ok(Some(message))) => Ok(message),
+            Some(Ok(None)) => Err(Error::DecodingError("Message is None".to_string())),
+            Some(Err(_)) => Err(Error::DecodingError("Cannot decode message !".to_string())),
+            None => Err(Error::DecodingError(
+                "UrData is not of decoder type!".to_string(),
+            )),
+        }
+    }
+
+    /// Load a message to encode with the given UR type
+    fn load_bytes(&mut self, data: &[u8], ur_type: &str) -> Result<(), Error> {
+        let max_len = self.max_len.unwrap_or(DEFAULT_MAX_LEN);
+        let encoder = ur::Encoder::new(data, max_len, ur_type)
+            .map_err(|e| Error::ur_with("cannot encode", e))?;
+        self.multi = Some(encoder.fragment_count() > 1);
+        self.encoder = Some(encoder);
+        Ok(())
     }
 }
 
+/// UR types supported and their output type
+const UR_TYPES: [(&str, OutputType); 8] = [
+    ("bytes", OutputType::UrBytes),
+    ("crypto-psbt", OutputType::UrPsbt),
+    ("crypto-output", OutputType::UrDescriptor),
+    ("crypto-hdkey", OutputType::UrXpub),
+    ("crypto-account", OutputType::UrAccount),
+    ("crypto-address", OutputType::UrAddress),
+    ("crypto-bip39", OutputType::UrMnemonic),
+    ("crypto-eckey", OutputType::UrPrivateKey),
+];
+
+/// UR type of an output type, inverse of `UrData::get_type()`
+fn type_name(output_type: &OutputType) -> Option<&'static str> {
+    UR_TYPES
+        .iter()
+        .find(|(_, other)| other == output_type)
+        .map(|(ur_type, _)| *ur_type)
+}
+
 impl Decode for UrData {
     fn pattern() -> &'static str {
-        todo!()
+        r"(?i)^ur:[a-z0-9-]+/"
     }
 
     fn is_complete(&self) -> bool {
-        if !self.is_decoder() {
-            false
-        } else {
-            self.decoder.unwrap().complete()
+        match &self.decoder {
+            Some(decoder) if self.is_decoder() => decoder.complete(),
+            _ => false,
         }
     }
 
+    fn set_limits(&mut self, limits: DecodeLimits) {
+        self.limits = limits;
+    }
+
+    fn set_reset_policy(&mut self, policy: ResetPolicy) {
+        self.policy = policy;
+    }
+
+    /// crypto-eckey has no network, crypto-hdkey may omit its coin-info
+    fn set_network(&mut self, network: Network) {
+        self.network = network;
+    }
+
     fn receive(&mut self, data: &str) -> Result<bool, Error> {
         // encoder/decoder mismatch
-        if self.is_decoder() {
+        if self.is_encoder() {
             return Err(Error::DecodingError(
                 "UrData cannot have encode and decode feature at same time!".to_string(),
             ));
         };
-        if self.type_check(&data.to_string()) {
-            let _type = UrData::get_type(&data.to_string())?;
-            // if no data_type define, init the decoder
-            return if (self.data_type == DataType::NoType) & (_type != DataType::NoType) {
-                self.data_type = _type;
-                self.decoder = Some(ur::Decoder::default());
-                self.decoder.as_mut().map(|decoder| decoder.receive(data));
-                Ok(true)
-            // if decoder already init
-            } else if self.data_type == _type {
-                self.decoder.as_mut().map(|decoder| decoder.receive(data));
-                Ok(true)
-            // types are different
-            } else {
-                Err(Error::DecodingError(" Mismatching types!".to_string()))
-            };
+        self.limits.check_frame(data)?;
+        if !UrData::is_ur(data) {
+            return Err(Error::InvalidHeader("data is not UR type!".to_string()));
+        }
+        let _type = UrData::get_type(data)?;
+        let multi = UrData::is_multi(data);
+        // declared sizes are checked before the decoder allocates anything
+        let fragment = if multi {
+            let fragment = UrData::fragment_header(data)?;
+            self.limits.check_parts(fragment.parts)?;
+            self.limits.check_payload(fragment.message_len)?;
+            Some(fragment)
         } else {
-            return Err(Error::DecodingError(
-                "data don't pass type check!".to_string(),
-            ));
+            None
+        };
+
+        // frame of another sequence
+        if self.decoder.is_some() {
+            if let Err(e) = self.check_sequence(&_type, multi, fragment) {
+                match self.policy {
+                    ResetPolicy::Error => return Err(e),
+                    // the first sequence completed is the result
+                    ResetPolicy::KeepBoth if self.is_complete() => return Err(e),
+                    ResetPolicy::Restart => self.decoder = None,
+                    ResetPolicy::KeepBoth => self.switch_sequence(&_type, multi, fragment),
+                }
+            }
         }
+
+        // if no decoder yet, init it
+        if self.decoder.is_none() {
+            self.output_type = _type;
+            self.multi = Some(multi);
+            self.fragment = fragment;
+            self.decoder = Some(ur::Decoder::default());
+        }
+        if let Some(decoder) = self.decoder.as_mut() {
+            decoder
+                .receive(data)
+                .map_err(|e| Error::ur_with("cannot decode fragment", e))?;
+        }
+        Ok(true)
     }
 
     fn result(&self) -> Result<DataType, Error> {
@@ -147,43 +478,88 @@ impl Decode for UrData {
         } else if self.is_complete() {
             match self.output_type {
                 // UR BYTES
-                OutputType::NoType => {
-                    let m = self.decoder.unwrap().message();
-                    if m.is_err() {
-                        Err(Error::DecodingError("Cannot decode message !".to_string()))
-                    } else {
-                        if m.unwrap().is_none() {
-                            Err(Error::DecodingError("Message is None".to_string()))
-                        } else {
-                            let message = String::from_utf8(m.unwrap().unwrap());
-                            if message.is_err() {
-                                Err(Error::DecodingError("FromUtf8Error".to_string()))
-                            } else {
-                                Ok(DataType::NoType(Some(message.unwrap())))
-                            }
-                        }
+                OutputType::UrBytes => match DataType::from_bytes(&bcr::unwrap_bytes(&self.message()?)) {
+                    DataType::NoType(None) => {
+                        Err(Error::DecodingError("FromUtf8Error".to_string()))
                     }
+                    data => Ok(data),
+                },
+                // UR CRYPTO-PSBT
+                OutputType::UrPsbt => {
+                    let (psbt, version) =
+                        psbt::decode_versioned(&bcr::unwrap_bytes(&self.message()?))?;
+                    Ok(DataType::from_psbt(psbt, version))
+                }
+                // UR CRYPTO-OUTPUT
+                OutputType::UrDescriptor => {
+                    let descriptor = bcr::output_to_descriptor(&self.message()?, self.network)?;
+                    Ok(DataType::Descriptor(Some(descriptor)))
                 }
-                // TODO: implememt other cases
-                _ => Err(Error::NotImplementedError("".to_string())),
+                // UR CRYPTO-HDKEY, a bare xpub is typed as such
+                OutputType::UrXpub => match bcr::hdkey_to_key(&self.message()?, self.network)? {
+                    DescriptorPublicKey::XPub(DescriptorXKey {
+                        origin: None,
+                        xkey,
+                        derivation_path,
+                        wildcard: Wildcard::None,
+                    }) if derivation_path.is_master() => Ok(DataType::Xpub(Some(xkey))),
+                    key => Ok(DataType::Keys(Some(vec![key]))),
+                },
+                // UR CRYPTO-ACCOUNT
+                OutputType::UrAccount => {
+                    let keys = bcr::account_to_keys(&self.message()?, self.network)?;
+                    Ok(DataType::Keys(Some(keys)))
+                }
+                // UR CRYPTO-BIP39
+                OutputType::UrMnemonic => {
+                    let mnemonic = bcr::bip39_to_mnemonic(&self.message()?)?;
+                    Ok(DataType::Mnemonic(Some(mnemonic)))
+                }
+                // UR CRYPTO-ECKEY
+                OutputType::UrPrivateKey => {
+                    let key = bcr::eckey_to_private_key(&self.message()?, self.network)?;
+                    Ok(DataType::PrivateKey(Some(key)))
+                }
+                _ => Err(Error::UnsupportedUrType(
+                    type_name(&self.output_type).unwrap_or_default().to_string(),
+                )),
             }
         } else {
-            Err(Error::NotImplementedError("".to_string()))
+            Err(Error::DecodingError(
+                "UR data not yet complete!".to_string(),
+            ))
         }
     }
 }
 
 impl Encode for UrData {
     fn max_len(&mut self) -> Option<usize> {
-        todo!()
+        self.max_len
     }
 
-    fn from_liana_descriptor(descriptor: &Descriptor) -> Result<Box<Self>, Error> {
-        todo!()
+    /// A Liana descriptor is multipath, it is sent as UR bytes
+    fn from_liana_descriptor(descriptor: &LianaDescriptor) -> Result<Box<Self>, Error> {
+        let mut ur = UrData::new();
+        ur.load(&DataType::LianaDescriptor(Some(descriptor.clone())))?;
+        Ok(Box::new(ur))
+    }
+
+    fn from_descriptor(descriptor: &Descriptor<DescriptorPublicKey>) -> Result<Box<Self>, Error> {
+        UrData::from_descriptor_with(descriptor, None)
+    }
+
+    /// A multipath descriptor is encoded as two crypto-output: receive then change
+    fn from_descriptors(
+        descriptor: &Descriptor<DescriptorPublicKey>,
+    ) -> Result<Vec<Box<Self>>, Error> {
+        UrData::from_descriptors_with(descriptor, None)
     }
 
     fn load_string(&mut self, data: &str) -> Result<Box<Self>, Error> {
-        todo!()
+        let mut ur = UrData::new();
+        ur.max_len = self.max_len;
+        ur.load(&self.data_type.parse_as(data)?)?;
+        Ok(Box::new(ur))
     }
 
     fn set_output_type(
@@ -191,23 +567,291 @@ impl Encode for UrData {
         data_type: DataType,
         encoding: Encoding,
         max_len: Option<usize>,
-    ) -> &mut Self {
-        todo!()
+    ) -> Result<&mut Self, Error> {
+        if encoding != Encoding::Ur {
+            return Err(Error::EncodingError(format!(
+                "UrData cannot encode as {:?}!",
+                encoding
+            )));
+        }
+        QREncoder::output_type(&data_type, encoding)?;
+        self.data_type = data_type;
+        self.max_len = max_len;
+        Ok(self)
     }
 
-    fn from_psbt(psbt: &Psbt) -> Result<Box<Self>, Error> {
-        todo!()
+    fn from_psbt(psbt: &Psbt, options: Option<&MinimiserOptions>) -> Result<Box<Self>, Error> {
+        let psbt = minimiser::apply(psbt, options);
+        UrData::from_psbt_with(&psbt, PsbtVersion::V0, None)
     }
 
     fn from_xpub(xpub: &XPub) -> Result<Box<Self>, Error> {
-        todo!()
+        let mut ur = UrData::new();
+        ur.load(&DataType::Xpub(Some(*xpub)))?;
+        Ok(Box::new(ur))
     }
 
     fn from_xpriv(xpriv: &XPriv) -> Result<Box<Self>, Error> {
-        todo!()
+        let mut ur = UrData::new();
+        ur.load(&DataType::Xpriv(Some(*xpriv)))?;
+        Ok(Box::new(ur))
+    }
+
+    fn from_mnemonic(mnemonic: &Mnemonic) -> Result<Box<Self>, Error> {
+        let mut ur = UrData::new();
+        ur.load(&DataType::Mnemonic(Some(mnemonic.clone())))?;
+        Ok(Box::new(ur))
+    }
+
+    fn from_transaction(tx: &Transaction) -> Result<Box<Self>, Error> {
+        let mut ur = UrData::new();
+        ur.load(&DataType::Transaction(Some(tx.clone())))?;
+        Ok(Box::new(ur))
+    }
+
+    fn from_private_key(key: &PrivateKey) -> Result<Box<Self>, Error> {
+        let mut ur = UrData::new();
+        ur.load(&DataType::PrivateKey(Some(*key)))?;
+        Ok(Box::new(ur))
     }
 
     fn next(&mut self) -> Option<String> {
-        todo!()
+        self.encoder.as_mut()?.next_part().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qr::Limit;
+    use std::str::FromStr;
+
+    const XPUB: &str = "xpub6ASuArnXKPbfEwhqN6e3mwBcDTgzisQN1wXN9BJcM47sSikHjJf3UFHKkNAWbWMiGj7Wf5uMash7SyYq527Hqck2AxYysAA7xmALppuCkwQ";
+
+    /// Scan the frames of `encoder` until the decoder completes
+    fn scan(encoder: &mut UrData) -> DataType {
+        let mut decoder = UrData::new();
+        for _ in 0..100 {
+            decoder.receive(&encoder.next().unwrap()).unwrap();
+            if decoder.is_complete() {
+                break;
+            }
+        }
+        decoder.result().unwrap()
+    }
+
+    fn roundtrip(data: &DataType, max_len: Option<usize>) -> DataType {
+        let mut encoder = UrData::new();
+        encoder
+            .set_output_type(data.clone(), Encoding::Ur, max_len)
+            .unwrap();
+        encoder.load(data).unwrap();
+        scan(&mut encoder)
+    }
+
+    #[test]
+    fn ur_types() {
+        for (name, output_type) in UR_TYPES.iter() {
+            let frame = format!("UR:{}/aeadaolazmjendeoti", name.to_uppercase());
+            assert_eq!(UrData::get_type(&frame).unwrap(), *output_type);
+            assert_eq!(type_name(output_type), Some(*name));
+        }
+        assert!(UrData::get_type("ur:crypto-unknown/aeadao").is_err());
+        assert!(UrData::get_type("xx:crypto-psbt/aeadao").is_err());
+        assert_eq!(type_name(&OutputType::UrXpriv), None);
+        assert_eq!(type_name(&OutputType::NoType), None);
+    }
+
+    #[test]
+    fn key_roundtrip() {
+        // depth and child number survive without origin
+        let xpub = DataType::Xpub(Some(XPub::from_str(XPUB).unwrap()));
+        assert_eq!(roundtrip(&xpub, None), xpub);
+
+        let key = DescriptorPublicKey::from_str(&format!("[3442193e/0'/1]{}/0/*", XPUB)).unwrap();
+        let keys = DataType::Keys(Some(vec![key.clone()]));
+        assert_eq!(roundtrip(&keys, None), keys);
+        let mut ur = UrData::new();
+        assert!(ur
+            .load(&DataType::Keys(Some(vec![key.clone(), key])))
+            .is_err());
+    }
+
+    #[test]
+    fn account_decoding() {
+        let account = format!("[3442193e/0'/1]{}", XPUB);
+        let output = descriptor::parse(&format!("wpkh({})", account)).unwrap();
+        let output = cbor::decode(&bcr::descriptor_to_output(&output).unwrap()).unwrap();
+        let data = cbor::encode(&cbor::Value::map(vec![
+            (1, cbor::Value::Unsigned(0x3442193e)),
+            (2, cbor::Value::Array(vec![output])),
+        ]));
+        let mut encoder = UrData::new();
+        encoder.load_bytes(&data, "crypto-account").unwrap();
+        assert_eq!(
+            scan(&mut encoder),
+            DataType::Keys(Some(vec![DescriptorPublicKey::from_str(&account).unwrap()]))
+        );
+    }
+
+    #[test]
+    fn data_roundtrip() {
+        let mnemonic = DataType::Mnemonic(Some(
+            Mnemonic::parse(
+                "forum undo fragile fade shy sign arrest garment culture tube off merit",
+            )
+            .unwrap(),
+        ));
+        let key = DataType::PrivateKey(Some(
+            PrivateKey::from_wif("KwDiBf89QgGbjEhKnhXJuH7LrciVrZi3qYjgd9M7rFU73sVHnoWn").unwrap(),
+        ));
+        let descriptor = DataType::Descriptor(Some(
+            descriptor::parse(&format!("wpkh([3442193e/0'/1]{}/0/*)", XPUB)).unwrap(),
+        ));
+        let text = DataType::NoType(Some("an untyped payload, split in fragments".to_string()));
+        for data in [mnemonic, key, descriptor, text] {
+            assert_eq!(roundtrip(&data, None), data);
+            assert_eq!(roundtrip(&data, Some(10)), data);
+        }
+    }
+
+    #[test]
+    fn output_type_selection() {
+        let mut ur = UrData::new();
+        let xpub = DataType::Xpub(Some(XPub::from_str(XPUB).unwrap()));
+        assert!(ur.set_output_type(xpub.clone(), Encoding::Ur, None).is_ok());
+        assert!(ur.set_output_type(xpub, Encoding::Specter, None).is_err());
+        assert!(ur
+            .set_output_type(DataType::Xpriv(None), Encoding::Ur, None)
+            .is_err());
+        let multipath = descriptor::parse(&format!("wpkh({}/<0;1>/*)", XPUB)).unwrap();
+        assert!(ur.load(&DataType::Descriptor(Some(multipath))).is_err());
+    }
+
+    #[test]
+    fn decode_limits() {
+        let mut encoder = UrData::new();
+        encoder.max_len = Some(10);
+        encoder
+            .load(&DataType::NoType(Some("a".repeat(50))))
+            .unwrap();
+        let frame = encoder.next().unwrap();
+        let fragment = UrData::fragment_header(&frame).unwrap();
+
+        let limits = DecodeLimits::default();
+        for (limits, limit) in [
+            (
+                DecodeLimits {
+                    max_parts: fragment.parts - 1,
+                    ..limits
+                },
+                Limit::Parts,
+            ),
+            (
+                DecodeLimits {
+                    max_payload: fragment.message_len - 1,
+                    ..limits
+                },
+                Limit::Payload,
+            ),
+            (
+                DecodeLimits {
+                    max_frame_len: frame.len() - 1,
+                    ..limits
+                },
+                Limit::FrameLength,
+            ),
+        ] {
+            let mut decoder = UrData::with_limits(limits);
+            match decoder.receive(&frame) {
+                Err(Error::LimitExceeded { limit: found, .. }) => assert_eq!(found, limit),
+                other => panic!("{:?} not caught: {:?}", limit, other),
+            }
+        }
+        let mut decoder = UrData::with_limits(limits);
+        assert!(decoder.receive(&frame).is_ok());
+
+        // the decoder sizes the sequence from the CBOR header, not from the path
+        let frame = "ur:bytes/1-3/lpadcyzmzmzmzmbkcybgeehfksfyhsidiaieatstftpr";
+        assert!(matches!(
+            UrData::fragment_header(frame),
+            Err(Error::InvalidHeader(_))
+        ));
+        assert!(UrData::new().receive(frame).is_err());
+        let frame = "ur:bytes/1-4294967295/lpadcyzmzmzmzmbkcybgeehfksfyhsidiaieatstftpr";
+        assert_eq!(UrData::fragment_header(frame).unwrap().parts, 0xffff_ffff);
+        assert!(matches!(
+            UrData::new().receive(frame),
+            Err(Error::LimitExceeded {
+                limit: Limit::Parts,
+                ..
+            })
+        ));
+        // sequence numbers start at 1
+        let frame = "ur:bytes/0-3/lpaeaxbkcybgeehfksfyhsidiaieenolwmsp";
+        assert!(UrData::fragment_header(frame).is_err());
+    }
+
+    #[test]
+    fn error_source() {
+        use std::error::Error as _;
+        let err = UrData::fragment_header("ur:bytes/1-2/zzzzzzzz").unwrap_err();
+        assert!(matches!(err, Error::UrError { .. }));
+        assert!(err.source().is_some());
+    }
+
+    /// The fragments of a multi-part UR, without fountain parts
+    fn fragments(data: &str) -> Vec<String> {
+        let mut encoder = UrData::new();
+        encoder.max_len = Some(10);
+        encoder
+            .load(&DataType::NoType(Some(data.to_string())))
+            .unwrap();
+        let first = encoder.next().unwrap();
+        let parts = UrData::fragment_header(&first).unwrap().parts;
+        let mut frames = vec![first];
+        frames.extend((1..parts).map(|_| encoder.next().unwrap()));
+        frames
+    }
+
+    #[test]
+    fn reset_policy() {
+        let (x, y) = ("x".repeat(50), "y".repeat(50));
+        let (first, second) = (fragments(&x), fragments(&y));
+        assert!(first.len() > 2);
+
+        // same length, another checksum
+        let mut decoder = UrData::new();
+        decoder.receive(&first[0]).unwrap();
+        assert!(matches!(
+            decoder.receive(&second[0]),
+            Err(Error::ChecksumMismatch { .. })
+        ));
+        for frame in &first[1..] {
+            decoder.receive(frame).unwrap();
+        }
+        assert_eq!(decoder.result().unwrap(), DataType::NoType(Some(x.clone())));
+
+        let mut decoder = UrData::new();
+        decoder.set_reset_policy(ResetPolicy::Restart);
+        decoder.receive(&first[0]).unwrap();
+        decoder.receive(&first[1]).unwrap();
+        for frame in &second {
+            decoder.receive(frame).unwrap();
+        }
+        assert_eq!(decoder.result().unwrap(), DataType::NoType(Some(y.clone())));
+
+        // both sequences interleaved, the first one completed is the result
+        let mut decoder = UrData::new();
+        decoder.set_reset_policy(ResetPolicy::KeepBoth);
+        for (a, b) in first.iter().zip(&second).take(first.len() - 1) {
+            decoder.receive(a).unwrap();
+            decoder.receive(b).unwrap();
+        }
+        assert!(!decoder.is_complete());
+        decoder.receive(second.last().unwrap()).unwrap();
+        assert!(decoder.is_complete());
+        assert!(decoder.receive(first.last().unwrap()).is_err());
+        assert_eq!(decoder.result().unwrap(), DataType::NoType(Some(y)));
     }
 }